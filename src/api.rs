@@ -0,0 +1,756 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use tree_sitter::Node;
+
+use crate::error::Result;
+use crate::fs::{file_diagnostic, parse_src_lang, read_source};
+use crate::modules::target_module_files;
+use crate::outline::item_kind;
+use crate::symbols::type_name;
+use crate::types::{ApiChange, ApiDiff, ApiItem, ApiKind, ApiOutput, FileDiagnostic, ItemKind, TargetKind};
+use crate::workspace::discover_crates;
+
+/// How deep `use` chains are followed before a path is given up on as unresolvable.
+const MAX_USE_DEPTH: usize = 16;
+
+/// The public API of every library crate under `root`: each `pub` item reachable from the crate root
+/// through `pub mod` declarations and `pub use` re-exports, under every path it can be named by, with
+/// public fields, enum variants, trait items, inherent `pub` associated items and trait impls listed
+/// below it. Paths start at the library's name. Sorted by path.
+///
+/// Modules are followed like `--module-tree` discovery does, and `cfg` attributes are not evaluated,
+/// except that `#[cfg(test)]` and `#[doc(hidden)]` items are left out. `use` paths are resolved
+/// within the crate only; re-exports of other crates are listed as `use` entries.
+pub fn api_sources(lang: &tree_sitter::Language, root: &Path) -> Result<ApiOutput> {
+    let mut output = ApiOutput::default();
+    for krate in discover_crates(root)? {
+        for t in krate.targets.iter().filter(|t| t.target.kind == TargetKind::Lib) {
+            let mut loader = Loader {
+                lang,
+                files: target_module_files(lang, &t.root_file)
+                    .into_iter()
+                    .map(|(file, module)| (module, file))
+                    .collect(),
+                table: CrateTable::default(),
+                diagnostics: Vec::new(),
+            };
+            let root_module = loader.table.new_module(None);
+            if let Some(file) = loader.files.get("crate").cloned() {
+                loader.load_file(&file, "crate", root_module);
+            }
+            let table = loader.table.linked();
+            output.diagnostics.append(&mut loader.diagnostics);
+
+            let mut exporter = Exporter {
+                table: &table,
+                items: Vec::new(),
+                stack: Vec::new(),
+            };
+            exporter.module(root_module, &t.target.name);
+            for m in &table.macros {
+                exporter.push(format!("{}::{}", t.target.name, m.name), m.kind, &m.entry);
+            }
+            output.items.append(&mut exporter.items);
+        }
+    }
+    output
+        .items
+        .sort_by(|a, b| (&a.path, a.kind, &a.signature).cmp(&(&b.path, b.kind, &b.signature)));
+    output
+        .items
+        .dedup_by(|a, b| (&a.path, a.kind, &a.signature) == (&b.path, b.kind, &b.signature));
+    output.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(output)
+}
+
+/// Classify the differences between two API listings by path and kind. A path defined more than once
+/// (e.g. under different `cfg`s) compares all of its signatures, joined with ` | `.
+pub fn diff_api(old: &[ApiItem], new: &[ApiItem]) -> ApiDiff {
+    fn group(items: &[ApiItem]) -> BTreeMap<(&str, ApiKind), Vec<&ApiItem>> {
+        let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for item in items {
+            groups.entry((item.path.as_str(), item.kind)).or_default().push(item);
+        }
+        groups
+    }
+    fn signatures(items: &[&ApiItem]) -> String {
+        let mut sigs: Vec<&str> = items.iter().map(|i| i.signature.as_str()).collect();
+        sigs.sort_unstable();
+        sigs.dedup();
+        sigs.join(" | ")
+    }
+
+    let (old, new) = (group(old), group(new));
+    let mut diff = ApiDiff::default();
+    for (key, items) in &old {
+        match new.get(key) {
+            None => diff.removed.extend(items.iter().map(|&i| i.clone())),
+            Some(now) => {
+                let (before, after) = (signatures(items), signatures(now));
+                if before != after {
+                    diff.changed.push(ApiChange {
+                        path: key.0.to_string(),
+                        kind: key.1,
+                        old: before,
+                        new: after,
+                        file: now[0].file.clone(),
+                        line: now[0].line,
+                    });
+                }
+            }
+        }
+    }
+    for (key, items) in &new {
+        if !old.contains_key(key) {
+            diff.added.extend(items.iter().map(|&i| i.clone()));
+        }
+    }
+    diff
+}
+
+/// Everything one library crate declares, by module, before paths are worked out.
+#[derive(Default)]
+struct CrateTable {
+    modules: Vec<Module>,
+    impls: Vec<Impl>,
+    /// `#[macro_export]` macros, which live at the crate root wherever they are defined.
+    macros: Vec<Member>,
+    /// Impls by the `(module, item)` their self type resolves to.
+    impls_of: HashMap<(usize, usize), Vec<usize>>,
+}
+
+#[derive(Default)]
+struct Module {
+    parent: Option<usize>,
+    items: Vec<Item>,
+    uses: Vec<Use>,
+}
+
+struct Item {
+    name: String,
+    kind: ApiKind,
+    public: bool,
+    entry: Entry,
+    /// Public fields, variants and trait items.
+    members: Vec<Member>,
+    /// The module a `mod` item declares.
+    module: Option<usize>,
+}
+
+struct Member {
+    name: String,
+    kind: ApiKind,
+    entry: Entry,
+}
+
+struct Entry {
+    signature: String,
+    file: String,
+    line: usize,
+}
+
+/// One name a `use` declaration brings in: `use a::{b, c as d, e::*}` is three.
+struct Use {
+    public: bool,
+    segments: Vec<String>,
+    /// The name it is imported as; `None` for globs.
+    name: Option<String>,
+    entry: Entry,
+}
+
+struct Impl {
+    module: usize,
+    self_type: Vec<String>,
+    /// The trait's name without its path (`Display`, `From<String>`); `None` for inherent impls.
+    trait_name: Option<String>,
+    entry: Entry,
+    /// `pub` associated items of inherent impls.
+    members: Vec<Member>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Module(usize),
+    Item(usize, usize),
+}
+
+struct Loader<'a> {
+    lang: &'a tree_sitter::Language,
+    /// Module files by module path (`crate::a::b`).
+    files: HashMap<String, PathBuf>,
+    table: CrateTable,
+    diagnostics: Vec<FileDiagnostic>,
+}
+
+struct Source<'a> {
+    src: &'a str,
+    file: &'a str,
+}
+
+impl<'a> Source<'a> {
+    fn text(&self, node: Node) -> &'a str {
+        node.utf8_text(self.src.as_bytes()).unwrap_or("")
+    }
+
+    fn entry(&self, node: Node, signature: String) -> Entry {
+        Entry {
+            signature,
+            file: self.file.to_string(),
+            line: node.start_position().row + 1,
+        }
+    }
+
+    fn name(&self, node: Node) -> Option<String> {
+        let name = self.text(node.child_by_field_name("name")?);
+        Some(name.trim_start_matches("r#").to_string())
+    }
+}
+
+impl Loader<'_> {
+    fn load_file(&mut self, path: &Path, module_path: &str, module: usize) {
+        let parsed = read_source(path).and_then(|src| {
+            let tree = parse_src_lang(self.lang, &src)?;
+            Ok((src, tree))
+        });
+        let (src, tree) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                self.diagnostics.push(file_diagnostic(path, &e));
+                return;
+            }
+        };
+        let file = path.display().to_string();
+        let s = Source { src: &src, file: &file };
+        self.visit(&s, tree.root_node(), module_path, module);
+    }
+
+    fn visit(&mut self, s: &Source, items: Node, module_path: &str, module: usize) {
+        for node in items.named_children(&mut items.walk()) {
+            if hidden(s, node) {
+                continue;
+            }
+            match node.kind() {
+                "use_declaration" => {
+                    let public = is_pub(s, node);
+                    let mut flat = Vec::new();
+                    if let Some(arg) = node.child_by_field_name("argument") {
+                        flatten_use(s, arg, &mut Vec::new(), &mut flat);
+                    }
+                    for (segments, name) in flat {
+                        let mut signature = format!("pub use {}", segments.join("::"));
+                        match &name {
+                            None => signature.push_str("::*"),
+                            Some(n) if segments.last() != Some(n) => signature.push_str(&format!(" as {n}")),
+                            Some(_) => {}
+                        }
+                        let entry = s.entry(node, signature);
+                        self.table.modules[module].uses.push(Use {
+                            public,
+                            segments,
+                            name,
+                            entry,
+                        });
+                    }
+                }
+                "foreign_mod_item" => {
+                    if let Some(body) = node.child_by_field_name("body") {
+                        self.visit(s, body, module_path, module);
+                    }
+                }
+                "impl_item" => self.add_impl(s, node, module),
+                "macro_definition" => {
+                    if let Some(name) = s.name(node).filter(|_| has_attribute(s, node, "macro_export")) {
+                        let entry = s.entry(node, format!("macro_rules! {name}"));
+                        self.table.macros.push(Member {
+                            name,
+                            kind: ApiKind::Macro,
+                            entry,
+                        });
+                    }
+                }
+                "mod_item" => {
+                    let Some(name) = s.name(node) else {
+                        continue;
+                    };
+                    let child = self.table.new_module(Some(module));
+                    let child_path = format!("{module_path}::{name}");
+                    match node.child_by_field_name("body") {
+                        Some(body) => self.visit(s, body, &child_path, child),
+                        None => {
+                            if let Some(file) = self.files.get(&child_path).cloned() {
+                                self.load_file(&file, &child_path, child);
+                            }
+                        }
+                    }
+                    self.table.modules[module].items.push(Item {
+                        name,
+                        kind: ApiKind::Mod,
+                        public: is_pub(s, node),
+                        entry: s.entry(node, signature(s, node)),
+                        members: Vec::new(),
+                        module: Some(child),
+                    });
+                }
+                _ => self.add_item(s, node, module),
+            }
+        }
+    }
+
+    fn add_item(&mut self, s: &Source, node: Node, module: usize) {
+        let kind = match item_kind(node.kind()) {
+            Some(ItemKind::Fn) => ApiKind::Fn,
+            Some(ItemKind::Struct) => ApiKind::Struct,
+            Some(ItemKind::Enum) => ApiKind::Enum,
+            Some(ItemKind::Union) => ApiKind::Union,
+            Some(ItemKind::Trait) => ApiKind::Trait,
+            Some(ItemKind::Type) => ApiKind::Type,
+            Some(ItemKind::Const) => ApiKind::Const,
+            Some(ItemKind::Static) => ApiKind::Static,
+            _ => return,
+        };
+        let Some(name) = s.name(node) else {
+            return;
+        };
+        let mut members = Vec::new();
+        if let Some(body) = node.child_by_field_name("body") {
+            for child in body.named_children(&mut body.walk()) {
+                if hidden(s, child) {
+                    continue;
+                }
+                let kind = match (kind, child.kind()) {
+                    (ApiKind::Struct | ApiKind::Union, "field_declaration") if is_pub(s, child) => ApiKind::Field,
+                    (ApiKind::Enum, "enum_variant") => ApiKind::Variant,
+                    (ApiKind::Trait, "function_item" | "function_signature_item") => ApiKind::Method,
+                    (ApiKind::Trait, "associated_type") => ApiKind::Type,
+                    (ApiKind::Trait, "const_item") => ApiKind::Const,
+                    _ => continue,
+                };
+                if let Some(name) = s.name(child) {
+                    let entry = s.entry(child, signature(s, child));
+                    members.push(Member { name, kind, entry });
+                }
+            }
+        }
+        self.table.modules[module].items.push(Item {
+            name,
+            kind,
+            public: is_pub(s, node),
+            entry: s.entry(node, signature(s, node)),
+            members,
+            module: None,
+        });
+    }
+
+    fn add_impl(&mut self, s: &Source, node: Node, module: usize) {
+        let Some(ty) = node.child_by_field_name("type") else {
+            return;
+        };
+        let self_type = type_name(ty, s.src).split("::").map(str::to_string).collect();
+        let trait_name = node.child_by_field_name("trait").map(|t| {
+            let full = render(s, t, t.end_byte());
+            // Drop the trait's path, but not paths inside its generic arguments.
+            let generics = full.find('<').unwrap_or(full.len());
+            let start = full[..generics].rfind("::").map_or(0, |i| i + 2);
+            full[start..].to_string()
+        });
+        let mut members = Vec::new();
+        if trait_name.is_none()
+            && let Some(body) = node.child_by_field_name("body")
+        {
+            for child in body.named_children(&mut body.walk()) {
+                if hidden(s, child) || !is_pub(s, child) {
+                    continue;
+                }
+                let kind = match child.kind() {
+                    "function_item" => ApiKind::Method,
+                    "const_item" => ApiKind::Const,
+                    _ => continue,
+                };
+                if let Some(name) = s.name(child) {
+                    let entry = s.entry(child, signature(s, child));
+                    members.push(Member { name, kind, entry });
+                }
+            }
+        }
+        self.table.impls.push(Impl {
+            module,
+            self_type,
+            trait_name,
+            entry: s.entry(node, signature(s, node)),
+            members,
+        });
+    }
+}
+
+impl CrateTable {
+    fn new_module(&mut self, parent: Option<usize>) -> usize {
+        self.modules.push(Module {
+            parent,
+            ..Module::default()
+        });
+        self.modules.len() - 1
+    }
+
+    /// Attach each impl to the item its self type resolves to from the impl's module.
+    fn linked(mut self) -> Self {
+        let mut links = Vec::new();
+        for (i, imp) in self.impls.iter().enumerate() {
+            for target in self.resolve(imp.module, &imp.self_type, 0) {
+                if let Target::Item(m, item) = target
+                    && matches!(
+                        self.modules[m].items[item].kind,
+                        ApiKind::Struct | ApiKind::Enum | ApiKind::Union | ApiKind::Type
+                    )
+                {
+                    links.push(((m, item), i));
+                }
+            }
+        }
+        for (item, i) in links {
+            self.impls_of.entry(item).or_default().push(i);
+        }
+        self
+    }
+
+    /// What a `use` path names when written in `module`; empty when it leads out of the crate.
+    fn resolve(&self, module: usize, segments: &[String], depth: usize) -> Vec<Target> {
+        if depth > MAX_USE_DEPTH {
+            return Vec::new();
+        }
+        let mut current = module;
+        let mut rest = segments;
+        match segments.first().map(String::as_str) {
+            Some("crate" | "$crate") => {
+                current = 0;
+                rest = &segments[1..];
+            }
+            Some("self") => rest = &segments[1..],
+            Some("super") => {
+                while let Some(("super", tail)) = rest.split_first().map(|(s, t)| (s.as_str(), t)) {
+                    let Some(parent) = self.modules[current].parent else {
+                        return Vec::new();
+                    };
+                    current = parent;
+                    rest = tail;
+                }
+            }
+            _ => {}
+        }
+        let Some((last, path)) = rest.split_last() else {
+            return vec![Target::Module(current)];
+        };
+        for segment in path {
+            let next = self
+                .lookup(current, segment, depth)
+                .into_iter()
+                .find_map(|t| self.module_of(t));
+            match next {
+                Some(m) => current = m,
+                None => return Vec::new(),
+            }
+        }
+        self.lookup(current, last, depth)
+    }
+
+    /// Items named `name` in `module`, then what its `use` declarations import under that name.
+    fn lookup(&self, module: usize, name: &str, depth: usize) -> Vec<Target> {
+        let m = &self.modules[module];
+        let found: Vec<Target> = m
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.name == name)
+            .map(|(i, _)| Target::Item(module, i))
+            .collect();
+        if !found.is_empty() {
+            return found;
+        }
+        let found: Vec<Target> = m
+            .uses
+            .iter()
+            .filter(|u| u.name.as_deref() == Some(name))
+            .flat_map(|u| self.resolve(module, &u.segments, depth + 1))
+            .collect();
+        if !found.is_empty() {
+            return found;
+        }
+        m.uses
+            .iter()
+            .filter(|u| u.name.is_none())
+            .flat_map(|u| self.resolve(module, &u.segments, depth + 1))
+            .filter_map(|t| self.module_of(t))
+            .filter(|&glob| glob != module)
+            .flat_map(|glob| self.lookup(glob, name, depth + 1))
+            .collect()
+    }
+
+    fn module_of(&self, target: Target) -> Option<usize> {
+        match target {
+            Target::Module(m) => Some(m),
+            Target::Item(m, i) => self.modules[m].items[i].module,
+        }
+    }
+}
+
+/// Walks the module tree from the crate root, naming everything public by the paths it is reachable at.
+struct Exporter<'a> {
+    table: &'a CrateTable,
+    items: Vec<ApiItem>,
+    /// Modules being exported, so re-exports of an ancestor do not recurse forever.
+    stack: Vec<usize>,
+}
+
+impl Exporter<'_> {
+    fn push(&mut self, path: String, kind: ApiKind, entry: &Entry) {
+        self.items.push(ApiItem {
+            path,
+            kind,
+            signature: entry.signature.clone(),
+            file: entry.file.clone(),
+            line: entry.line,
+        });
+    }
+
+    /// A module re-exported into itself or a descendant (`pub use super::*`) is listed one level deep.
+    fn module(&mut self, module: usize, prefix: &str) {
+        let descend = !self.stack.contains(&module);
+        self.stack.push(module);
+        let table = self.table;
+        let m = &table.modules[module];
+        for (i, item) in m.items.iter().enumerate().filter(|(_, item)| item.public) {
+            self.item(module, i, &format!("{prefix}::{}", item.name), descend);
+        }
+        for u in m.uses.iter().filter(|u| u.public && (descend || u.name.is_some())) {
+            self.reexport(module, u, prefix, descend);
+        }
+        self.stack.pop();
+    }
+
+    fn item(&mut self, module: usize, index: usize, path: &str, descend: bool) {
+        let table = self.table;
+        let item = &table.modules[module].items[index];
+        self.push(path.to_string(), item.kind, &item.entry);
+        for m in &item.members {
+            self.push(format!("{path}::{}", m.name), m.kind, &m.entry);
+        }
+        for &i in table.impls_of.get(&(module, index)).into_iter().flatten() {
+            let imp = &table.impls[i];
+            match &imp.trait_name {
+                Some(t) => self.push(format!("<{path} as {t}>"), ApiKind::Impl, &imp.entry),
+                None => {
+                    for m in &imp.members {
+                        self.push(format!("{path}::{}", m.name), m.kind, &m.entry);
+                    }
+                }
+            }
+        }
+        if descend && let Some(child) = item.module {
+            self.module(child, path);
+        }
+    }
+
+    fn reexport(&mut self, module: usize, u: &Use, prefix: &str, descend: bool) {
+        let table = self.table;
+        let targets = table.resolve(module, &u.segments, 0);
+        if targets.is_empty() {
+            let name = u.name.as_deref().unwrap_or("*");
+            self.push(format!("{prefix}::{name}"), ApiKind::Use, &u.entry);
+            return;
+        }
+        for target in targets {
+            match (&u.name, target) {
+                (Some(name), Target::Item(m, i)) => self.item(m, i, &format!("{prefix}::{name}"), descend),
+                (Some(name), Target::Module(m)) if descend => self.module(m, &format!("{prefix}::{name}")),
+                (Some(_), Target::Module(_)) => {}
+                (None, Target::Module(m)) => self.module(m, prefix),
+                (None, Target::Item(m, i)) => {
+                    let item = &table.modules[m].items[i];
+                    match item.module {
+                        Some(child) => self.module(child, prefix),
+                        // `pub use Enum::*` re-exports the variants.
+                        None => {
+                            for v in item.members.iter().filter(|v| v.kind == ApiKind::Variant) {
+                                self.push(format!("{prefix}::{}", v.name), v.kind, &v.entry);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The names a `use` tree imports, as full paths and the name each is bound to (`None` for globs).
+fn flatten_use(s: &Source, node: Node, prefix: &mut Vec<String>, out: &mut Vec<(Vec<String>, Option<String>)>) {
+    match node.kind() {
+        "use_list" => {
+            for child in node.named_children(&mut node.walk()) {
+                flatten_use(s, child, prefix, out);
+            }
+        }
+        "scoped_use_list" => {
+            let len = prefix.len();
+            if let Some(path) = node.child_by_field_name("path") {
+                prefix.extend(path_segments(s, path));
+            }
+            if let Some(list) = node.child_by_field_name("list") {
+                flatten_use(s, list, prefix, out);
+            }
+            prefix.truncate(len);
+        }
+        "use_wildcard" => {
+            let mut segments = prefix.clone();
+            if let Some(path) = node.named_child(0) {
+                segments.extend(path_segments(s, path));
+            }
+            out.push((segments, None));
+        }
+        "use_as_clause" => {
+            let (Some(path), Some(alias)) = (node.child_by_field_name("path"), node.child_by_field_name("alias"))
+            else {
+                return;
+            };
+            let segments = join_use_path(prefix, path_segments(s, path));
+            out.push((segments, Some(s.text(alias).trim_start_matches("r#").to_string())));
+        }
+        "line_comment" | "block_comment" => {}
+        _ => {
+            let segments = join_use_path(prefix, path_segments(s, node));
+            let name = segments.last().cloned();
+            out.push((segments, name));
+        }
+    }
+}
+
+/// `prefix::segments`, where `a::{self}` stands for `a` itself.
+fn join_use_path(prefix: &[String], segments: Vec<String>) -> Vec<String> {
+    if segments == ["self"] && !prefix.is_empty() {
+        return prefix.to_vec();
+    }
+    prefix.iter().cloned().chain(segments).collect()
+}
+
+fn path_segments(s: &Source, node: Node) -> Vec<String> {
+    if node.kind() != "scoped_identifier" {
+        return vec![s.text(node).trim_start_matches("r#").to_string()];
+    }
+    let mut segments = match node.child_by_field_name("path") {
+        Some(path) => path_segments(s, path),
+        // A leading `::` names an external crate, which is never resolved.
+        None => vec![String::new()],
+    };
+    if let Some(name) = node.child_by_field_name("name") {
+        segments.push(s.text(name).trim_start_matches("r#").to_string());
+    }
+    segments
+}
+
+fn is_pub(s: &Source, node: Node) -> bool {
+    node.named_children(&mut node.walk())
+        .find(|c| c.kind() == "visibility_modifier")
+        .is_some_and(|v| s.text(v) == "pub")
+}
+
+/// The attributes right above `node`, with whitespace removed (`cfg(test)`, `doc(hidden)`).
+fn attributes(s: &Source, node: Node) -> Vec<String> {
+    let mut attrs = Vec::new();
+    let mut prev = node.prev_named_sibling();
+    while let Some(p) = prev {
+        match p.kind() {
+            "attribute_item" => {
+                if let Some(attr) = p.named_children(&mut p.walk()).find(|c| c.kind() == "attribute") {
+                    attrs.push(s.text(attr).split_whitespace().collect());
+                }
+            }
+            "line_comment" | "block_comment" => {}
+            _ => break,
+        }
+        prev = p.prev_named_sibling();
+    }
+    attrs
+}
+
+fn has_attribute(s: &Source, node: Node, attr: &str) -> bool {
+    attributes(s, node)
+        .iter()
+        .any(|a| a == attr || a.strip_prefix(attr).is_some_and(|args| args.starts_with('(')))
+}
+
+/// Test-only and doc-hidden items are not part of the API.
+fn hidden(s: &Source, node: Node) -> bool {
+    attributes(s, node)
+        .iter()
+        .any(|a| a == "cfg(test)" || a == "doc(hidden)")
+}
+
+/// The source text of an item's declaration: the header of items with a body (up to the `{` of a
+/// function, named-field struct, enum, trait, impl or module), the type of a `const` or `static`, and
+/// the whole of anything else, enum variants included.
+fn signature(s: &Source, node: Node) -> String {
+    let body = node
+        .child_by_field_name("body")
+        .filter(|b| b.kind() != "ordered_field_declaration_list");
+    let end = match node.kind() {
+        "const_item" | "static_item" => node.child_by_field_name("value").map(|v| v.start_byte()),
+        "enum_variant" => None,
+        _ => body.map(|b| b.start_byte()),
+    };
+    render(s, node, end.unwrap_or(node.end_byte()))
+}
+
+/// The tokens of `node` before byte `end`, without comments and attributes, joined with the spacing
+/// rustfmt would use, so layout changes do not count as signature changes. Trailing commas are dropped.
+fn render(s: &Source, node: Node, end: usize) -> String {
+    let mut tokens = Vec::new();
+    collect_tokens(s, node, end, &mut tokens);
+    while matches!(tokens.last(), Some(&(";" | "=" | ","))) {
+        tokens.pop();
+    }
+    let word = |t: &str| t.starts_with(|c: char| c.is_alphanumeric() || matches!(c, '_' | '\'' | '"' | '&'));
+    let mut out = String::new();
+    let mut emitted: Vec<&str> = Vec::new();
+    for token in tokens {
+        if matches!(token, ")" | ">" | "]" | "}") && emitted.last() == Some(&",") {
+            emitted.pop();
+            out.pop();
+        }
+        let prev = emitted.last().copied().unwrap_or("");
+        let space = match (prev, token) {
+            ("", _) => false,
+            ("mut" | "const" | "dyn" | "impl", "(" | "[") => true,
+            (_, "->" | "=" | "+" | "=>" | "{" | "}") | ("->" | "=" | "+" | "=>" | "{" | "," | ";" | ":", _) => true,
+            (_, "," | ";" | ":" | "::" | ")" | "]" | ">" | "(" | "<" | "?")
+            | ("::" | "(" | "[" | "<" | "&" | "*", _) => false,
+            (")" | "]" | ">", _) => word(token),
+            _ => word(prev) && word(token),
+        };
+        if space {
+            out.push(' ');
+        }
+        out.push_str(token);
+        emitted.push(token);
+    }
+    out
+}
+
+fn collect_tokens<'s>(s: &Source<'s>, node: Node, end: usize, out: &mut Vec<&'s str>) {
+    if node.start_byte() >= end {
+        return;
+    }
+    match node.kind() {
+        "line_comment" | "block_comment" | "attribute_item" | "inner_attribute_item" => {}
+        "string_literal" | "raw_string_literal" | "char_literal" | "lifetime" => out.push(s.text(node)),
+        _ if node.child_count() == 0 => {
+            let t = s.text(node);
+            if !t.is_empty() {
+                out.push(t);
+            }
+        }
+        _ => {
+            for child in node.children(&mut node.walk()) {
+                collect_tokens(s, child, end, out);
+            }
+        }
+    }
+}
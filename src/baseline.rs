@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Language, Node, Point};
+
+use crate::error::{ArbolError, Result};
+use crate::fs::{parse_src_lang, read_source, relative_to_root};
+use crate::outline;
+use crate::types::{Capture, QueryOutput};
+
+/// Format version written to and expected in baseline files.
+pub const BASELINE_VERSION: u32 = 1;
+
+/// Line-insensitive identity of one capture, so a baseline survives unrelated edits that shift code
+/// around.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BaselineEntry {
+    /// Path relative to the scan root, `/`-separated.
+    pub file: String,
+    /// Capture name.
+    pub name: String,
+    pub text: String,
+    /// Path of the items enclosing the capture, e.g. `outer::<impl Display for Foo>::fmt`; empty at the
+    /// top level of a file.
+    pub fingerprint: String,
+}
+
+/// Captures accepted as pre-existing; see [`Baseline::filter`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Baseline {
+    pub version: u32,
+    pub entries: Vec<BaselineEntry>,
+}
+
+impl Baseline {
+    /// Record every capture of `output`. Entries are sorted so the file diffs cleanly.
+    pub fn from_output(lang: &Language, output: &QueryOutput, root: &Path) -> Self {
+        let captures: Vec<&Capture> = output.crates.iter().flat_map(|c| &c.captures).collect();
+        let mut entries = baseline_entries(lang, &captures, root);
+        entries.sort();
+        Self {
+            version: BASELINE_VERSION,
+            entries,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let invalid = |reason: String| ArbolError::InvalidBaseline(format!("{}: {reason}", path.display()));
+        let baseline: Self =
+            serde_json::from_str(&std::fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+        if baseline.version != BASELINE_VERSION {
+            return Err(invalid(format!(
+                "unsupported version {} (expected {BASELINE_VERSION})",
+                baseline.version
+            )));
+        }
+        Ok(baseline)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Drop the captures of `output` recorded in the baseline, and crates left empty; returns how many
+    /// were dropped. Entries are counted, so a baseline holding one copy of an entry hides a single
+    /// identical capture and any further copies are reported as new.
+    pub fn filter(&self, lang: &Language, output: &mut QueryOutput, root: &Path) -> usize {
+        let captures: Vec<&Capture> = output.crates.iter().flat_map(|c| &c.captures).collect();
+        let entries = baseline_entries(lang, &captures, root);
+        let mut remaining: HashMap<&BaselineEntry, usize> = HashMap::new();
+        for entry in &self.entries {
+            *remaining.entry(entry).or_default() += 1;
+        }
+        let keep: Vec<bool> = entries
+            .iter()
+            .map(|entry| match remaining.get_mut(entry) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+        let dropped = keep.iter().filter(|k| !**k).count();
+        let mut keep = keep.into_iter();
+        for c in output.crates.iter_mut() {
+            c.captures.retain(|_| keep.next().unwrap_or(true));
+        }
+        output.crates.retain(|c| !c.captures.is_empty());
+        dropped
+    }
+}
+
+/// One entry per capture, in input order. Files with captures are parsed again (in parallel) to find
+/// the items enclosing each capture; a file that can no longer be read gets empty fingerprints.
+fn baseline_entries(lang: &Language, captures: &[&Capture], root: &Path) -> Vec<BaselineEntry> {
+    let mut by_file: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, cap) in captures.iter().enumerate() {
+        by_file.entry(cap.file.as_str()).or_default().push(i);
+    }
+    let located: Vec<(usize, String)> = by_file
+        .into_par_iter()
+        .flat_map_iter(|(file, indices)| {
+            let parsed = read_source(Path::new(file)).and_then(|src| Ok((parse_src_lang(lang, &src)?, src)));
+            indices.into_iter().map(move |i| {
+                let cap = captures[i];
+                let fingerprint = parsed.as_ref().ok().and_then(|(tree, src)| {
+                    let start = Point::new(cap.line - 1, cap.column - 1);
+                    let end = Point::new(cap.end_line - 1, cap.end_column - 1);
+                    let node = tree.root_node().descendant_for_point_range(start, end)?;
+                    Some(item_path(node, src))
+                });
+                (i, fingerprint.unwrap_or_default())
+            })
+        })
+        .collect();
+    let mut fingerprints = vec![String::new(); captures.len()];
+    for (i, fingerprint) in located {
+        fingerprints[i] = fingerprint;
+    }
+
+    captures
+        .iter()
+        .zip(fingerprints)
+        .map(|(cap, fingerprint)| BaselineEntry {
+            file: relative_to_root(&cap.file, root).unwrap_or_else(|| cap.file.clone()),
+            name: cap.name.clone(),
+            text: cap.text.clone(),
+            fingerprint,
+        })
+        .collect()
+}
+
+/// `::`-joined names of `node` and the items around it.
+fn item_path(node: Node, src: &str) -> String {
+    let mut parts = Vec::new();
+    let mut cur = Some(node);
+    while let Some(n) = cur {
+        parts.extend(item_name(n, src));
+        cur = n.parent();
+    }
+    parts.reverse();
+    parts.join("::")
+}
+
+fn item_name(node: Node, src: &str) -> Option<String> {
+    match node.kind() {
+        "impl_item" => Some(format!("<{}>", outline::item_name(node, src)?)),
+        "mod_item"
+        | "function_item"
+        | "function_signature_item"
+        | "struct_item"
+        | "enum_item"
+        | "union_item"
+        | "enum_variant"
+        | "trait_item"
+        | "type_item"
+        | "const_item"
+        | "static_item"
+        | "macro_definition" => outline::item_name(node, src),
+        _ => None,
+    }
+}
@@ -1,8 +1,9 @@
+use std::io::Write;
 use std::path::PathBuf;
 
 use arbol::Result;
 use clap::{Parser, Subcommand};
-use rayon::prelude::*;
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -30,6 +31,30 @@ struct Cli {
     #[arg(long, global = true, value_name = "DIR", num_args=1.., action=clap::ArgAction::Append)]
     skip_dir: Vec<PathBuf>,
 
+    /// Only scan files matching this gitignore-style glob (relative to root; `!` negates). Repeatable.
+    #[arg(long, global = true, value_name = "GLOB", action=clap::ArgAction::Append)]
+    include: Vec<String>,
+
+    /// Skip files matching this gitignore-style glob (relative to root; `!` negates). Repeatable.
+    #[arg(long, global = true, value_name = "GLOB", action=clap::ArgAction::Append)]
+    exclude: Vec<String>,
+
+    /// Do not honour .gitignore, .ignore, global git excludes or .arbolignore files
+    #[arg(long, global = true)]
+    no_ignore: bool,
+
+    /// Only scan files reachable through `mod` declarations from each Cargo target's root file
+    #[arg(long, global = true)]
+    module_tree: bool,
+
+    /// Exit with status 2 if any discovered file could not be read or parsed
+    #[arg(long, global = true)]
+    fail_on_file_errors: bool,
+
+    /// Do not read or write the query result cache under `<root>/target/arbol-cache`
+    #[arg(long, global = true)]
+    no_cache: bool,
+
     /// Emit markdown help to stdout (or to HELP.md with --help-output <path>)
     #[arg(long, global = true)]
     markdown_help: bool,
@@ -51,6 +76,9 @@ enum Commands {
         /// Limit depth (0 = only root)
         #[arg(long, default_value_t=usize::MAX)]
         max_depth: usize,
+        /// Stream one JSON object per line (each file or skipped file) as soon as it is parsed
+        #[arg(long)]
+        ndjson: bool,
     },
     /// Run a raw tree-sitter query across all Rust source files and aggregate captures per crate
     Query {
@@ -66,7 +94,170 @@ enum Commands {
         /// Emit JSON (otherwise plain text grouped by crate)
         #[arg(long)]
         json: bool,
+        /// Only report captures from files belonging to this kind of Cargo target. Repeatable.
+        #[arg(long, value_enum, value_name = "KIND", action=clap::ArgAction::Append)]
+        target_kind: Vec<arbol::TargetKind>,
+        /// Report one result per pattern match, with its captures grouped by name
+        #[arg(long)]
+        matches: bool,
+        /// Stream one JSON object per line (each capture or skipped file) as soon as its file is queried
+        #[arg(long, conflicts_with_all = ["json", "matches"])]
+        ndjson: bool,
+        /// Output format; `sarif` emits SARIF 2.1.0 using the `; message:` / `; severity:` / `; help:` header
+        /// comments of the query
+        #[arg(long, value_enum, conflicts_with_all = ["json", "matches", "ndjson"])]
+        format: Option<QueryFormat>,
+        /// Only report captures not recorded in this baseline file (matched by file, capture name, text
+        /// and enclosing item path, ignoring line numbers)
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["matches", "ndjson"])]
+        baseline: Option<PathBuf>,
+        /// Record the current captures in the --baseline file instead of reporting them
+        #[arg(long, requires = "baseline")]
+        write_baseline: bool,
+        /// Keep running: re-query changed `.rs` files (parsed incrementally) and print the captures added and
+        /// removed. With --json, one JSON object per update
+        #[arg(long, conflicts_with_all = ["matches", "ndjson", "format", "baseline", "target_kind"])]
+        watch: bool,
+    },
+    /// Run a pack of `.scm` lint rules in one pass and print compiler-style diagnostics; exits 1 on errors
+    Lint {
+        /// Rule file or directory of `.scm` rule files (searched recursively). Repeatable; defaults to
+        /// `<root>/.arbol/rules`
+        #[arg(long, value_name = "PATH", action=clap::ArgAction::Append)]
+        rules: Vec<PathBuf>,
+        /// Emit JSON (otherwise rustc-style diagnostics)
+        #[arg(long)]
+        json: bool,
+        /// Exit with status 1 on warnings too
+        #[arg(long)]
+        deny_warnings: bool,
     },
+    /// Structural search-and-replace: replace each query match with a template. Prints a diff unless --apply;
+    /// edits that would introduce syntax errors are refused
+    Rewrite {
+        /// Path to a .scm query file (if omitted, use --expr)
+        #[arg(long, value_name = "FILE")]
+        query: Option<PathBuf>,
+        /// Inline query expression (alternative to --query)
+        #[arg(long, conflicts_with = "query")]
+        expr: Option<String>,
+        /// Replacement text; `{{name}}` interpolates the source text of capture `@name`
+        #[arg(long)]
+        template: String,
+        /// Capture whose node is replaced (default: the outermost capture of each match)
+        #[arg(long, value_name = "NAME")]
+        capture: Option<String>,
+        /// Print a unified diff of the edits without writing anything (the default)
+        #[arg(long, conflicts_with = "apply")]
+        dry_run: bool,
+        /// Write the rewritten files
+        #[arg(long)]
+        apply: bool,
+        /// Emit the edits as JSON instead of a diff
+        #[arg(long)]
+        json: bool,
+    },
+    /// Answer JSON-RPC 2.0 requests (one JSON object per line) on stdin/stdout from parsed trees kept in
+    /// memory: `query`, `dumpFile`, `outline`, `fileChanged`, `shutdown`
+    Serve,
+    /// Language server over stdio: lint open editor buffers with `.scm` rules, publish the findings as
+    /// diagnostics and offer rule `rewrite` templates as quick fixes
+    Lsp {
+        /// Rule file or directory of `.scm` rule files (searched recursively). Repeatable; defaults to
+        /// `<root>/.arbol/rules`
+        #[arg(long, value_name = "PATH", action=clap::ArgAction::Append)]
+        rules: Vec<PathBuf>,
+    },
+    /// Model Context Protocol server over stdio (one JSON-RPC message per line) with tools for coding agents:
+    /// `run_query`, `list_node_kinds`, `outline_file`, `find_symbol`, `dump_subtree`
+    Mcp {
+        /// Cut each tool result at this many bytes, ending it with a truncation marker
+        #[arg(long, value_name = "BYTES", default_value_t = arbol::mcp::DEFAULT_MAX_OUTPUT)]
+        max_output: usize,
+    },
+    /// List every named item with its fully qualified path (`crate::module::Type::method`), kind, visibility,
+    /// span and the first line of its doc comment
+    Symbols {
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: SymbolFormat,
+        /// Write to this file instead of stdout (e.g. `tags` or `TAGS`)
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Print the items of one file as a tree (modules > impls / traits > items) with their line ranges
+    Outline {
+        /// The `.rs` file to outline
+        file: PathBuf,
+        /// Emit the nested outline as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the public API of each library crate: `pub` items reachable from the crate root through `pub mod`
+    /// and `pub use`, one `path: signature` line each, with signatures rendered from the source
+    Api {
+        /// Emit `{ "items": [...], "diagnostics": [...] }`
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare the public APIs of two checkouts and list added, removed and changed paths; exits 1 if
+    /// anything was removed or changed
+    ApiDiff {
+        /// Crate root of the old version
+        old: PathBuf,
+        /// Crate root of the new version
+        new: PathBuf,
+        /// Emit `{ "added": [...], "removed": [...], "changed": [...] }`
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the query result cache under `<root>/target/arbol-cache`
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// List every file and line where tree-sitter hit a syntax error (ERROR / MISSING nodes); exits 1 if any
+    Check {
+        /// Emit JSON (otherwise one `file:line:column` line per error)
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Delete every cached query result
+    Clear,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SymbolFormat {
+    /// One `file:line: [visibility ]kind path` line per item
+    #[default]
+    Text,
+    /// `{ "symbols": [...], "diagnostics": [...] }`
+    Json,
+    /// Sorted vi-style `tags` file
+    Ctags,
+    /// Emacs `TAGS` file
+    Etags,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum QueryFormat {
+    /// Plain text grouped by crate
+    #[default]
+    Text,
+    /// Same as `--json`
+    Json,
+    /// SARIF 2.1.0 log for code-scanning tools
+    Sarif,
+}
+
+/// JSON body emitted on stdout when a query fails to compile under `--json`.
+#[derive(Serialize)]
+struct ErrorReport<'a> {
+    error: &'a arbol::QueryDiagnostic,
 }
 
 fn main() -> Result<()> {
@@ -83,36 +274,83 @@ fn main() -> Result<()> {
         return Ok(());
     }
     if cli.verbose {
-        let _ = tracing_subscriber::fmt::try_init();
+        // Logs go to stderr so they never mix with results or `serve` responses on stdout.
+        let _ = tracing_subscriber::fmt().with_writer(std::io::stderr).try_init();
     }
 
+    let filter = arbol::PathFilter::new(&cli.skip_dir, &cli.include, &cli.exclude)?;
+    let discovery = arbol::Discovery {
+        respect_ignore: !cli.no_ignore,
+        module_tree: cli.module_tree,
+    };
+
     match cli.command {
         Some(Commands::DumpJson {
             output,
             with_source,
             max_depth,
+            ndjson,
         }) => {
-            let asts = arbol::fs::collect_rust_files(&cli.root, cli.include_tests, &cli.skip_dir).map(|files| {
-                let lang = arbol::rust_language();
-                files
-                    .par_iter()
-                    .map(|p| arbol::fs::dump_file(&lang, p, with_source, max_depth))
-                    .filter_map(|r| r.ok())
-                    .collect::<Vec<arbol::FileAst>>()
-            })?;
-            let json = serde_json::to_string_pretty(&asts)?;
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            if ndjson {
+                let mut file = output
+                    .map(std::fs::File::create)
+                    .transpose()?
+                    .map(std::io::BufWriter::new);
+                let mut diagnostics = Vec::new();
+                let mut failure = None;
+                arbol::fs::stream_dump(&arbol::rust_language(), &files, with_source, max_depth, |event| {
+                    if let arbol::DumpEvent::Diagnostic(d) = &event {
+                        diagnostics.push(d.clone());
+                    }
+                    let written = match serde_json::to_string(&event) {
+                        Ok(line) => match &mut file {
+                            Some(f) => writeln!(f, "{line}").map(|()| true).map_err(arbol::ArbolError::from),
+                            None => Ok(write_line(&line)),
+                        },
+                        Err(e) => Err(e.into()),
+                    };
+                    written.unwrap_or_else(|e| {
+                        failure = Some(e);
+                        false
+                    })
+                });
+                if let Some(e) = failure {
+                    return Err(e);
+                }
+                if let Some(f) = &mut file {
+                    f.flush()?;
+                }
+                exit_on_file_errors(cli.fail_on_file_errors, &diagnostics);
+                return Ok(());
+            }
+            let dump = arbol::fs::dump_sources(&arbol::rust_language(), &files, with_source, max_depth);
+            let json = serde_json::to_string_pretty(&dump)?;
             if let Some(out) = output {
                 std::fs::write(out, json)?;
             } else if !write_line(&json) {
                 return Ok(());
             }
+            exit_on_file_errors(cli.fail_on_file_errors, &dump.diagnostics);
         }
         Some(Commands::Query {
             query_file,
             expr,
             context,
             json,
+            target_kind,
+            matches,
+            ndjson,
+            format,
+            baseline,
+            write_baseline,
+            watch,
         }) => {
+            let format = if json {
+                QueryFormat::Json
+            } else {
+                format.unwrap_or_default()
+            };
             let query_src = if let Some(f) = query_file {
                 std::fs::read_to_string(f)?
             } else if let Some(e) = expr {
@@ -121,23 +359,135 @@ fn main() -> Result<()> {
                 return Err(arbol::ArbolError::Cli("Provide --query-file or --expr".into()));
             };
             let lang = arbol::rust_language();
-            let crates =
-                arbol::query::execute_query(&lang, &cli.root, cli.include_tests, &query_src, context, &cli.skip_dir)?;
-            if json {
-                let pretty = serde_json::to_string_pretty(&crates)?;
+            let cache = (!cli.no_cache).then(|| arbol::cache::QueryCache::for_root(&cli.root));
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            if watch {
+                let watcher = arbol::watch::FileWatcher::new(&cli.root)?;
+                let result = arbol::watch::WatchIndex::new(&lang, &cli.root, &files, &query_src, context);
+                let (mut index, diagnostics) = exit_on_compile_error(result, ErrorFormat::from_json(json))?;
+                if !write_watch_update(&index.snapshot(diagnostics), json, context) {
+                    return Ok(());
+                }
+                if !json && !write_line(&format!("-- watching {} file(s) for changes", index.len())) {
+                    return Ok(());
+                }
+                while let Some(changed) = watcher.next_changes() {
+                    let update = index.update(&changed, || {
+                        arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)
+                    })?;
+                    if (!update.deltas.is_empty() || !update.diagnostics.is_empty())
+                        && !write_watch_update(&update, json, context)
+                    {
+                        return Ok(());
+                    }
+                }
+                return Ok(());
+            }
+            if ndjson {
+                let mut diagnostics = Vec::new();
+                let result = arbol::query::stream_captures(
+                    &lang,
+                    &cli.root,
+                    &files,
+                    &query_src,
+                    context,
+                    cache.as_ref(),
+                    |event| {
+                        match &event {
+                            arbol::QueryEvent::Capture(c)
+                                if !target_kind.is_empty()
+                                    && !c.target.as_ref().is_some_and(|t| target_kind.contains(&t.kind)) =>
+                            {
+                                return true;
+                            }
+                            arbol::QueryEvent::Diagnostic(d) => diagnostics.push(d.clone()),
+                            arbol::QueryEvent::Capture(_) | arbol::QueryEvent::UnusedSuppression(_) => {}
+                        }
+                        serde_json::to_string(&event).is_ok_and(|line| write_line(&line))
+                    },
+                );
+                exit_on_compile_error(result, ErrorFormat::Ndjson)?;
+                exit_on_file_errors(cli.fail_on_file_errors, &diagnostics);
+                return Ok(());
+            }
+            if matches {
+                let result =
+                    arbol::query::query_matches_sources(&lang, &cli.root, &files, &query_src, context, cache.as_ref());
+                let mut output = exit_on_compile_error(result, ErrorFormat::from_json(json))?;
+                if !target_kind.is_empty() {
+                    arbol::query::retain_match_target_kinds(&mut output.crates, &target_kind);
+                }
+                if json {
+                    if !write_line(&serde_json::to_string_pretty(&output)?) {
+                        return Ok(());
+                    }
+                } else {
+                    for c in &output.crates {
+                        if !write_line(&format!("== Crate: {} ==", crate_label(&c.crate_path, &c.krate))) {
+                            return Ok(());
+                        }
+                        for m in &c.matches {
+                            let caps: Vec<String> = m
+                                .captures
+                                .iter()
+                                .flat_map(|(name, nodes)| nodes.iter().map(move |n| format!("@{name}=`{}`", n.text)))
+                                .collect();
+                            if !write_line(&format!(
+                                "{}:{}:{} #{} {}",
+                                m.file,
+                                m.line,
+                                m.column,
+                                m.pattern_index,
+                                caps.join(" ")
+                            )) {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    if !write_skipped_files(&output.diagnostics)
+                        || !write_unused_suppressions(&output.unused_suppressions)
+                    {
+                        return Ok(());
+                    }
+                    let total: usize = output.crates.iter().map(|c| c.matches.len()).sum();
+                    let _ = write_line(&format!("-- total matches: {total}"));
+                }
+                exit_on_file_errors(cli.fail_on_file_errors, &output.diagnostics);
+                return Ok(());
+            }
+            let result = arbol::query::query_sources(&lang, &cli.root, &files, &query_src, context, cache.as_ref());
+            let mut output = exit_on_compile_error(result, ErrorFormat::from_json(format == QueryFormat::Json))?;
+            if !target_kind.is_empty() {
+                arbol::query::retain_target_kinds(&mut output.crates, &target_kind);
+            }
+            let mut baselined = 0;
+            if let Some(path) = &baseline {
+                if write_baseline {
+                    let recorded = arbol::baseline::Baseline::from_output(&lang, &output, &cli.root);
+                    recorded.save(path)?;
+                    let _ = write_line(&format!(
+                        "-- wrote {} capture(s) to {}",
+                        recorded.entries.len(),
+                        path.display()
+                    ));
+                    return Ok(());
+                }
+                baselined = arbol::baseline::Baseline::load(path)?.filter(&lang, &mut output, &cli.root);
+            }
+            if format == QueryFormat::Sarif {
+                let meta = arbol::rules::QueryMetadata::parse(&query_src)?;
+                let log = arbol::sarif::query_to_sarif(&output, &meta, &cli.root);
+                if !write_line(&serde_json::to_string_pretty(&log)?) {
+                    return Ok(());
+                }
+            } else if format == QueryFormat::Json {
+                let pretty = serde_json::to_string_pretty(&output)?;
                 if !write_line(&pretty) {
                     return Ok(());
                 }
             } else {
-                for c in &crates {
-                    if !write_line(&format!(
-                        "== Crate: {} ==",
-                        if c.crate_path.is_empty() {
-                            "(root)"
-                        } else {
-                            &c.crate_path
-                        }
-                    )) {
+                for c in &output.crates {
+                    if !write_line(&format!("== Crate: {} ==", crate_label(&c.crate_path, &c.krate))) {
                         return Ok(());
                     }
                     for cap in &c.captures {
@@ -156,8 +506,313 @@ fn main() -> Result<()> {
                         }
                     }
                 }
-                let total: usize = crates.iter().map(|c| c.captures.len()).sum();
-                let _ = write_line(&format!("-- total captures: {total}"));
+                if !write_skipped_files(&output.diagnostics) || !write_unused_suppressions(&output.unused_suppressions)
+                {
+                    return Ok(());
+                }
+                let total: usize = output.crates.iter().map(|c| c.captures.len()).sum();
+                if baseline.is_some() {
+                    let _ = write_line(&format!("-- total captures: {total} ({baselined} in baseline)"));
+                } else {
+                    let _ = write_line(&format!("-- total captures: {total}"));
+                }
+            }
+            exit_on_file_errors(cli.fail_on_file_errors, &output.diagnostics);
+        }
+        Some(Commands::Lint {
+            rules,
+            json,
+            deny_warnings,
+        }) => {
+            let rule_paths = if rules.is_empty() {
+                vec![cli.root.join(".arbol").join("rules")]
+            } else {
+                rules
+            };
+            let rules = arbol::rules::load_rules(&rule_paths)?;
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            let report = arbol::lint::lint_sources(&arbol::rust_language(), &cli.root, &files, &rules)?;
+            if json {
+                if !write_line(&serde_json::to_string_pretty(&report)?) {
+                    return Ok(());
+                }
+            } else {
+                for f in &report.findings {
+                    if !write_line(&arbol::lint::render_finding(f)) || !write_line("") {
+                        return Ok(());
+                    }
+                }
+                for d in &report.diagnostics {
+                    if !write_line(&format!("{}: skipped: {}", d.file, d.reason)) {
+                        return Ok(());
+                    }
+                }
+                if !write_unused_suppressions(&report.unused_suppressions) {
+                    return Ok(());
+                }
+                let count = |s: arbol::Severity| report.findings.iter().filter(|f| f.severity == s).count();
+                let _ = write_line(&format!(
+                    "-- {} finding(s) from {} rule(s): {} error(s), {} warning(s), {} note(s)",
+                    report.findings.len(),
+                    rules.len(),
+                    count(arbol::Severity::Error),
+                    count(arbol::Severity::Warning),
+                    count(arbol::Severity::Note)
+                ));
+            }
+            exit_on_file_errors(cli.fail_on_file_errors, &report.diagnostics);
+            let threshold = if deny_warnings {
+                arbol::Severity::Warning
+            } else {
+                arbol::Severity::Error
+            };
+            if report.findings.iter().any(|f| f.severity >= threshold) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Rewrite {
+            query,
+            expr,
+            template,
+            capture,
+            dry_run: _,
+            apply,
+            json,
+        }) => {
+            let query_src = if let Some(f) = query {
+                std::fs::read_to_string(f)?
+            } else if let Some(e) = expr {
+                e
+            } else {
+                return Err(arbol::ArbolError::Cli("Provide --query or --expr".into()));
+            };
+            let template = arbol::rewrite::Template::parse(&template);
+            let spec = arbol::rewrite::RewriteSpec {
+                query: &query_src,
+                template: &template,
+                capture: capture.as_deref(),
+            };
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            let result = arbol::rewrite::rewrite_sources(&arbol::rust_language(), &cli.root, &files, &spec);
+            let mut report = exit_on_compile_error(result, ErrorFormat::from_json(json))?;
+            if apply {
+                arbol::rewrite::apply_rewrites(&mut report)?;
+            }
+            if json {
+                if !write_line(&serde_json::to_string_pretty(&report)?) {
+                    return Ok(());
+                }
+            } else {
+                if !apply {
+                    for file in &report.files {
+                        if !write_line(arbol::rewrite::unified_diff(file, &cli.root).trim_end()) {
+                            return Ok(());
+                        }
+                    }
+                }
+                for c in &report.conflicts {
+                    if !write_line(&format!(
+                        "{}:{}:{}: overlapping edit skipped (overlaps the edit at {}:{})",
+                        c.dropped.file, c.dropped.line, c.dropped.column, c.kept.line, c.kept.column
+                    )) {
+                        return Ok(());
+                    }
+                }
+                for r in &report.rejected {
+                    let first = r.errors.first().map_or(String::new(), |e| {
+                        format!(": {} at {}:{} of the rewritten file", e.message, e.line, e.column)
+                    });
+                    if !write_line(&format!(
+                        "{}:{}:{}: edit rejected, it introduces syntax errors{first}",
+                        r.edit.file, r.edit.line, r.edit.column
+                    )) {
+                        return Ok(());
+                    }
+                }
+                if !write_skipped_files(&report.diagnostics) {
+                    return Ok(());
+                }
+                let edits: usize = report.files.iter().map(|f| f.edits.len()).sum();
+                let _ = write_line(&format!(
+                    "-- {}{edits} edit(s) in {} file(s), {} conflict(s), {} rejected",
+                    if apply { "applied " } else { "" },
+                    report.files.len(),
+                    report.conflicts.len(),
+                    report.rejected.len()
+                ));
+            }
+            exit_on_file_errors(cli.fail_on_file_errors, &report.diagnostics);
+            if !report.conflicts.is_empty() || !report.rejected.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Serve) => {
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            let index = arbol::index::SourceIndex::new(&arbol::rust_language(), &cli.root, &files)?;
+            tracing::debug!("serve: indexed {} file(s)", index.len());
+            arbol::serve::Server::new(index).run(std::io::stdin().lock(), std::io::stdout().lock())?;
+        }
+        Some(Commands::Lsp { rules }) => {
+            let rule_paths = if rules.is_empty() {
+                vec![cli.root.join(".arbol").join("rules")]
+            } else {
+                rules
+            };
+            let rules = arbol::rules::load_rules(&rule_paths)?;
+            tracing::debug!("lsp: loaded {} rule(s)", rules.len());
+            arbol::lsp::LanguageServer::new(&arbol::rust_language(), &cli.root, &rules)?
+                .run(std::io::stdin().lock(), std::io::stdout().lock())?;
+        }
+        Some(Commands::Mcp { max_output }) => {
+            arbol::mcp::McpServer::new(
+                &arbol::rust_language(),
+                &cli.root,
+                cli.include_tests,
+                filter,
+                discovery,
+                max_output,
+            )
+            .run(std::io::stdin().lock(), std::io::stdout().lock())?;
+        }
+        Some(Commands::Symbols { format, output }) => {
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            let report = arbol::symbols::symbols_sources(&arbol::rust_language(), &cli.root, &files)?;
+            let rendered = match format {
+                SymbolFormat::Text => report
+                    .symbols
+                    .iter()
+                    .map(|s| {
+                        let vis = s.visibility.as_deref().map(|v| format!("{v} ")).unwrap_or_default();
+                        format!("{}:{}: {vis}{} {}\n", s.file, s.line, s.kind, s.path)
+                    })
+                    .collect(),
+                SymbolFormat::Json => serde_json::to_string_pretty(&report)? + "\n",
+                SymbolFormat::Ctags => arbol::symbols::render_ctags(&report.symbols, &cli.root),
+                SymbolFormat::Etags => arbol::symbols::render_etags(&report.symbols, &cli.root)?,
+            };
+            if let Some(out) = &output {
+                std::fs::write(out, rendered)?;
+            } else if !rendered.is_empty() && !write_line(rendered.trim_end_matches('\n')) {
+                return Ok(());
+            }
+            if format == SymbolFormat::Text && output.is_none() && !write_skipped_files(&report.diagnostics) {
+                return Ok(());
+            }
+            exit_on_file_errors(cli.fail_on_file_errors, &report.diagnostics);
+        }
+        Some(Commands::Outline { file, json }) => {
+            let outline = arbol::outline::outline_file(&arbol::rust_language(), &file)?;
+            let rendered = if json {
+                serde_json::to_string_pretty(&outline)?
+            } else {
+                arbol::outline::render_outline(&outline)
+            };
+            let _ = write_line(&rendered);
+        }
+        Some(Commands::Api { json }) => {
+            let report = arbol::api::api_sources(&arbol::rust_language(), &cli.root)?;
+            if json {
+                if !write_line(&serde_json::to_string_pretty(&report)?) {
+                    return Ok(());
+                }
+            } else {
+                for item in &report.items {
+                    if !write_line(&format!("{}: {}", item.path, item.signature)) {
+                        return Ok(());
+                    }
+                }
+                if !write_skipped_files(&report.diagnostics) {
+                    return Ok(());
+                }
+            }
+            exit_on_file_errors(cli.fail_on_file_errors, &report.diagnostics);
+        }
+        Some(Commands::ApiDiff { old, new, json }) => {
+            let lang = arbol::rust_language();
+            let before = arbol::api::api_sources(&lang, &old)?;
+            let after = arbol::api::api_sources(&lang, &new)?;
+            let diff = arbol::api::diff_api(&before.items, &after.items);
+            if json {
+                if !write_line(&serde_json::to_string_pretty(&diff)?) {
+                    return Ok(());
+                }
+            } else {
+                let mut lines: Vec<String> = Vec::new();
+                lines.extend(diff.removed.iter().map(|i| format!("- {}: {}", i.path, i.signature)));
+                lines.extend(diff.added.iter().map(|i| format!("+ {}: {}", i.path, i.signature)));
+                lines.extend(
+                    diff.changed
+                        .iter()
+                        .map(|c| format!("~ {}\n    - {}\n    + {}", c.path, c.old, c.new)),
+                );
+                for line in lines {
+                    if !write_line(&line) {
+                        return Ok(());
+                    }
+                }
+                if !write_skipped_files(&before.diagnostics) || !write_skipped_files(&after.diagnostics) {
+                    return Ok(());
+                }
+                let _ = write_line(&format!(
+                    "-- api-diff: {} added, {} removed, {} changed",
+                    diff.added.len(),
+                    diff.removed.len(),
+                    diff.changed.len()
+                ));
+            }
+            let diagnostics: Vec<_> = before.diagnostics.into_iter().chain(after.diagnostics).collect();
+            exit_on_file_errors(cli.fail_on_file_errors, &diagnostics);
+            if !diff.removed.is_empty() || !diff.changed.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Cache {
+            command: CacheCommand::Clear,
+        }) => {
+            let cache = arbol::cache::QueryCache::for_root(&cli.root);
+            let removed = cache.clear()?;
+            let _ = write_line(&format!(
+                "-- removed {removed} cache entr{} from {}",
+                if removed == 1 { "y" } else { "ies" },
+                cache.dir().display()
+            ));
+        }
+        Some(Commands::Check { json }) => {
+            let files = arbol::fs::discover_sources(&cli.root, cli.include_tests, &filter, discovery)?;
+            let report = arbol::check::check_sources(&arbol::rust_language(), &files);
+            if json {
+                if !write_line(&serde_json::to_string_pretty(&report)?) {
+                    return Ok(());
+                }
+            } else {
+                for e in &report.errors {
+                    let kind = match e.kind {
+                        arbol::SyntaxErrorKind::Error => "error",
+                        arbol::SyntaxErrorKind::Missing => "missing",
+                    };
+                    if !write_line(&format!("{}:{}:{}: {kind}: {}", e.file, e.line, e.column, e.message)) {
+                        return Ok(());
+                    }
+                }
+                for d in &report.diagnostics {
+                    if !write_line(&format!("{}: skipped: {}", d.file, d.reason)) {
+                        return Ok(());
+                    }
+                }
+                let files_with_errors = report
+                    .errors
+                    .iter()
+                    .map(|e| e.file.as_str())
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .len();
+                let _ = write_line(&format!(
+                    "-- {} syntax error(s) in {files_with_errors} file(s)",
+                    report.errors.len()
+                ));
+            }
+            exit_on_file_errors(cli.fail_on_file_errors, &report.diagnostics);
+            if !report.errors.is_empty() {
+                std::process::exit(1);
             }
         }
         None => {}
@@ -165,8 +820,118 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// How a query compile error is reported.
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    Text,
+    Json,
+    Ndjson,
+}
+
+impl ErrorFormat {
+    fn from_json(json: bool) -> Self {
+        if json {
+            Self::Json
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Print a query compile error (as JSON on stdout under `--json` / `--ndjson`) and exit with status 1.
+fn exit_on_compile_error<T>(result: Result<T>, format: ErrorFormat) -> Result<T> {
+    match result {
+        Err(arbol::ArbolError::QueryCompile(diag)) => {
+            let report = ErrorReport { error: &diag };
+            match format {
+                ErrorFormat::Text => arbol::print_query_diagnostic(&diag),
+                ErrorFormat::Json => {
+                    let _ = write_line(&serde_json::to_string_pretty(&report)?);
+                }
+                ErrorFormat::Ndjson => {
+                    let _ = write_line(&serde_json::to_string(&report)?);
+                }
+            }
+            std::process::exit(1);
+        }
+        other => other,
+    }
+}
+
+fn crate_label(crate_path: &str, krate: &Option<arbol::CrateInfo>) -> String {
+    match krate {
+        Some(k) => format!("{} v{} ({})", k.name, k.version, crate_path),
+        None if crate_path.is_empty() => "(root)".to_string(),
+        None => crate_path.to_string(),
+    }
+}
+
+/// Plain-text `== Skipped files ==` section; returns false on broken pipe.
+fn write_skipped_files(diagnostics: &[arbol::FileDiagnostic]) -> bool {
+    if diagnostics.is_empty() {
+        return true;
+    }
+    if !write_line("== Skipped files ==") {
+        return false;
+    }
+    diagnostics
+        .iter()
+        .all(|d| write_line(&format!("{}: {}", d.file, d.reason)))
+}
+
+fn write_unused_suppressions(unused: &[arbol::Suppression]) -> bool {
+    if unused.is_empty() {
+        return true;
+    }
+    if !write_line("== Unused suppressions ==") {
+        return false;
+    }
+    unused.iter().all(|s| {
+        write_line(&format!(
+            "{}:{}:{}: `{}` suppressed nothing",
+            s.file, s.line, s.column, s.rule
+        ))
+    })
+}
+
+/// One `--watch` report: a JSON line, or `+` / `-` capture lines and a running total; returns false on
+/// broken pipe.
+fn write_watch_update(update: &arbol::WatchUpdate, json: bool, context: bool) -> bool {
+    if json {
+        return serde_json::to_string(update).is_ok_and(|line| write_line(&line));
+    }
+    let line = |sign: char, cap: &arbol::Capture| {
+        let mut line = format!(
+            "{sign} {}:{}:{} {} {}",
+            cap.file, cap.line, cap.column, cap.name, cap.text
+        );
+        if context {
+            line.push_str(&format!(" // {}", cap.line_text));
+        }
+        write_line(&line)
+    };
+    let (mut added, mut removed) = (0, 0);
+    for d in &update.deltas {
+        if !d.removed.iter().all(|c| line('-', c)) || !d.added.iter().all(|c| line('+', c)) {
+            return false;
+        }
+        added += d.added.len();
+        removed += d.removed.len();
+    }
+    write_skipped_files(&update.diagnostics)
+        && write_line(&format!("-- total captures: {} (+{added} -{removed})", update.total))
+}
+
+/// Exit with status 2 when `--fail-on-file-errors` is set and some files were skipped.
+fn exit_on_file_errors(enabled: bool, diagnostics: &[arbol::FileDiagnostic]) {
+    if enabled && !diagnostics.is_empty() {
+        eprintln!("{} file(s) could not be read or parsed", diagnostics.len());
+        std::process::exit(2);
+    }
+}
+
 fn write_line(line: &str) -> bool {
-    use std::io::{self, Write};
+    use std::io;
     let mut out = io::stdout().lock();
     if let Err(e) = out.write_all(line.as_bytes())
         && e.kind() == io::ErrorKind::BrokenPipe
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::error::Result;
+use crate::types::Suppression;
+
+/// Cache directory below the scan root.
+pub const CACHE_DIR: &str = "target/arbol-cache";
+
+/// On-disk cache of per-file query results. Entries live in one directory per query (query text,
+/// result shape, grammar and arbol version) and one file per source path; an entry is only used
+/// when the file's content hash and workspace placement (crate, target, module) are unchanged, so
+/// cached files are neither parsed nor queried again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryCache {
+    dir: PathBuf,
+}
+
+impl QueryCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The cache under `<root>/target/arbol-cache`.
+    pub fn for_root(root: &Path) -> Self {
+        Self::new(root.join(CACHE_DIR))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Delete every entry; returns how many there were.
+    pub fn clear(&self) -> Result<usize> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let entries = walkdir::WalkDir::new(&self.dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .count();
+        std::fs::remove_dir_all(&self.dir)?;
+        Ok(entries)
+    }
+
+    /// Entries of one query. `shape` names the kind of results stored (captures, matches) and
+    /// `context` whether they carry line text, since both change what is cached for the same query.
+    pub(crate) fn scope(
+        &self,
+        lang: &tree_sitter::Language,
+        query_src: &str,
+        shape: &str,
+        context: bool,
+    ) -> CacheScope {
+        let grammar = match lang.metadata() {
+            Some(m) => format!("{}.{}.{}", m.major_version, m.minor_version, m.patch_version),
+            None => String::new(),
+        };
+        let key = format!(
+            "arbol {}\ngrammar {:?} {grammar} abi {} kinds {}\nshape {shape} context {context}\n{query_src}",
+            env!("CARGO_PKG_VERSION"),
+            lang.name(),
+            lang.abi_version(),
+            lang.node_kind_count(),
+        );
+        CacheScope {
+            dir: self.dir.join(format!("{:016x}", fnv1a(key.as_bytes()))),
+        }
+    }
+}
+
+pub(crate) struct CacheScope {
+    dir: PathBuf,
+}
+
+/// Identity of one source file's results within a [`CacheScope`].
+pub(crate) struct CacheKey {
+    path: PathBuf,
+    content_hash: String,
+    origin_hash: String,
+}
+
+#[derive(Deserialize)]
+struct Entry<T> {
+    content_hash: String,
+    origin_hash: String,
+    results: Vec<T>,
+    unused_suppressions: Vec<Suppression>,
+}
+
+/// Borrowed form of [`Entry`] for writing.
+#[derive(Serialize)]
+struct EntryRef<'a, T> {
+    content_hash: &'a str,
+    origin_hash: &'a str,
+    results: &'a [T],
+    unused_suppressions: &'a [Suppression],
+}
+
+impl CacheScope {
+    /// `origin` describes where the file sits in the workspace; results embed it, so a change (a
+    /// renamed package, say) must invalidate the entry just like a content change.
+    pub(crate) fn key(&self, file: &str, src: &str, origin: &str) -> CacheKey {
+        CacheKey {
+            path: self.dir.join(format!("{:016x}.json", fnv1a(file.as_bytes()))),
+            content_hash: format!("{:016x}-{}", fnv1a(src.as_bytes()), src.len()),
+            origin_hash: format!("{:016x}", fnv1a(origin.as_bytes())),
+        }
+    }
+
+    pub(crate) fn load<T: DeserializeOwned>(&self, key: &CacheKey) -> Option<(Vec<T>, Vec<Suppression>)> {
+        let bytes = std::fs::read(&key.path).ok()?;
+        let entry: Entry<T> = serde_json::from_slice(&bytes).ok()?;
+        (entry.content_hash == key.content_hash && entry.origin_hash == key.origin_hash)
+            .then_some((entry.results, entry.unused_suppressions))
+    }
+
+    /// Best effort: a cache that cannot be written only costs speed.
+    pub(crate) fn store<T: Serialize>(&self, key: &CacheKey, results: &[T], unused_suppressions: &[Suppression]) {
+        let entry = EntryRef {
+            content_hash: key.content_hash.as_str(),
+            origin_hash: key.origin_hash.as_str(),
+            results,
+            unused_suppressions,
+        };
+        let written = std::fs::create_dir_all(&self.dir)
+            .map_err(|e| e.to_string())
+            .and_then(|()| serde_json::to_vec(&entry).map_err(|e| e.to_string()))
+            .and_then(|json| {
+                // Write then rename so concurrent runs never read a half-written entry.
+                let tmp = key.path.with_extension(format!("json.{}.tmp", std::process::id()));
+                std::fs::write(&tmp, json)
+                    .and_then(|()| std::fs::rename(&tmp, &key.path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = written {
+            debug!("cache: could not write {}: {e}", key.path.display());
+        }
+    }
+}
+
+/// 64-bit FNV-1a: stable across platforms and Rust releases, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+use tree_sitter::{Language, Node, Tree};
+
+use crate::error::Result;
+use crate::fs::{file_diagnostic, parse_src_lang, read_source, SourceFile};
+use crate::types::{CheckOutput, SyntaxError, SyntaxErrorKind};
+
+/// Longest excerpt of an `ERROR` node quoted in its message.
+const EXCERPT_CHARS: usize = 40;
+
+/// Every `ERROR` and `MISSING` node tree-sitter produced while parsing `path`, in source order.
+pub fn check_file(lang: &Language, path: &Path) -> Result<Vec<SyntaxError>> {
+    let src = read_source(path)?;
+    let tree = parse_src_lang(lang, &src)?;
+    Ok(syntax_errors(&tree, &src, &path.display().to_string()))
+}
+
+/// Every `ERROR` and `MISSING` node of an already parsed `src`, in source order.
+pub(crate) fn syntax_errors(tree: &Tree, src: &str, file: &str) -> Vec<SyntaxError> {
+    let mut out = Vec::new();
+    collect_errors(tree.root_node(), src, file, &mut out);
+    out
+}
+
+/// Check every file in parallel; files that cannot be read or parsed become diagnostics.
+pub fn check_sources(lang: &Language, files: &[SourceFile]) -> CheckOutput {
+    let results: Vec<_> = files
+        .par_iter()
+        .map(|f| check_file(lang, &f.path).map_err(|e| file_diagnostic(&f.path, &e)))
+        .collect();
+    let mut out = CheckOutput::default();
+    for r in results {
+        match r {
+            Ok(mut errors) => out.errors.append(&mut errors),
+            Err(d) => out.diagnostics.push(d),
+        }
+    }
+    out.errors.sort_by(|a, b| {
+        a.file
+            .cmp(&b.file)
+            .then_with(|| a.line.cmp(&b.line))
+            .then_with(|| a.column.cmp(&b.column))
+    });
+    out.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    out
+}
+
+fn collect_errors(node: Node, src: &str, file: &str, out: &mut Vec<SyntaxError>) {
+    let kind = if node.is_missing() {
+        Some(SyntaxErrorKind::Missing)
+    } else if node.is_error() {
+        Some(SyntaxErrorKind::Error)
+    } else {
+        None
+    };
+    if let Some(kind) = kind {
+        let start = node.start_position();
+        let end = node.end_position();
+        let message = match kind {
+            SyntaxErrorKind::Missing => format!("missing `{}`", node.kind()),
+            SyntaxErrorKind::Error => {
+                let text = node.utf8_text(src.as_bytes()).unwrap_or("");
+                let first_line = text.lines().next().unwrap_or("");
+                let mut excerpt: String = first_line.chars().take(EXCERPT_CHARS).collect();
+                if excerpt.len() < text.len() {
+                    excerpt.push('…');
+                }
+                format!("unexpected `{excerpt}`")
+            }
+        };
+        out.push(SyntaxError {
+            file: file.to_string(),
+            line: start.row + 1,
+            column: start.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+            kind,
+            message,
+        });
+    }
+    if !node.has_error() {
+        return;
+    }
+    for child in node.children(&mut node.walk()) {
+        collect_errors(child, src, file, out);
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt;
+
+use serde::Serialize;
 use thiserror::Error;
 
 pub type Result<T, E = ArbolError> = std::result::Result<T, E>;
@@ -6,14 +9,140 @@ pub type Result<T, E = ArbolError> = std::result::Result<T, E>;
 pub enum ArbolError {
     #[error("IO: {0}")]
     Io(#[from] std::io::Error),
+    #[error("UTF-8: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
     #[error("JSON: {0}")]
     SerdeJson(#[from] serde_json::Error),
     #[error("Tree-sitter: failed to set language: {0}")]
     SetLanguage(String),
     #[error("Tree-sitter: parse failed")]
     ParseFailed,
-    #[error("Query compile error")]
-    QueryCompile,
+    #[error("Query compile error: {0}")]
+    QueryCompile(Box<QueryDiagnostic>),
+    #[error("Rule {path}: {diag}")]
+    RuleCompile { path: String, diag: Box<QueryDiagnostic> },
+    #[error("Invalid rule: {0}")]
+    InvalidRule(String),
+    #[error("Invalid baseline: {0}")]
+    InvalidBaseline(String),
+    #[error("Invalid rewrite: {0}")]
+    InvalidRewrite(String),
+    #[error("Watch: {0}")]
+    Watch(String),
+    #[error("MCP: {0}")]
+    Mcp(String),
+    #[error("Invalid glob: {0}")]
+    InvalidGlob(String),
     #[error("CLI: {0}")]
     Cli(String),
 }
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryErrorKind {
+    Syntax,
+    NodeType,
+    Field,
+    Capture,
+    Structure,
+    Predicate,
+    Language,
+}
+
+impl fmt::Display for QueryErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Syntax => "syntax",
+            Self::NodeType => "node type",
+            Self::Field => "field",
+            Self::Capture => "capture",
+            Self::Structure => "structure",
+            Self::Predicate => "predicate",
+            Self::Language => "language",
+        })
+    }
+}
+
+/// A query compile error with its location in the query source. `row` and `column` are 1-based,
+/// `offset` is the 0-based byte offset.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct QueryDiagnostic {
+    pub kind: QueryErrorKind,
+    pub row: usize,
+    pub column: usize,
+    pub offset: usize,
+    /// The offending token (node type, field or capture name, or the text at the error position).
+    pub token: String,
+    pub message: String,
+    /// The offending query line followed by a caret line pointing at `column`.
+    pub snippet: String,
+}
+
+impl QueryDiagnostic {
+    pub fn new(query: &str, err: &tree_sitter::QueryError) -> Self {
+        use tree_sitter::QueryErrorKind as Ts;
+        let kind = match err.kind {
+            Ts::Syntax => QueryErrorKind::Syntax,
+            Ts::NodeType => QueryErrorKind::NodeType,
+            Ts::Field => QueryErrorKind::Field,
+            Ts::Capture => QueryErrorKind::Capture,
+            Ts::Structure => QueryErrorKind::Structure,
+            Ts::Predicate => QueryErrorKind::Predicate,
+            Ts::Language => QueryErrorKind::Language,
+        };
+        let token = match kind {
+            // tree-sitter reports these names quoted in `message`
+            QueryErrorKind::NodeType | QueryErrorKind::Field | QueryErrorKind::Capture => {
+                err.message.trim_matches('"').to_string()
+            }
+            _ => token_at(query, err.offset),
+        };
+        let message = match kind {
+            QueryErrorKind::Syntax if err.offset >= query.len() => "unexpected end of query".to_string(),
+            QueryErrorKind::Syntax => "invalid syntax".to_string(),
+            QueryErrorKind::NodeType => format!("invalid node type `{token}`"),
+            QueryErrorKind::Field => format!("invalid field name `{token}`"),
+            QueryErrorKind::Capture => format!("invalid capture name `{token}`"),
+            QueryErrorKind::Structure => "impossible pattern".to_string(),
+            QueryErrorKind::Predicate => format!("invalid predicate: {}", err.message),
+            QueryErrorKind::Language => err.message.clone(),
+        };
+        let line = query.lines().nth(err.row).unwrap_or("");
+        let snippet = format!(
+            "{line}\n{}^",
+            " ".repeat(line[..err.column.min(line.len())].chars().count())
+        );
+        Self {
+            kind,
+            row: err.row + 1,
+            column: err.column + 1,
+            offset: err.offset,
+            token,
+            message,
+            snippet,
+        }
+    }
+}
+
+impl fmt::Display for QueryDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} error at {}:{}: {}",
+            self.kind, self.row, self.column, self.message
+        )
+    }
+}
+
+/// The identifier-like run (or single character) starting at `offset`; empty at end of input.
+fn token_at(query: &str, offset: usize) -> String {
+    let Some(rest) = query.get(offset..) else {
+        return String::new();
+    };
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '@' | '#' | '?' | '!' | '>');
+    match rest.find(|c: char| !is_word(c)) {
+        Some(0) => rest.chars().next().map(String::from).unwrap_or_default(),
+        Some(end) => rest[..end].to_string(),
+        None => rest.to_string(),
+    }
+}
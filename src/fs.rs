@@ -1,15 +1,139 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use tree_sitter::{Language, Node, Parser as TsParser, Tree};
-use walkdir::WalkDir;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use tree_sitter::{Language, Node, Parser as TsParser, Point, Tree};
 
 use crate::error::{ArbolError, Result};
-use crate::types::{FileAst, JsonNode};
+use crate::types::{DumpEvent, DumpOutput, FileAst, FileDiagnostic, FileErrorKind, JsonNode, Target};
 
-pub fn collect_rust_files(root: &Path, include_tests: bool, skip_dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+/// Name of the arbol-specific ignore file, honoured alongside `.gitignore` and `.ignore`.
+pub const ARBOL_IGNORE_FILENAME: &str = ".arbolignore";
+
+/// User supplied path filters: directory skips plus gitignore-style `--include` / `--exclude` globs.
+///
+/// Globs are matched against the path relative to the scan root, and a glob matching a directory
+/// applies to everything beneath it. A leading `!` negates a glob, so `--exclude 'api/' --exclude
+/// '!src/api/keep.rs'` drops every `api` directory except for one file. When any include glob is given,
+/// only files selected by the include set are kept.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    skip_dirs: Vec<PathBuf>,
+    include: Option<Gitignore>,
+    exclude: Option<Gitignore>,
+}
+
+impl PathFilter {
+    pub fn new(skip_dirs: &[PathBuf], include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            skip_dirs: skip_dirs.to_vec(),
+            include: build_globs(include)?,
+            exclude: build_globs(exclude)?,
+        })
+    }
+
+    pub fn from_skip_dirs(skip_dirs: &[PathBuf]) -> Self {
+        Self {
+            skip_dirs: skip_dirs.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether `path` (found while walking `root`) passes the skip directories and globs.
+    pub fn allows(&self, path: &Path, root: &Path) -> bool {
+        if should_skip(path, root, &self.skip_dirs) {
+            return false;
+        }
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        if rel.has_root() {
+            // Outside of the scan root: globs are root-relative, so they cannot apply.
+            return self.include.is_none();
+        }
+        if let Some(include) = &self.include
+            && !include.matched_path_or_any_parents(rel, false).is_ignore()
+        {
+            return false;
+        }
+        if let Some(exclude) = &self.exclude
+            && exclude.matched_path_or_any_parents(rel, false).is_ignore()
+        {
+            return false;
+        }
+        true
+    }
+}
+
+fn build_globs(globs: &[String]) -> Result<Option<Gitignore>> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(".");
+    for glob in globs {
+        builder
+            .add_line(None, glob)
+            .map_err(|e| ArbolError::InvalidGlob(format!("{glob}: {e}")))?;
+    }
+    let matcher = builder.build().map_err(|e| ArbolError::InvalidGlob(e.to_string()))?;
+    Ok(Some(matcher))
+}
+
+/// How source files are discovered under the scan root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discovery {
+    /// Honour `.gitignore`, `.ignore`, global git excludes and `.arbolignore` files.
+    pub respect_ignore: bool,
+    /// Only keep files reachable through `mod` declarations from a Cargo target's root file.
+    pub module_tree: bool,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            respect_ignore: true,
+            module_tree: false,
+        }
+    }
+}
+
+/// A discovered source file; `module_path` (`crate::a::b`) and the Cargo `target` compiling it are only
+/// known in module-tree mode, where a file shared by several targets is listed once for each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub module_path: Option<String>,
+    pub target: Option<Target>,
+}
+
+pub fn discover_sources(
+    root: &Path,
+    include_tests: bool,
+    filter: &PathFilter,
+    discovery: Discovery,
+) -> Result<Vec<SourceFile>> {
+    if discovery.module_tree {
+        return crate::modules::collect_module_files(root, include_tests, filter, discovery.respect_ignore);
+    }
+    let files = collect_rust_files(root, include_tests, filter, discovery.respect_ignore)?;
+    Ok(files
+        .into_iter()
+        .map(|path| SourceFile {
+            path,
+            module_path: None,
+            target: None,
+        })
+        .collect())
+}
+
+pub fn collect_rust_files(
+    root: &Path,
+    include_tests: bool,
+    filter: &PathFilter,
+    respect_ignore: bool,
+) -> Result<Vec<PathBuf>> {
     let mut v = Vec::new();
-    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+    for entry in walker(root, respect_ignore).build().filter_map(|e| e.ok()) {
         let path = entry.path();
         if !path.is_file() {
             continue;
@@ -23,9 +147,8 @@ pub fn collect_rust_files(root: &Path, include_tests: bool, skip_dirs: &[PathBuf
         {
             continue;
         }
-        // Custom skip directories provided by user (via CLI). A provided path may be absolute or
-        // relative to root. We skip any file whose path starts with one of the skip dirs.
-        if should_skip(path, root, skip_dirs) {
+        // Custom skip directories and globs provided by user (via CLI).
+        if !filter.allows(path, root) {
             continue;
         }
         if !include_tests
@@ -37,9 +160,26 @@ pub fn collect_rust_files(root: &Path, include_tests: bool, skip_dirs: &[PathBuf
         }
         v.push(path.to_path_buf());
     }
+    v.sort();
     Ok(v)
 }
 
+/// Build the directory walker. When `respect_ignore` is set, `.gitignore`, `.ignore`, global git
+/// excludes and `.arbolignore` files are honoured (even outside a git checkout); otherwise every
+/// file under `root` is visited.
+fn walker(root: &Path, respect_ignore: bool) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .standard_filters(respect_ignore)
+        .hidden(false)
+        .require_git(false);
+    if respect_ignore {
+        builder.add_custom_ignore_filename(ARBOL_IGNORE_FILENAME);
+    }
+    builder
+}
+
+/// A skip dir may be absolute or relative to root; any file whose path starts with one is skipped.
 fn should_skip(path: &Path, root: &Path, skip_dirs: &[PathBuf]) -> bool {
     for skip in skip_dirs {
         if skip.as_os_str().is_empty() {
@@ -55,33 +195,172 @@ fn should_skip(path: &Path, root: &Path, skip_dirs: &[PathBuf]) -> bool {
             if path.starts_with(&candidate) {
                 return true;
             }
-            // Also allow simple substring match on relative display to support loose patterns
-            if let Ok(rel) = path.strip_prefix(root) {
-                let rel_s = rel.to_string_lossy();
-                let skip_s = skip.to_string_lossy();
-                if rel_s.contains(skip_s.as_ref()) {
-                    return true;
-                }
-            }
         }
     }
     false
 }
 
+/// Read a source file, keeping invalid UTF-8 distinct from I/O failures.
+pub(crate) fn read_source(path: &Path) -> Result<String> {
+    Ok(String::from_utf8(fs::read(path)?)?)
+}
+
+pub(crate) fn file_diagnostic(path: &Path, err: &ArbolError) -> FileDiagnostic {
+    let kind = match err {
+        ArbolError::Io(_) => FileErrorKind::Io,
+        ArbolError::Utf8(_) => FileErrorKind::InvalidUtf8,
+        _ => FileErrorKind::Parse,
+    };
+    FileDiagnostic {
+        file: path.display().to_string(),
+        kind,
+        reason: err.to_string(),
+    }
+}
+
+/// `file` relative to `root` with `/` separators and no `.` components, or `None` when `file` is an
+/// absolute path outside `root`.
+pub fn relative_to_root(file: &str, root: &Path) -> Option<String> {
+    let path = Path::new(file);
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    if rel.is_absolute() {
+        return None;
+    }
+    Some(
+        rel.components()
+            .filter(|c| !matches!(c, std::path::Component::CurDir))
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
 pub fn dump_file(lang: &Language, path: &Path, with_source: bool, max_depth: usize) -> Result<FileAst> {
-    let src = fs::read_to_string(path)?;
+    let src = read_source(path)?;
+    let tree = parse_src_lang(lang, &src)?;
+    Ok(dump_tree(&tree, &src, path, with_source, max_depth))
+}
+
+/// [`dump_file`] restricted to one node of the file and its descendants, `max_depth` levels down.
+/// With a `column` (1-based, like `line`) the node is the smallest named node there; without, the
+/// outermost node starting at the first non-blank character of the line, such as a whole item.
+/// `None` when the position is past the end of the file.
+pub fn dump_subtree(
+    lang: &Language,
+    path: &Path,
+    line: usize,
+    column: Option<usize>,
+    with_source: bool,
+    max_depth: usize,
+) -> Result<Option<FileAst>> {
+    let src = read_source(path)?;
     let tree = parse_src_lang(lang, &src)?;
+    let Some(text) = src.lines().nth(line.saturating_sub(1)) else {
+        return Ok(None);
+    };
+    let byte_column = match column {
+        Some(column) => column.saturating_sub(1).min(text.len()),
+        None => text.len() - text.trim_start().len(),
+    };
+    let point = Point::new(line.saturating_sub(1), byte_column);
+    let Some(mut node) = tree.root_node().named_descendant_for_point_range(point, point) else {
+        return Ok(None);
+    };
+    if column.is_none() {
+        while let Some(parent) = node.parent()
+            && parent.start_byte() == node.start_byte()
+            && parent.parent().is_some()
+        {
+            node = parent;
+        }
+    }
+    let mut nodes = Vec::new();
+    collect_nodes(node, &src, with_source, max_depth, 0, &mut nodes);
+    Ok(Some(FileAst {
+        path: path.display().to_string(),
+        module_path: None,
+        root_kind: node.kind().to_string(),
+        nodes,
+    }))
+}
+
+/// [`dump_file`] for an already parsed source.
+pub(crate) fn dump_tree(tree: &Tree, src: &str, path: &Path, with_source: bool, max_depth: usize) -> FileAst {
     let root = tree.root_node();
     let mut nodes = Vec::new();
-    collect_nodes(root, &src, with_source, max_depth, 0, &mut nodes);
-    Ok(FileAst {
+    collect_nodes(root, src, with_source, max_depth, 0, &mut nodes);
+    FileAst {
         path: path.display().to_string(),
+        module_path: None,
         root_kind: root.kind().to_string(),
         nodes,
-    })
+    }
+}
+
+/// [`dump_file`] for a discovered file, carrying over its module path.
+pub fn dump_source(lang: &Language, source: &SourceFile, with_source: bool, max_depth: usize) -> Result<FileAst> {
+    let mut ast = dump_file(lang, &source.path, with_source, max_depth)?;
+    ast.module_path = source.module_path.clone();
+    Ok(ast)
+}
+
+/// Dump every file in parallel, collecting files that fail to read or parse as diagnostics.
+pub fn dump_sources(lang: &Language, files: &[SourceFile], with_source: bool, max_depth: usize) -> DumpOutput {
+    let mut out = DumpOutput::default();
+    stream_dump(lang, files, with_source, max_depth, |event| {
+        match event {
+            DumpEvent::File(ast) => out.files.push(ast),
+            DumpEvent::Diagnostic(d) => out.diagnostics.push(d),
+        }
+        true
+    });
+    out.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    out
+}
+
+/// Dump every file in parallel, handing each result to `on_event` in file order a small window of files
+/// at a time instead of materializing the whole workspace. Return `false` from `on_event` to stop early.
+pub fn stream_dump(
+    lang: &Language,
+    files: &[SourceFile],
+    with_source: bool,
+    max_depth: usize,
+    mut on_event: impl FnMut(DumpEvent) -> bool,
+) {
+    for_each_ordered(
+        files,
+        |f| dump_source(lang, f, with_source, max_depth).map_err(|e| file_diagnostic(&f.path, &e)),
+        |r| match r {
+            Ok(ast) => on_event(DumpEvent::File(ast)),
+            Err(d) => on_event(DumpEvent::Diagnostic(d)),
+        },
+    );
+}
+
+/// Map `items` in parallel and feed the results to `sink` on the calling thread, in input order.
+/// Items are mapped in windows of [`ordered_window`] so at most one window of results is held in
+/// memory at a time. Once `sink` returns `false` no further windows are started.
+pub(crate) fn for_each_ordered<T, R>(items: &[T], map: impl Fn(&T) -> R + Sync, mut sink: impl FnMut(R) -> bool)
+where
+    T: Sync,
+    R: Send,
+{
+    for window in items.chunks(ordered_window()) {
+        let results: Vec<R> = window.par_iter().map(&map).collect();
+        for r in results {
+            if !sink(r) {
+                return;
+            }
+        }
+    }
+}
+
+/// How many items [`for_each_ordered`] maps before handing their results on.
+pub(crate) fn ordered_window() -> usize {
+    rayon::current_num_threads() * 4
 }
 
-fn parse_src_lang(lang: &Language, src: &str) -> Result<Tree> {
+pub(crate) fn parse_src_lang(lang: &Language, src: &str) -> Result<Tree> {
     let mut parser = TsParser::new();
     parser
         .set_language(lang)
@@ -107,6 +386,9 @@ fn collect_nodes(node: Node, src: &str, with_source: bool, max_depth: usize, dep
         start_line: start.row as u32,
         end_line: end.row as u32,
         child_count: node.child_count() as u32,
+        is_error: node.is_error(),
+        is_missing: node.is_missing(),
+        has_error: node.has_error(),
         text,
     });
     for child in node.children(&mut node.walk()) {
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use tree_sitter::{InputEdit, Language, Parser as TsParser, Point, Tree};
+
+use crate::error::{ArbolError, Result};
+use crate::fs::{dump_tree, file_diagnostic, read_source, SourceFile};
+use crate::outline::outline_tree;
+use crate::query::{
+    captures_output, compile_query, matches_output, push_captures, push_match, query_tree, CompiledQuery, FileContext,
+};
+use crate::types::{FileAst, FileChange, FileDiagnostic, FileOutline, MatchOutput, QueryOutput, Suppression};
+use crate::workspace::{discover_crates, CrateRoot};
+
+/// A source file kept parsed in memory.
+pub(crate) struct ParsedFile {
+    pub(crate) source: SourceFile,
+    pub(crate) src: String,
+    pub(crate) tree: Tree,
+}
+
+impl ParsedFile {
+    pub(crate) fn read(lang: &Language, source: SourceFile) -> std::result::Result<Self, FileDiagnostic> {
+        let src = read_source(&source.path).map_err(|e| file_diagnostic(&source.path, &e))?;
+        Self::parse(lang, source, src, None)
+    }
+
+    /// Parse `src`. With the file's `old` state, its tree is edited to match the new source and
+    /// handed to tree-sitter so unchanged subtrees are reused.
+    pub(crate) fn parse(
+        lang: &Language,
+        source: SourceFile,
+        src: String,
+        old: Option<&ParsedFile>,
+    ) -> std::result::Result<Self, FileDiagnostic> {
+        let p = &source.path;
+        let mut parser = TsParser::new();
+        parser
+            .set_language(lang)
+            .map_err(|e| file_diagnostic(p, &ArbolError::SetLanguage(e.to_string())))?;
+        let old_tree = old.map(|old| {
+            let mut tree = old.tree.clone();
+            tree.edit(&input_edit(&old.src, &src));
+            tree
+        });
+        let tree = parser
+            .parse(&src, old_tree.as_ref())
+            .ok_or_else(|| file_diagnostic(p, &ArbolError::ParseFailed))?;
+        Ok(Self { source, src, tree })
+    }
+}
+
+/// The parsed sources of a workspace kept in memory, so that repeated queries neither walk the
+/// workspace nor parse files again. Files are updated one at a time, reusing their previous tree.
+pub struct SourceIndex {
+    lang: Language,
+    root: PathBuf,
+    canonical_root: Option<PathBuf>,
+    crate_roots: Vec<CrateRoot>,
+    files: BTreeMap<PathBuf, ParsedFile>,
+    /// Files that could not be read or parsed the last time they were loaded.
+    failed: BTreeMap<PathBuf, FileDiagnostic>,
+}
+
+impl SourceIndex {
+    /// Parse every file in parallel; files that cannot be read or parsed are reported by every query
+    /// until an update succeeds.
+    pub fn new(lang: &Language, root: &Path, files: &[SourceFile]) -> Result<Self> {
+        let results: Vec<_> = files
+            .par_iter()
+            .map(|source| ParsedFile::read(lang, source.clone()))
+            .collect();
+        let mut index = Self {
+            lang: lang.clone(),
+            root: root.to_path_buf(),
+            canonical_root: root.canonicalize().ok(),
+            crate_roots: discover_crates(root)?,
+            files: BTreeMap::new(),
+            failed: BTreeMap::new(),
+        };
+        for (source, r) in files.iter().zip(results) {
+            match r {
+                Ok(file) => {
+                    index.files.insert(source.path.clone(), file);
+                }
+                Err(d) => {
+                    index.failed.insert(source.path.clone(), d);
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Number of parsed files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// `path` spelled like discovered files: relative paths not already starting with the root are
+    /// taken relative to it, and absolute paths below the root are made relative to it again.
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        if path.starts_with(&self.root) {
+            return path.to_path_buf();
+        }
+        if path.is_relative() {
+            return self.root.join(path);
+        }
+        match self.canonical_root.as_ref().and_then(|c| path.strip_prefix(c).ok()) {
+            Some(rel) => self.root.join(rel),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// [`crate::query::query_sources`] over the parsed files.
+    pub fn query(&self, query_src: &str, context: bool) -> Result<QueryOutput> {
+        let compiled = compile_query(&self.lang, query_src)?;
+        let capture_names = compiled.query.capture_names();
+        let (captures, unused) = self.run(&compiled, context, |ctx, m, out| {
+            push_captures(ctx, m, capture_names, out)
+        });
+        Ok(captures_output(captures, self.diagnostics(), unused))
+    }
+
+    /// [`crate::query::query_matches_sources`] over the parsed files.
+    pub fn query_matches(&self, query_src: &str, context: bool) -> Result<MatchOutput> {
+        let compiled = compile_query(&self.lang, query_src)?;
+        let capture_names = compiled.query.capture_names();
+        let (matches, unused) = self.run(&compiled, context, |ctx, m, out| push_match(ctx, m, capture_names, out));
+        Ok(matches_output(matches, self.diagnostics(), unused))
+    }
+
+    fn run<T: Send>(
+        &self,
+        compiled: &CompiledQuery,
+        context: bool,
+        build: impl Fn(&FileContext, &tree_sitter::QueryMatch, &mut Vec<T>) + Sync,
+    ) -> (Vec<T>, Vec<Suppression>) {
+        let results: Vec<_> = self
+            .files
+            .par_iter()
+            .map(|(_, f)| query_tree(&f.source, &self.crate_roots, compiled, &f.src, &f.tree, context, &build))
+            .collect();
+        let mut items = Vec::new();
+        let mut unused = Vec::new();
+        for (mut v, mut u) in results {
+            items.append(&mut v);
+            unused.append(&mut u);
+        }
+        unused.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+        (items, unused)
+    }
+
+    fn diagnostics(&self) -> Vec<FileDiagnostic> {
+        self.failed.values().cloned().collect()
+    }
+
+    /// [`crate::fs::dump_file`] of a parsed file; `None` when `path` is not indexed.
+    pub fn dump(&self, path: &Path, with_source: bool, max_depth: usize) -> Option<FileAst> {
+        let file = self.files.get(&self.resolve(path))?;
+        let mut ast = dump_tree(&file.tree, &file.src, &file.source.path, with_source, max_depth);
+        ast.module_path = file.source.module_path.clone();
+        Some(ast)
+    }
+
+    /// [`crate::outline::outline_file`] of a parsed file; `None` when `path` is not indexed.
+    pub fn outline(&self, path: &Path) -> Option<FileOutline> {
+        let file = self.files.get(&self.resolve(path))?;
+        Some(FileOutline {
+            file: file.source.path.display().to_string(),
+            items: outline_tree(&file.tree, &file.src),
+        })
+    }
+
+    /// The parsed state of `path`; `None` when it is not indexed.
+    pub(crate) fn file(&self, path: &Path) -> Option<&ParsedFile> {
+        self.files.get(&self.resolve(path))
+    }
+
+    /// Drop `path` from the index; `false` when it was not indexed.
+    pub fn remove_file(&mut self, path: &Path) -> bool {
+        let path = self.resolve(path);
+        let failed = self.failed.remove(&path).is_some();
+        self.files.remove(&path).is_some() || failed
+    }
+
+    /// Re-parse `path` from `text`, or from disk when `None`, reusing its previous tree. A file new to
+    /// the index is added as is, without applying discovery filters; one gone from disk is dropped.
+    pub fn update_file(
+        &mut self,
+        path: &Path,
+        text: Option<String>,
+    ) -> std::result::Result<FileChange, FileDiagnostic> {
+        let path = self.resolve(path);
+        let src = match text {
+            Some(text) => text,
+            None if !path.exists() && (self.files.contains_key(&path) || self.failed.contains_key(&path)) => {
+                self.failed.remove(&path);
+                self.files.remove(&path);
+                return Ok(FileChange::Removed);
+            }
+            None => match read_source(&path) {
+                Ok(src) => src,
+                Err(e) => {
+                    let d = file_diagnostic(&path, &e);
+                    return Err(self.fail(path, d));
+                }
+            },
+        };
+        if self.files.get(&path).is_some_and(|old| old.src == src) {
+            return Ok(FileChange::Unchanged);
+        }
+        let old = self.files.remove(&path);
+        let source = old.as_ref().map_or_else(
+            || SourceFile {
+                path: path.clone(),
+                module_path: None,
+                target: None,
+            },
+            |old| old.source.clone(),
+        );
+        let change = if old.is_some() {
+            FileChange::Updated
+        } else {
+            FileChange::Added
+        };
+        match ParsedFile::parse(&self.lang, source, src, old.as_ref()) {
+            Ok(file) => {
+                self.failed.remove(&path);
+                self.files.insert(path, file);
+                Ok(change)
+            }
+            Err(d) => Err(self.fail(path, d)),
+        }
+    }
+
+    /// Record that `path` could not be loaded, dropping its previous tree.
+    fn fail(&mut self, path: PathBuf, d: FileDiagnostic) -> FileDiagnostic {
+        self.files.remove(&path);
+        self.failed.insert(path, d.clone());
+        d
+    }
+}
+
+/// The single edit turning `old` into `new`: everything between their common prefix and common
+/// suffix.
+pub(crate) fn input_edit(old: &str, new: &str) -> InputEdit {
+    let mut prefix = old.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old
+        .bytes()
+        .rev()
+        .zip(new.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) {
+        suffix -= 1;
+    }
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    InputEdit {
+        start_byte: prefix,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: point_at(old, prefix),
+        old_end_position: point_at(old, old_end),
+        new_end_position: point_at(new, new_end),
+    }
+}
+
+/// Row and byte column of `byte` in `src`.
+fn point_at(src: &str, byte: usize) -> Point {
+    let before = &src.as_bytes()[..byte];
+    let row = before.iter().filter(|&&b| b == b'\n').count();
+    let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    Point::new(row, byte - line_start)
+}
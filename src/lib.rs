@@ -1,9 +1,36 @@
+pub mod api;
+pub mod baseline;
+pub mod cache;
+pub mod check;
 pub mod error;
 pub mod fs;
+pub mod index;
+pub mod lint;
+pub mod lsp;
+pub mod mcp;
+pub mod modules;
+pub mod outline;
+pub mod predicates;
 pub mod query;
+pub mod rewrite;
+pub mod rpc;
+pub mod rules;
+pub mod sarif;
+pub mod serve;
+pub mod suppress;
+pub mod symbols;
 pub mod types;
+pub mod watch;
 pub mod workspace;
 
+#[cfg(test)]
+mod test_api;
+#[cfg(test)]
+mod test_baseline;
+#[cfg(test)]
+mod test_cache;
+#[cfg(test)]
+mod test_check;
 #[cfg(test)]
 mod test_crate_roots;
 #[cfg(test)]
@@ -11,47 +38,51 @@ mod test_dump_json;
 #[cfg(test)]
 mod test_fs_collect;
 #[cfg(test)]
+mod test_lint;
+#[cfg(test)]
+mod test_lsp;
+#[cfg(test)]
+mod test_mcp;
+#[cfg(test)]
+mod test_modules;
+#[cfg(test)]
+mod test_outline;
+#[cfg(test)]
+mod test_predicates;
+#[cfg(test)]
 mod test_query_exec;
+#[cfg(test)]
+mod test_rewrite;
+#[cfg(test)]
+mod test_sarif;
+#[cfg(test)]
+mod test_serve;
+#[cfg(test)]
+mod test_symbols;
+#[cfg(test)]
+mod test_watch;
 
 use std::path::Path;
 
-use rayon::prelude::*;
-use tree_sitter::{Language, QueryError};
+use tree_sitter::Language;
 use tree_sitter_rust::LANGUAGE as RUST_LANGUAGE;
 
-pub use self::error::{ArbolError, Result};
+pub use self::error::{ArbolError, QueryDiagnostic, QueryErrorKind, Result};
+pub use self::fs::{Discovery, PathFilter, SourceFile};
+pub use self::rules::{Rule, Severity};
 pub use self::types::*;
 
 pub fn rust_language() -> Language {
     Language::new(RUST_LANGUAGE)
 }
 
-pub fn print_query_diagnostic(query: &str, err: &QueryError) {
-    eprintln!("Query compile error: {err}");
-    let err_str = err.to_string();
-    if let Some(pos_idx) = err_str.rfind("position ")
-        && let Some(num_str) = err_str[pos_idx + 9..].split(|c: char| !c.is_ascii_digit()).next()
-        && let Ok(offset) = num_str.parse::<usize>()
-        && offset < query.len()
-    {
-        let snippet_radius = 40usize;
-        let start = offset.saturating_sub(snippet_radius);
-        let end = (offset + snippet_radius).min(query.len());
-        let snippet = &query[start..end];
-        let caret_pos = offset - start;
-        eprintln!("--- query context ---\n{}\n{}^", snippet, " ".repeat(caret_pos));
-    }
+pub fn print_query_diagnostic(diag: &QueryDiagnostic) {
+    eprintln!("Query compile error: {diag}");
+    eprintln!("--- query context ---\n{}", diag.snippet);
 }
 
-pub fn dump_json(root: &Path, include_tests: bool, with_source: bool, max_depth: usize) -> Result<Vec<FileAst>> {
-    let lang = rust_language();
-    let files = fs::collect_rust_files(root, include_tests, &[])?;
-    let out: Vec<FileAst> = files
-        .par_iter()
-        .map(|p| fs::dump_file(&lang, p, with_source, max_depth))
-        .filter_map(|r| r.ok())
-        .collect();
-    Ok(out)
+pub fn dump_json(root: &Path, include_tests: bool, with_source: bool, max_depth: usize) -> Result<DumpOutput> {
+    dump_json_with_filter(root, include_tests, with_source, max_depth, &PathFilter::default())
 }
 
 pub fn dump_json_with_skips(
@@ -60,20 +91,30 @@ pub fn dump_json_with_skips(
     with_source: bool,
     max_depth: usize,
     skip_dirs: &[std::path::PathBuf],
-) -> Result<Vec<FileAst>> {
-    let lang = rust_language();
-    let files = fs::collect_rust_files(root, include_tests, skip_dirs)?;
-    let out: Vec<FileAst> = files
-        .par_iter()
-        .map(|p| fs::dump_file(&lang, p, with_source, max_depth))
-        .filter_map(|r| r.ok())
-        .collect();
-    Ok(out)
+) -> Result<DumpOutput> {
+    dump_json_with_filter(
+        root,
+        include_tests,
+        with_source,
+        max_depth,
+        &PathFilter::from_skip_dirs(skip_dirs),
+    )
 }
 
-pub fn execute_query(root: &Path, include_tests: bool, query_src: &str, context: bool) -> Result<Vec<CrateCaptures>> {
+pub fn dump_json_with_filter(
+    root: &Path,
+    include_tests: bool,
+    with_source: bool,
+    max_depth: usize,
+    filter: &PathFilter,
+) -> Result<DumpOutput> {
     let lang = rust_language();
-    query::execute_query(&lang, root, include_tests, query_src, context, &[])
+    let files = fs::discover_sources(root, include_tests, filter, Discovery::default())?;
+    Ok(fs::dump_sources(&lang, &files, with_source, max_depth))
+}
+
+pub fn execute_query(root: &Path, include_tests: bool, query_src: &str, context: bool) -> Result<QueryOutput> {
+    execute_query_with_filter(root, include_tests, query_src, context, &PathFilter::default())
 }
 
 pub fn execute_query_with_skips(
@@ -82,7 +123,65 @@ pub fn execute_query_with_skips(
     query_src: &str,
     context: bool,
     skip_dirs: &[std::path::PathBuf],
-) -> Result<Vec<CrateCaptures>> {
+) -> Result<QueryOutput> {
+    execute_query_with_filter(
+        root,
+        include_tests,
+        query_src,
+        context,
+        &PathFilter::from_skip_dirs(skip_dirs),
+    )
+}
+
+pub fn execute_query_with_filter(
+    root: &Path,
+    include_tests: bool,
+    query_src: &str,
+    context: bool,
+    filter: &PathFilter,
+) -> Result<QueryOutput> {
+    let lang = rust_language();
+    query::execute_query(
+        &lang,
+        root,
+        include_tests,
+        query_src,
+        context,
+        filter,
+        Discovery::default(),
+    )
+}
+
+/// Like [`execute_query`], but returns one result per pattern match with captures grouped by name.
+pub fn execute_query_matches(root: &Path, include_tests: bool, query_src: &str, context: bool) -> Result<MatchOutput> {
+    execute_query_matches_with_filter(root, include_tests, query_src, context, &PathFilter::default())
+}
+
+pub fn execute_query_matches_with_filter(
+    root: &Path,
+    include_tests: bool,
+    query_src: &str,
+    context: bool,
+    filter: &PathFilter,
+) -> Result<MatchOutput> {
+    let lang = rust_language();
+    query::execute_query_matches(
+        &lang,
+        root,
+        include_tests,
+        query_src,
+        context,
+        filter,
+        Discovery::default(),
+    )
+}
+
+pub fn check_syntax(root: &Path, include_tests: bool) -> Result<CheckOutput> {
+    check_syntax_with_filter(root, include_tests, &PathFilter::default())
+}
+
+pub fn check_syntax_with_filter(root: &Path, include_tests: bool, filter: &PathFilter) -> Result<CheckOutput> {
     let lang = rust_language();
-    query::execute_query(&lang, root, include_tests, query_src, context, skip_dirs)
+    let files = fs::discover_sources(root, include_tests, filter, Discovery::default())?;
+    Ok(check::check_sources(&lang, &files))
 }
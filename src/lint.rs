@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+use tracing::warn;
+use tree_sitter::{Node, Tree};
+
+use crate::error::{ArbolError, Result};
+use crate::fs::SourceFile;
+use crate::query::{
+    compile_query, for_each_match, with_parsed_file, with_tree, CompiledQuery, FileContext, FileResults,
+};
+use crate::rewrite::{edit_for, resolve_overlaps, rewrite_target, validate_edits, RewriteTarget, Template};
+use crate::rules::{render_message, Rule};
+use crate::types::{Edit, Finding, LintOutput, Suppression};
+use crate::workspace::{discover_crates, CrateRoot};
+
+/// A rule with its query compiled, the capture findings are anchored at resolved and its rewrite
+/// template checked against the query.
+struct CompiledRule {
+    rule: Rule,
+    compiled: CompiledQuery,
+    anchor: Option<u32>,
+    rewrite: Option<RewriteTarget>,
+}
+
+/// A set of rules compiled once, to lint any number of files with.
+pub(crate) struct Linter {
+    lang: tree_sitter::Language,
+    rules: Vec<CompiledRule>,
+    crate_roots: Vec<CrateRoot>,
+}
+
+impl Linter {
+    pub(crate) fn new(lang: &tree_sitter::Language, root: &Path, rules: &[Rule]) -> Result<Self> {
+        Ok(Self {
+            lang: lang.clone(),
+            rules: rules
+                .iter()
+                .map(|rule| compile_rule(lang, rule))
+                .collect::<Result<_>>()?,
+            crate_roots: discover_crates(root)?,
+        })
+    }
+
+    fn ids(&self) -> Vec<&str> {
+        self.rules.iter().map(|r| r.rule.id.as_str()).collect()
+    }
+
+    /// Lint a file parsed by the caller, e.g. an editor buffer that was never written to disk.
+    /// Findings are sorted by position and rule id.
+    pub(crate) fn lint_tree(&self, source: &SourceFile, src: &str, tree: &Tree) -> (Vec<Finding>, Vec<Suppression>) {
+        let (mut findings, unused) = with_tree(source, &self.crate_roots, src, tree, true, |ctx| self.lint(ctx, tree));
+        sort_findings(&mut findings);
+        (findings, unused)
+    }
+
+    fn lint(&self, ctx: &FileContext, tree: &Tree) -> (Vec<Finding>, Vec<Suppression>) {
+        let mut out = Vec::new();
+        for rule in &self.rules {
+            for_each_match(ctx, tree, &rule.compiled, |m| {
+                out.extend(finding(ctx, rule, m).filter(|f| !ctx.suppressions.suppresses(&f.rule, f.line)));
+            });
+        }
+        self.validate_suggestions(ctx, tree, &mut out);
+        (out, ctx.suppressions.unused(&self.ids()))
+    }
+
+    /// Like `arbol rewrite`, never suggest an edit that leaves new syntax errors behind. The file's
+    /// non-overlapping suggestions are checked together with a single reparse, falling back to one
+    /// check per edit only when they break the file in combination; overlapping ones are checked alone.
+    fn validate_suggestions(&self, ctx: &FileContext, tree: &Tree, findings: &mut [Finding]) {
+        let suggested: Vec<Edit> = findings.iter().filter_map(|f| f.suggestion.clone()).collect();
+        if suggested.is_empty() {
+            return;
+        }
+        let (batch, conflicts) = resolve_overlaps(suggested);
+        let mut valid = validate_edits(&self.lang, ctx, tree, batch).0;
+        for conflict in conflicts {
+            valid.extend(validate_edits(&self.lang, ctx, tree, vec![conflict.dropped]).0);
+        }
+        for f in findings {
+            if f.suggestion.as_ref().is_some_and(|edit| !valid.contains(edit)) {
+                f.suggestion = None;
+            }
+        }
+    }
+}
+
+/// Run every rule over every file. Each file is parsed once and all rule queries run on the same
+/// tree. Findings silenced by an `arbol-ignore` comment naming the rule id are dropped, and
+/// suppressions of a loaded rule that silenced nothing are reported. Findings are sorted by file,
+/// position and rule id.
+pub fn lint_sources(
+    lang: &tree_sitter::Language,
+    root: &Path,
+    files: &[SourceFile],
+    rules: &[Rule],
+) -> Result<LintOutput> {
+    let linter = Linter::new(lang, root, rules)?;
+
+    let results: Vec<FileResults<Finding>> = files
+        .par_iter()
+        .map(|source| {
+            with_parsed_file(lang, source, &linter.crate_roots, true, |ctx, tree| {
+                linter.lint(ctx, tree)
+            })
+        })
+        .collect();
+
+    let mut output = LintOutput::default();
+    for r in results {
+        match r {
+            Ok((mut findings, mut unused)) => {
+                output.findings.append(&mut findings);
+                output.unused_suppressions.append(&mut unused);
+            }
+            Err(d) => output.diagnostics.push(d),
+        }
+    }
+    sort_findings(&mut output.findings);
+    output.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    output
+        .unused_suppressions
+        .sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+    Ok(output)
+}
+
+fn sort_findings(findings: &mut [Finding]) {
+    findings.sort_by(|a, b| {
+        a.file
+            .cmp(&b.file)
+            .then_with(|| a.line.cmp(&b.line))
+            .then_with(|| a.column.cmp(&b.column))
+            .then_with(|| a.rule.cmp(&b.rule))
+    });
+}
+
+fn compile_rule(lang: &tree_sitter::Language, rule: &Rule) -> Result<CompiledRule> {
+    let compiled = compile_query(lang, &rule.query).map_err(|e| match e {
+        ArbolError::QueryCompile(diag) => ArbolError::RuleCompile {
+            path: rule.path.display().to_string(),
+            diag,
+        },
+        other => other,
+    })?;
+    let anchor = match &rule.capture {
+        Some(name) => Some(compiled.query.capture_index_for_name(name).ok_or_else(|| {
+            ArbolError::InvalidRule(format!(
+                "{}: capture `@{name}` does not appear in the query",
+                rule.path.display()
+            ))
+        })?),
+        None => None,
+    };
+    let rewrite = match &rule.rewrite {
+        Some(template) => Some(
+            rewrite_target(
+                &compiled.query,
+                &Template::parse(template),
+                rule.rewrite_capture.as_deref(),
+            )
+            .map_err(|e| match e {
+                ArbolError::InvalidRewrite(msg) => ArbolError::InvalidRule(format!("{}: {msg}", rule.path.display())),
+                other => other,
+            })?,
+        ),
+        None => None,
+    };
+    Ok(CompiledRule {
+        rule: rule.clone(),
+        compiled,
+        anchor,
+        rewrite,
+    })
+}
+
+fn finding(ctx: &FileContext, rule: &CompiledRule, m: &tree_sitter::QueryMatch) -> Option<Finding> {
+    let node = match rule.anchor {
+        Some(i) => m.nodes_for_capture_index(i).next(),
+        None => m.captures.iter().map(|c| c.node).min_by_key(Node::start_byte),
+    };
+    let Some(node) = node else {
+        warn!("lint: rule {} matched without captures in {}", rule.rule.id, ctx.file);
+        return None;
+    };
+    let query = &rule.compiled.query;
+    let text_of = |n: Node| n.utf8_text(ctx.src.as_bytes()).ok();
+    let message = match &rule.rule.message {
+        Some(template) => render_message(template, |key| match key {
+            "text" => text_of(node),
+            name => text_of(m.nodes_for_capture_index(query.capture_index_for_name(name)?).next()?),
+        }),
+        None => rule.rule.id.clone(),
+    };
+    let (start, end) = (node.start_position(), node.end_position());
+    Some(Finding {
+        rule: rule.rule.id.clone(),
+        severity: rule.rule.severity,
+        message,
+        file: ctx.file.clone(),
+        line: start.row + 1,
+        column: start.column + 1,
+        end_line: end.row + 1,
+        end_column: end.column + 1,
+        source_line: ctx.lines.get(start.row).copied().unwrap_or("").to_string(),
+        fix: rule.rule.fix.clone(),
+        help_uri: rule.rule.help_uri.clone(),
+        suggestion: rule.rewrite.as_ref().and_then(|r| edit_for(ctx, query, r, m)),
+    })
+}
+
+/// Render a finding the way rustc prints diagnostics:
+///
+/// ```text
+/// warning[pub-fn-too-long]: `foo` is longer than 50 lines
+///  --> src/lib.rs:3:8
+///   |
+/// 3 | pub fn foo() {
+///   |        ^^^
+///   = help: split it into smaller functions
+/// ```
+pub fn render_finding(f: &Finding) -> String {
+    let line_no = f.line.to_string();
+    let pad = " ".repeat(line_no.len());
+    let mut out = format!(
+        "{}[{}]: {}\n{pad}--> {}:{}:{}",
+        f.severity.as_str(),
+        f.rule,
+        f.message,
+        f.file,
+        f.line,
+        f.column
+    );
+    if !f.source_line.is_empty() {
+        let start = f.column - 1;
+        let end = if f.end_line == f.line {
+            f.end_column - 1
+        } else {
+            f.source_line.len()
+        };
+        let indent = f.source_line.get(..start).map_or(0, |s| s.chars().count());
+        let width = f.source_line.get(start..end).map_or(1, |s| s.chars().count()).max(1);
+        out.push_str(&format!(
+            "\n{pad} |\n{line_no} | {}\n{pad} | {}{}",
+            f.source_line.trim_end(),
+            " ".repeat(indent),
+            "^".repeat(width)
+        ));
+    }
+    if let Some(fix) = &f.fix {
+        out.push_str(&format!("\n{pad} = help: {fix}"));
+    }
+    if let Some(uri) = &f.help_uri {
+        out.push_str(&format!("\n{pad} = see: {uri}"));
+    }
+    out
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::error::Result;
+use crate::index::SourceIndex;
+use crate::lint::Linter;
+use crate::rpc::{self, Notification, RpcError, INVALID_REQUEST, METHOD_NOT_FOUND};
+use crate::rules::{Rule, Severity};
+use crate::types::{Finding, Suppression};
+
+/// `textDocumentSync` kind: clients send the whole document on every change.
+const SYNC_FULL: u8 = 1;
+
+/// `arbol lsp`: a minimal Language Server Protocol server over stdio. It lints the buffers the editor
+/// has open with a set of rules, publishing findings as diagnostics, and offers a rule's `rewrite`
+/// template as a quick fix.
+///
+/// Buffers are linted from their in-memory text, never from disk. Each buffer is kept parsed and
+/// re-parsed incrementally on change. Positions use the protocol's default UTF-16 columns.
+pub struct LanguageServer {
+    linter: Linter,
+    documents: SourceIndex,
+    open: BTreeMap<String, Document>,
+    outbox: Vec<Notification>,
+    shutdown: bool,
+}
+
+/// An open buffer: the path it is indexed under and what its last lint found.
+struct Document {
+    path: PathBuf,
+    findings: Vec<Finding>,
+    lines: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentItem {
+    uri: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: TextDocumentIdentifier,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Deserialize)]
+struct ContentChange {
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeActionParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Position {
+    line: usize,
+    character: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+impl LanguageServer {
+    /// Compile `rules` once; documents are resolved against `root` to find the crate they belong to.
+    pub fn new(lang: &tree_sitter::Language, root: &Path, rules: &[Rule]) -> Result<Self> {
+        Ok(Self {
+            linter: Linter::new(lang, root, rules)?,
+            documents: SourceIndex::new(lang, root, &[])?,
+            open: BTreeMap::new(),
+            outbox: Vec::new(),
+            shutdown: false,
+        })
+    }
+
+    /// Answer messages from `input` until `exit` or end of input.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> Result<()> {
+        while let Some(message) = rpc::read_framed_message(&mut input)? {
+            let request = match rpc::decode(&message) {
+                Ok(request) => request,
+                Err(response) => {
+                    rpc::write_framed_message(&mut output, &response)?;
+                    continue;
+                }
+            };
+            if request.method == "exit" {
+                break;
+            }
+            let outcome = self.handle(&request.method, request.params);
+            if let Some(id) = request.id {
+                rpc::write_framed_message(&mut output, &rpc::Response::new(id, outcome))?;
+            }
+            for notification in self.outbox.drain(..) {
+                rpc::write_framed_message(&mut output, &notification)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one method. Diagnostics it publishes are queued until the response has been sent.
+    fn handle(&mut self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        if self.shutdown {
+            return Err(RpcError::new(INVALID_REQUEST, "server is shut down"));
+        }
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": {"openClose": true, "change": SYNC_FULL},
+                    "codeActionProvider": {"codeActionKinds": ["quickfix"]},
+                },
+                "serverInfo": {"name": "arbol", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "initialized" => Ok(Value::Null),
+            "textDocument/didOpen" => {
+                let p: DidOpenParams = rpc::params(params)?;
+                self.update(p.text_document.uri, p.text_document.text);
+                Ok(Value::Null)
+            }
+            "textDocument/didChange" => {
+                let p: DidChangeParams = rpc::params(params)?;
+                // With full sync every change carries the whole buffer; the last one wins.
+                if let Some(change) = p.content_changes.into_iter().last() {
+                    self.update(p.text_document.uri, change.text);
+                }
+                Ok(Value::Null)
+            }
+            "textDocument/didClose" => {
+                let p: DocumentParams = rpc::params(params)?;
+                if let Some(doc) = self.open.remove(&p.text_document.uri) {
+                    self.documents.remove_file(&doc.path);
+                }
+                self.publish(&p.text_document.uri, Vec::new());
+                Ok(Value::Null)
+            }
+            "textDocument/codeAction" => {
+                let p: CodeActionParams = rpc::params(params)?;
+                Ok(Value::Array(self.code_actions(&p.text_document.uri, p.range)))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method not found: {method}"))),
+        }
+    }
+
+    /// Re-parse and lint the buffer behind `uri` and publish what it found.
+    fn update(&mut self, uri: String, text: String) {
+        let path = self
+            .open
+            .get(&uri)
+            .map_or_else(|| uri_to_path(&uri), |doc| doc.path.clone());
+        let (findings, unused) = match self.documents.update_file(&path, Some(text)) {
+            Ok(_) => {
+                let file = self.documents.file(&path).expect("updated document is indexed");
+                self.linter.lint_tree(&file.source, &file.src, &file.tree)
+            }
+            Err(d) => {
+                warn!("lsp: cannot lint {uri}: {}", d.reason);
+                Default::default()
+            }
+        };
+        let lines = self
+            .documents
+            .file(&path)
+            .map(|f| f.src.split('\n').map(str::to_string).collect())
+            .unwrap_or_default();
+        let doc = Document { path, findings, lines };
+        let mut diagnostics: Vec<Value> = doc.findings.iter().map(|f| diagnostic(&doc, f)).collect();
+        diagnostics.extend(unused.iter().map(|s| unused_suppression(&doc, s)));
+        self.open.insert(uri.clone(), doc);
+        self.publish(&uri, diagnostics);
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) {
+        self.outbox.push(Notification::new(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": diagnostics}),
+        ));
+    }
+
+    /// A quick fix for every finding with a suggested rewrite that overlaps `range`.
+    fn code_actions(&self, uri: &str, range: Range) -> Vec<Value> {
+        let Some(doc) = self.open.get(uri) else {
+            return Vec::new();
+        };
+        doc.findings
+            .iter()
+            .filter_map(|f| {
+                let edit = f.suggestion.as_ref()?;
+                let start = doc.position(f.line, f.column);
+                let end = doc.position(f.end_line, f.end_column);
+                if start > range.end || range.start > end {
+                    return None;
+                }
+                let title = match edit.replacement.lines().count() {
+                    0 | 1 if edit.replacement.len() <= 60 => {
+                        format!("{}: replace with `{}`", f.rule, edit.replacement)
+                    }
+                    _ => format!("{}: apply suggested rewrite", f.rule),
+                };
+                let text_edit = json!({
+                    "range": doc.range(edit.line, edit.column, edit.end_line, edit.end_column),
+                    "newText": edit.replacement,
+                });
+                Some(json!({
+                    "title": title,
+                    "kind": "quickfix",
+                    "diagnostics": [diagnostic(doc, f)],
+                    "isPreferred": true,
+                    "edit": {"changes": {uri: [text_edit]}},
+                }))
+            })
+            .collect()
+    }
+}
+
+impl Document {
+    /// The protocol position of a 1-based line and byte column.
+    fn position(&self, line: usize, column: usize) -> Position {
+        let text = self.lines.get(line - 1).map_or("", String::as_str);
+        let prefix = text.get(..column - 1).unwrap_or(text);
+        Position {
+            line: line - 1,
+            character: prefix.encode_utf16().count(),
+        }
+    }
+
+    fn range(&self, line: usize, column: usize, end_line: usize, end_column: usize) -> Value {
+        let (start, end) = (self.position(line, column), self.position(end_line, end_column));
+        json!({
+            "start": {"line": start.line, "character": start.character},
+            "end": {"line": end.line, "character": end.character},
+        })
+    }
+}
+
+fn diagnostic(doc: &Document, f: &Finding) -> Value {
+    let severity = match f.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let message = match &f.fix {
+        Some(fix) => format!("{}\nhelp: {fix}", f.message),
+        None => f.message.clone(),
+    };
+    let mut d = json!({
+        "range": doc.range(f.line, f.column, f.end_line, f.end_column),
+        "severity": severity,
+        "code": f.rule,
+        "source": "arbol",
+        "message": message,
+    });
+    if let Some(uri) = &f.help_uri {
+        d["codeDescription"] = json!({"href": uri});
+    }
+    d
+}
+
+/// A faded hint over an `arbol-ignore` comment that silenced nothing.
+fn unused_suppression(doc: &Document, s: &Suppression) -> Value {
+    let end_column = doc.lines.get(s.line - 1).map_or(s.column, |l| l.trim_end().len() + 1);
+    json!({
+        "range": doc.range(s.line, s.column, s.line, end_column),
+        "severity": 4,
+        "source": "arbol",
+        "message": format!("`{}` suppressed nothing", s.rule),
+        "tags": [1],
+    })
+}
+
+/// The path of a `file://` URI, percent-decoded. Other URIs (unsaved buffers) are used verbatim.
+fn uri_to_path(uri: &str) -> PathBuf {
+    let Some(rest) = uri.strip_prefix("file://") else {
+        return PathBuf::from(uri);
+    };
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&out).into_owned())
+}
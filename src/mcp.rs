@@ -0,0 +1,458 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tree_sitter::Language;
+
+use crate::error::{ArbolError, Result};
+use crate::fs::{discover_sources, dump_file, dump_subtree, Discovery, PathFilter};
+use crate::outline::outline_file;
+use crate::query::execute_query;
+use crate::rpc::{self, RpcError, INVALID_PARAMS, METHOD_NOT_FOUND};
+use crate::types::{FileAst, OutlineItem};
+
+/// Protocol revisions this server speaks, newest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+/// Entries a list-returning tool returns unless the call asks for another `limit`.
+const DEFAULT_LIMIT: usize = 100;
+/// Upper bound for `limit`, whatever the call asks for.
+const MAX_LIMIT: usize = 1000;
+/// Capture texts longer than this many characters are cut short.
+const MAX_TEXT: usize = 200;
+/// Default cap on the size of one tool result, in bytes.
+pub const DEFAULT_MAX_OUTPUT: usize = 64 * 1024;
+
+/// `arbol mcp`: a Model Context Protocol server over stdio (newline-delimited JSON-RPC 2.0) whose tools
+/// let a coding agent explore the workspace structurally: `run_query`, `list_node_kinds`,
+/// `outline_file`, `find_symbol` and `dump_subtree`.
+///
+/// Every call re-discovers and re-reads files, so results follow edits the agent makes. Results are
+/// bounded twice: lists stop after `limit` entries with a `truncated` note saying how many were left
+/// out, and the text of a whole result is cut at `max_output` bytes with a closing marker.
+pub struct McpServer {
+    lang: Language,
+    root: PathBuf,
+    include_tests: bool,
+    filter: PathFilter,
+    discovery: Discovery,
+    max_output: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitializeParams {
+    #[serde(default)]
+    protocol_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct RunQueryArgs {
+    query: String,
+    #[serde(default)]
+    context: bool,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct NodeKindsArgs {
+    #[serde(default)]
+    contains: Option<String>,
+    #[serde(default)]
+    include_anonymous: bool,
+}
+
+#[derive(Deserialize)]
+struct PathArgs {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct FindSymbolArgs {
+    name: String,
+    kind: Option<String>,
+    #[serde(default)]
+    partial: bool,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct DumpSubtreeArgs {
+    path: PathBuf,
+    line: Option<usize>,
+    column: Option<usize>,
+    max_depth: Option<usize>,
+    #[serde(default)]
+    with_source: bool,
+    limit: Option<usize>,
+}
+
+/// One capture of `run_query`, without the crate metadata repeated on every capture.
+#[derive(Serialize)]
+struct QueryHit {
+    file: String,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    name: String,
+    text: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    line_text: String,
+}
+
+/// One `find_symbol` result. `container` names the enclosing items, outermost first.
+#[derive(Serialize)]
+struct Symbol {
+    file: String,
+    kind: String,
+    name: String,
+    line: usize,
+    end_line: usize,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    container: String,
+}
+
+impl McpServer {
+    pub fn new(
+        lang: &Language,
+        root: &Path,
+        include_tests: bool,
+        filter: PathFilter,
+        discovery: Discovery,
+        max_output: usize,
+    ) -> Self {
+        Self {
+            lang: lang.clone(),
+            root: root.to_path_buf(),
+            include_tests,
+            filter,
+            discovery,
+            max_output,
+        }
+    }
+
+    /// Answer requests from `input` until end of input.
+    pub fn run(&self, mut input: impl BufRead, mut output: impl Write) -> Result<()> {
+        while let Some(message) = rpc::read_line_message(&mut input)? {
+            let request = match rpc::decode(&message) {
+                Ok(request) => request,
+                Err(response) => {
+                    rpc::write_line_message(&mut output, &response)?;
+                    continue;
+                }
+            };
+            let outcome = self.handle(&request.method, request.params);
+            if let Some(id) = request.id {
+                rpc::write_line_message(&mut output, &rpc::Response::new(id, outcome))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one method.
+    pub fn handle(&self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        match method {
+            "initialize" => {
+                let p: InitializeParams = rpc::params(params)?;
+                let version = p
+                    .protocol_version
+                    .filter(|v| PROTOCOL_VERSIONS.contains(&v.as_str()))
+                    .unwrap_or_else(|| PROTOCOL_VERSIONS[0].to_string());
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": {"tools": {"listChanged": false}},
+                    "serverInfo": {"name": "arbol", "version": env!("CARGO_PKG_VERSION")},
+                    "instructions": "Structural search over a Rust workspace with tree-sitter queries. \
+                        Use list_node_kinds to find grammar node kinds, run_query to search, \
+                        find_symbol / outline_file to navigate and dump_subtree to inspect syntax trees.",
+                }))
+            }
+            "notifications/initialized" | "notifications/cancelled" => Ok(Value::Null),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({"tools": tools()})),
+            "tools/call" => {
+                let p: CallParams = rpc::params(params)?;
+                let result = match p.name.as_str() {
+                    "run_query" => self.run_query(rpc::params(p.arguments)?),
+                    "list_node_kinds" => Ok(self.list_node_kinds(rpc::params(p.arguments)?)),
+                    "outline_file" => self.outline_file(rpc::params(p.arguments)?),
+                    "find_symbol" => self.find_symbol(rpc::params(p.arguments)?),
+                    "dump_subtree" => self.dump_subtree(rpc::params(p.arguments)?),
+                    name => return Err(RpcError::new(INVALID_PARAMS, format!("unknown tool: {name}"))),
+                };
+                // Failures of the tool itself are results the agent can read and react to.
+                Ok(match result {
+                    Ok(value) => json!({
+                        "content": [{"type": "text", "text": self.bounded(&value)}],
+                        "isError": false,
+                    }),
+                    Err(e) => json!({"content": [{"type": "text", "text": e.to_string()}], "isError": true}),
+                })
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method not found: {method}"))),
+        }
+    }
+
+    fn run_query(&self, args: RunQueryArgs) -> Result<Value> {
+        let output = execute_query(
+            &self.lang,
+            &self.root,
+            self.include_tests,
+            &args.query,
+            args.context,
+            &self.filter,
+            self.discovery,
+        )?;
+        let hits: Vec<QueryHit> = output
+            .crates
+            .into_iter()
+            .flat_map(|c| c.captures)
+            .map(|c| QueryHit {
+                file: c.file,
+                line: c.line,
+                column: c.column,
+                end_line: c.end_line,
+                end_column: c.end_column,
+                name: c.name,
+                text: shorten(c.text),
+                line_text: c.line_text,
+            })
+            .collect();
+        let mut result = limited("captures", hits, args.limit);
+        if !output.diagnostics.is_empty() {
+            result["skipped_files"] = json!(output.diagnostics);
+        }
+        Ok(result)
+    }
+
+    fn list_node_kinds(&self, args: NodeKindsArgs) -> Value {
+        let lang = &self.lang;
+        let wanted = |name: &str| args.contains.as_deref().is_none_or(|c| name.contains(c));
+        let mut kinds: Vec<&str> = (0..lang.node_kind_count() as u16)
+            .filter(|&id| lang.node_kind_is_visible(id) && (args.include_anonymous || lang.node_kind_is_named(id)))
+            .filter_map(|id| lang.node_kind_for_id(id))
+            .filter(|name| wanted(name))
+            .collect();
+        kinds.sort_unstable();
+        kinds.dedup();
+        let mut fields: Vec<&str> = (1..=lang.field_count() as u16)
+            .filter_map(|id| lang.field_name_for_id(id))
+            .filter(|name| wanted(name))
+            .collect();
+        fields.sort_unstable();
+        json!({"kinds": kinds, "fields": fields})
+    }
+
+    fn outline_file(&self, args: PathArgs) -> Result<Value> {
+        let outline = outline_file(&self.lang, &self.resolve(&args.path)?)?;
+        Ok(json!(outline))
+    }
+
+    fn find_symbol(&self, args: FindSymbolArgs) -> Result<Value> {
+        let files = discover_sources(&self.root, self.include_tests, &self.filter, self.discovery)?;
+        let mut symbols: Vec<Symbol> = files
+            .par_iter()
+            .filter_map(|f| outline_file(&self.lang, &f.path).ok())
+            .flat_map_iter(|outline| {
+                let mut out = Vec::new();
+                collect_symbols(&outline.file, &outline.items, "", &args, &mut out);
+                out
+            })
+            .collect();
+        symbols.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        Ok(limited("symbols", symbols, args.limit))
+    }
+
+    fn dump_subtree(&self, args: DumpSubtreeArgs) -> Result<Value> {
+        let path = self.resolve(&args.path)?;
+        let max_depth = args.max_depth.unwrap_or(3);
+        let ast = match args.line {
+            Some(line) => dump_subtree(&self.lang, &path, line, args.column, args.with_source, max_depth)?
+                .ok_or_else(|| ArbolError::Mcp(format!("{}: no line {line}", path.display())))?,
+            None => dump_file(&self.lang, &path, args.with_source, max_depth)?,
+        };
+        let FileAst {
+            path, root_kind, nodes, ..
+        } = ast;
+        let mut result = limited("nodes", nodes, args.limit);
+        result["path"] = json!(path);
+        result["root_kind"] = json!(root_kind);
+        Ok(result)
+    }
+
+    /// `path` relative to the root, or absolute; either way it must lie inside the root.
+    fn resolve(&self, path: &Path) -> Result<PathBuf> {
+        let joined = self.root.join(path);
+        let canonical = joined
+            .canonicalize()
+            .map_err(|e| ArbolError::Mcp(format!("{}: {e}", path.display())))?;
+        if !canonical.starts_with(self.root.canonicalize()?) {
+            return Err(ArbolError::Mcp(format!(
+                "{}: outside of the workspace root",
+                path.display()
+            )));
+        }
+        Ok(joined)
+    }
+
+    /// `value` as pretty JSON, cut at `max_output` bytes with a marker saying how much was left out.
+    fn bounded(&self, value: &Value) -> String {
+        let mut text = serde_json::to_string_pretty(value).unwrap_or_default();
+        if text.len() > self.max_output {
+            let mut cut = self.max_output;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let omitted = text.len() - cut;
+            text.truncate(cut);
+            text.push_str(&format!(
+                "\n[truncated: {omitted} more bytes; lower `limit` or narrow the request]"
+            ));
+        }
+        text
+    }
+}
+
+/// `{ "<key>": items[..limit], "total": n }`, plus a `truncated` note when entries were left out.
+fn limited<T: Serialize>(key: &str, mut items: Vec<T>, limit: Option<usize>) -> Value {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let total = items.len();
+    items.truncate(limit);
+    let mut result = json!({ key: items, "total": total });
+    if total > limit {
+        result["truncated"] = json!(format!(
+            "showing {limit} of {total} {key}; raise `limit` (at most {MAX_LIMIT}) or narrow the request"
+        ));
+    }
+    result
+}
+
+fn shorten(text: String) -> String {
+    match text.char_indices().nth(MAX_TEXT) {
+        Some((cut, _)) => format!("{}… [{} more bytes]", &text[..cut], text.len() - cut),
+        None => text,
+    }
+}
+
+fn collect_symbols(file: &str, items: &[OutlineItem], container: &str, args: &FindSymbolArgs, out: &mut Vec<Symbol>) {
+    for item in items {
+        let kind = item.kind.to_string();
+        let name_matches = if args.partial {
+            item.name.contains(&args.name)
+        } else {
+            item.name == args.name
+        };
+        if name_matches && args.kind.as_deref().is_none_or(|k| k == kind) {
+            out.push(Symbol {
+                file: file.to_string(),
+                kind: kind.clone(),
+                name: item.name.clone(),
+                line: item.line,
+                end_line: item.end_line,
+                container: container.to_string(),
+            });
+        }
+        if !item.children.is_empty() {
+            let label = item.label();
+            let inner = if container.is_empty() {
+                label
+            } else {
+                format!("{container} > {label}")
+            };
+            collect_symbols(file, &item.children, &inner, args, out);
+        }
+    }
+}
+
+/// Tool descriptions with JSON Schemas for their arguments, as listed by `tools/list`.
+fn tools() -> Value {
+    let limit = json!({
+        "type": "integer",
+        "minimum": 1,
+        "maximum": MAX_LIMIT,
+        "description": format!("Maximum number of entries to return (default {DEFAULT_LIMIT})"),
+    });
+    json!([
+        {
+            "name": "run_query",
+            "description": "Run a tree-sitter query (S-expression) over every Rust file of the workspace and \
+                return its captures with 1-based positions. arbol predicates such as #is-pub?, #has-attr?, \
+                #in-module? and #line-count>? are available.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "Query source, e.g. (function_item name: (identifier) @name)"},
+                    "context": {"type": "boolean", "description": "Include the source line of each capture"},
+                    "limit": limit,
+                },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "list_node_kinds",
+            "description": "List the node kinds and field names of the Rust grammar, for writing queries.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "contains": {"type": "string", "description": "Only names containing this text"},
+                    "include_anonymous": {"type": "boolean", "description": "Also list anonymous tokens such as `fn` or `{`"},
+                },
+            },
+        },
+        {
+            "name": "outline_file",
+            "description": "Items of one file (modules, functions, types, traits, impls and their members) \
+                with their line ranges, nested as written.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {"path": {"type": "string", "description": "File path, relative to the workspace root"}},
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "find_symbol",
+            "description": "Find items by name across the workspace: their file, kind, line range and \
+                enclosing items. Impl blocks are named `impl Trait for Type` / `impl Type`.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string", "description": "Item name"},
+                    "kind": {
+                        "type": "string",
+                        "enum": ["mod", "fn", "struct", "enum", "union", "trait", "impl", "type", "const", "static", "macro"],
+                    },
+                    "partial": {"type": "boolean", "description": "Match names containing `name` instead of equal to it"},
+                    "limit": limit,
+                },
+                "required": ["name"],
+            },
+        },
+        {
+            "name": "dump_subtree",
+            "description": "Syntax tree of a file, or of the node at a position, as a pre-order list of nodes \
+                with kinds, byte ranges and 0-based lines. Without a column, the outermost node starting on \
+                the line (usually the whole item) is dumped.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "File path, relative to the workspace root"},
+                    "line": {"type": "integer", "minimum": 1, "description": "1-based line; the whole file when omitted"},
+                    "column": {"type": "integer", "minimum": 1, "description": "1-based byte column"},
+                    "max_depth": {"type": "integer", "minimum": 0, "description": "Levels below the node to include (default 3)"},
+                    "with_source": {"type": "boolean", "description": "Include the source text of short nodes"},
+                    "limit": limit,
+                },
+                "required": ["path"],
+            },
+        },
+    ])
+}
@@ -0,0 +1,217 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use tracing::warn;
+use tree_sitter::Node;
+
+use crate::error::Result;
+use crate::fs::{collect_rust_files, parse_src_lang, read_source, PathFilter, SourceFile};
+use crate::types::TargetKind;
+use crate::workspace::discover_crates;
+
+/// A module file waiting to be read, with the directory its own `mod foo;` children resolve in.
+struct Pending {
+    file: PathBuf,
+    module_path: String,
+    mod_dir: PathBuf,
+}
+
+/// Collect the files reachable from each Cargo target's root file by following `mod foo;`
+/// declarations (`foo.rs` / `foo/mod.rs` layouts, inline `mod a { .. }` blocks and `#[path]`
+/// attributes). `cfg` attributes are not evaluated, so every declared module is followed. A file
+/// reached from several targets (`mod util;` in both `src/lib.rs` and `src/main.rs`) is listed once
+/// per target, with that target's module path, sorted by path and then discovery order.
+///
+/// The result is further restricted to what [`collect_rust_files`] would return with the same
+/// arguments, so skip dirs, globs, ignore files and the tests switch still apply.
+pub fn collect_module_files(
+    root: &Path,
+    include_tests: bool,
+    filter: &PathFilter,
+    respect_ignore: bool,
+) -> Result<Vec<SourceFile>> {
+    let allowed: HashMap<PathBuf, PathBuf> = collect_rust_files(root, include_tests, filter, respect_ignore)?
+        .into_iter()
+        .map(|p| (normalize(&p), p))
+        .collect();
+    let lang = crate::rust_language();
+    let mut out = Vec::new();
+    for krate in discover_crates(root)? {
+        for t in &krate.targets {
+            if !include_tests && matches!(t.target.kind, TargetKind::Test | TargetKind::Bench) {
+                continue;
+            }
+            for (file, module_path) in target_module_files(&lang, &t.root_file) {
+                if let Some(original) = allowed.get(&file) {
+                    out.push(SourceFile {
+                        path: original.clone(),
+                        module_path: Some(module_path),
+                        target: Some(t.target.clone()),
+                    });
+                }
+            }
+        }
+    }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(out)
+}
+
+/// The files of one target's module tree, reached from `root_file` through `mod foo;` declarations, with
+/// their module paths (`crate::a::b`). Paths are normalized; the root file comes first.
+pub(crate) fn target_module_files(lang: &tree_sitter::Language, root_file: &Path) -> Vec<(PathBuf, String)> {
+    let file = normalize(root_file);
+    let mod_dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut stack = vec![Pending {
+        file,
+        module_path: "crate".to_string(),
+        mod_dir,
+    }];
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    while let Some(pending) = stack.pop() {
+        if !seen.insert(pending.file.clone()) {
+            continue;
+        }
+        stack.extend(declared_modules(lang, &pending));
+        out.push((pending.file, pending.module_path));
+    }
+    out
+}
+
+/// Parse `pending.file` and resolve its out-of-line module declarations to files on disk.
+fn declared_modules(lang: &tree_sitter::Language, pending: &Pending) -> Vec<Pending> {
+    let src = match read_source(&pending.file) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("modules: failed to read {}: {}", pending.file.display(), e);
+            return Vec::new();
+        }
+    };
+    let tree = match parse_src_lang(lang, &src) {
+        Ok(t) => t,
+        Err(e) => {
+            warn!("modules: failed to parse {}: {}", pending.file.display(), e);
+            return Vec::new();
+        }
+    };
+    let file_dir = pending.file.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut out = Vec::new();
+    let scope = Scope {
+        src: &src,
+        file_dir: &file_dir,
+        inline: false,
+    };
+    scope.visit(tree.root_node(), &pending.mod_dir, &pending.module_path, &mut out);
+    out
+}
+
+struct Scope<'a> {
+    src: &'a str,
+    file_dir: &'a Path,
+    /// Inside an inline `mod a { .. }` block, where `#[path]` is relative to the module directory
+    /// instead of the file's directory.
+    inline: bool,
+}
+
+impl Scope<'_> {
+    fn visit(&self, items: Node, dir: &Path, module_path: &str, out: &mut Vec<Pending>) {
+        let mut path_attr = None;
+        for child in items.named_children(&mut items.walk()) {
+            match child.kind() {
+                "attribute_item" => {
+                    path_attr = path_attr.or_else(|| self.path_attribute(child));
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                _ => {}
+            }
+            let attr = path_attr.take();
+            if child.kind() != "mod_item" {
+                continue;
+            }
+            let Some(name) = child
+                .child_by_field_name("name")
+                .and_then(|n| n.utf8_text(self.src.as_bytes()).ok())
+            else {
+                continue;
+            };
+            let name = name.trim_start_matches("r#");
+            let child_path = format!("{module_path}::{name}");
+            if let Some(body) = child.child_by_field_name("body") {
+                let inner_dir = match &attr {
+                    Some(p) => self.attr_base(dir).join(p),
+                    None => dir.join(name),
+                };
+                let inner = Scope { inline: true, ..*self };
+                inner.visit(body, &normalize(&inner_dir), &child_path, out);
+                continue;
+            }
+            let resolved = match attr {
+                // Files loaded through `#[path]` own their directory, like `mod.rs`.
+                Some(p) => {
+                    let file = normalize(&self.attr_base(dir).join(p));
+                    let mod_dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+                    Some((file, mod_dir))
+                }
+                None => {
+                    let flat = dir.join(format!("{name}.rs"));
+                    let nested = dir.join(name).join("mod.rs");
+                    if flat.is_file() {
+                        Some((flat, dir.join(name)))
+                    } else if nested.is_file() {
+                        Some((nested, dir.join(name)))
+                    } else {
+                        None
+                    }
+                }
+            };
+            match resolved {
+                Some((file, mod_dir)) => out.push(Pending {
+                    file,
+                    module_path: child_path,
+                    mod_dir,
+                }),
+                None => warn!("modules: no file for `mod {name};` in {}", dir.display()),
+            }
+        }
+    }
+
+    fn attr_base<'d>(&'d self, dir: &'d Path) -> &'d Path {
+        if self.inline {
+            dir
+        } else {
+            self.file_dir
+        }
+    }
+
+    /// The string value of a `#[path = "..."]` attribute.
+    fn path_attribute(&self, item: Node) -> Option<String> {
+        let attr = item
+            .named_children(&mut item.walk())
+            .find(|n| n.kind() == "attribute")?;
+        let key = attr.named_child(0)?;
+        if key.kind() != "identifier" || key.utf8_text(self.src.as_bytes()).ok()? != "path" {
+            return None;
+        }
+        let value = attr.child_by_field_name("value")?;
+        let content = value
+            .named_children(&mut value.walk())
+            .find(|n| n.kind() == "string_content")?;
+        content.utf8_text(self.src.as_bytes()).ok().map(str::to_string)
+    }
+}
+
+/// Lexically resolve `.` and `..` components so paths reached through different routes compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(out.components().next_back(), Some(Component::Normal(_))) => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
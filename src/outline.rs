@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use tree_sitter::{Language, Node, Tree};
+
+use crate::error::Result;
+use crate::fs::{parse_src_lang, read_source};
+use crate::types::{FileOutline, ItemKind, OutlineItem};
+
+/// The items of `path`, nested as written: modules contain their items, impls and traits their
+/// associated items. Function bodies are not descended into.
+pub fn outline_file(lang: &Language, path: &Path) -> Result<FileOutline> {
+    let src = read_source(path)?;
+    let tree = parse_src_lang(lang, &src)?;
+    Ok(FileOutline {
+        file: path.display().to_string(),
+        items: outline_tree(&tree, &src),
+    })
+}
+
+/// [`outline_file`] for an already parsed source.
+pub fn outline_tree(tree: &Tree, src: &str) -> Vec<OutlineItem> {
+    let mut out = Vec::new();
+    collect_items(tree.root_node(), src, &mut out);
+    out
+}
+
+fn collect_items(parent: Node, src: &str, out: &mut Vec<OutlineItem>) {
+    for node in parent.named_children(&mut parent.walk()) {
+        // `extern "C" { ... }` blocks are transparent: their items belong to the enclosing module.
+        if node.kind() == "foreign_mod_item" {
+            if let Some(body) = node.child_by_field_name("body") {
+                collect_items(body, src, out);
+            }
+            continue;
+        }
+        let Some(kind) = item_kind(node.kind()) else {
+            continue;
+        };
+        let Some(name) = item_name(node, src) else {
+            continue;
+        };
+        let mut children = Vec::new();
+        if matches!(kind, ItemKind::Mod | ItemKind::Impl | ItemKind::Trait)
+            && let Some(body) = node.child_by_field_name("body")
+        {
+            collect_items(body, src, &mut children);
+        }
+        out.push(OutlineItem {
+            kind,
+            name,
+            line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+            children,
+        });
+    }
+}
+
+impl OutlineItem {
+    /// The item's kind and name (`fn tokenize`); impls are labelled by their name alone, which already
+    /// starts with `impl`.
+    pub fn label(&self) -> String {
+        match self.kind {
+            ItemKind::Impl => self.name.clone(),
+            kind => format!("{kind} {}", self.name),
+        }
+    }
+}
+
+/// Render an outline as an indented tree, one `kind name  line-end_line` line per item below the file
+/// name:
+///
+/// ```text
+/// src/lib.rs
+/// ├── mod parse  1-12
+/// │   └── fn tokenize  3-11
+/// └── impl Display for Config  14-20
+///     └── fn fmt  15-19
+/// ```
+pub fn render_outline(outline: &FileOutline) -> String {
+    let mut out = outline.file.clone();
+    render_items(&outline.items, "", &mut out);
+    out
+}
+
+fn render_items(items: &[OutlineItem], indent: &str, out: &mut String) {
+    for (i, item) in items.iter().enumerate() {
+        let last = i + 1 == items.len();
+        let (branch, nested) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let lines = if item.line == item.end_line {
+            item.line.to_string()
+        } else {
+            format!("{}-{}", item.line, item.end_line)
+        };
+        out.push_str(&format!("\n{indent}{branch}{}  {lines}", item.label()));
+        render_items(&item.children, &format!("{indent}{nested}"), out);
+    }
+}
+
+pub(crate) fn item_kind(node_kind: &str) -> Option<ItemKind> {
+    Some(match node_kind {
+        "mod_item" => ItemKind::Mod,
+        "function_item" | "function_signature_item" => ItemKind::Fn,
+        "struct_item" => ItemKind::Struct,
+        "enum_item" => ItemKind::Enum,
+        "union_item" => ItemKind::Union,
+        "trait_item" => ItemKind::Trait,
+        "impl_item" => ItemKind::Impl,
+        "type_item" | "associated_type" => ItemKind::Type,
+        "const_item" => ItemKind::Const,
+        "static_item" => ItemKind::Static,
+        "macro_definition" => ItemKind::Macro,
+        _ => return None,
+    })
+}
+
+/// The name of an item node, with whitespace runs collapsed; impls are named after their trait and
+/// type.
+pub(crate) fn item_name(node: Node, src: &str) -> Option<String> {
+    let field = |name| {
+        let text = node.child_by_field_name(name)?.utf8_text(src.as_bytes()).ok()?;
+        Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+    };
+    match node.kind() {
+        "impl_item" => Some(match field("trait") {
+            Some(t) => format!("impl {t} for {}", field("type")?),
+            None => format!("impl {}", field("type")?),
+        }),
+        _ => field("name"),
+    }
+}
@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+
+use tree_sitter::{Node, Query, QueryError, QueryPredicateArg};
+
+use crate::error::{ArbolError, QueryDiagnostic, Result};
+
+/// tree-sitter's query syntax does not allow `>` in predicate names, so `#line-count>?` is compiled
+/// under this same-length alias, keeping error positions in the original source exact.
+const LINE_COUNT_GT: &str = "#line-count>?";
+const LINE_COUNT_GT_ALIAS: &str = "#line-count-?";
+
+/// Rewrite `#line-count>?` predicates in `query_src` into a form tree-sitter accepts.
+pub(crate) fn rewrite_aliases(query_src: &str) -> Cow<'_, str> {
+    if !query_src.contains(LINE_COUNT_GT) {
+        return Cow::Borrowed(query_src);
+    }
+    let mut out = String::with_capacity(query_src.len());
+    let mut rest = query_src;
+    while let Some(i) = rest.find(LINE_COUNT_GT) {
+        out.push_str(&rest[..i]);
+        // Only the predicate name right after `(`, never the same text inside a string argument.
+        let opens_predicate = out.trim_end().ends_with('(');
+        out.push_str(if opens_predicate {
+            LINE_COUNT_GT_ALIAS
+        } else {
+            LINE_COUNT_GT
+        });
+        rest = &rest[i + LINE_COUNT_GT.len()..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Check {
+    /// The captured item has a bare `pub` visibility (`pub(crate)` and friends do not count).
+    IsPub { capture: u32 },
+    /// The captured item carries an outer attribute with exactly this path (`test`, `tokio::test`).
+    HasAttr { capture: u32, path: String },
+    /// The match lies in this module or one of its descendants.
+    InModule { path: String },
+    /// The captured node spans more than this many lines.
+    LineCountGt { capture: u32, lines: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Predicate {
+    check: Check,
+    negated: bool,
+}
+
+/// The arbol predicates of every pattern in a query: `#is-pub?`, `#has-attr?`, `#in-module?` (each
+/// also with a `not-` prefix) and `#line-count>?`. tree-sitter evaluates its own text predicates
+/// (`#eq?`, `#match?`, `#any-of?`, ...); anything else is rejected when the query is compiled so a
+/// typo fails loudly instead of silently matching everything.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Predicates {
+    per_pattern: Vec<Vec<Predicate>>,
+}
+
+impl Predicates {
+    /// Validate the predicates of `query`, compiled from [`rewrite_aliases`] of `query_src`.
+    pub(crate) fn new(query: &Query, query_src: &str) -> Result<Self> {
+        let mut per_pattern = Vec::with_capacity(query.pattern_count());
+        for pattern in 0..query.pattern_count() {
+            let mut preds = Vec::new();
+            for p in query.general_predicates(pattern) {
+                let op = match p.operator.as_ref() {
+                    "line-count-?" => "line-count>?",
+                    other => other,
+                };
+                let err = |message: String| {
+                    let start = query.start_byte_for_pattern(pattern);
+                    let offset = query_src[start..].find(&format!("#{op}")).map_or(start, |i| start + i);
+                    predicate_error(query_src, offset, message)
+                };
+                let (negated, name) = match op.strip_prefix("not-") {
+                    Some(rest) if rest != "line-count>?" => (true, rest),
+                    _ => (false, op),
+                };
+                let check = match (name, p.args.as_ref()) {
+                    ("is-pub?", [QueryPredicateArg::Capture(c)]) => Check::IsPub { capture: *c },
+                    ("has-attr?", [QueryPredicateArg::Capture(c), QueryPredicateArg::String(s)]) => Check::HasAttr {
+                        capture: *c,
+                        path: s.to_string(),
+                    },
+                    ("in-module?", [QueryPredicateArg::String(s)]) => Check::InModule { path: s.to_string() },
+                    ("line-count>?", [QueryPredicateArg::Capture(c), QueryPredicateArg::String(s)]) => {
+                        let lines = s
+                            .parse()
+                            .map_err(|_| err(format!("`#{op}` expects a line count, got \"{s}\"")))?;
+                        Check::LineCountGt { capture: *c, lines }
+                    }
+                    ("is-pub?", _) => return Err(err(format!("`#{op}` expects one capture"))),
+                    ("has-attr?", _) => return Err(err(format!("`#{op}` expects a capture and an attribute path"))),
+                    ("in-module?", _) => return Err(err(format!("`#{op}` expects a module path"))),
+                    ("line-count>?", _) => return Err(err(format!("`#{op}` expects a capture and a line count"))),
+                    _ => return Err(err(format!("unknown predicate `#{op}`"))),
+                };
+                preds.push(Predicate { check, negated });
+            }
+            per_pattern.push(preds);
+        }
+        Ok(Self { per_pattern })
+    }
+
+    /// Whether every arbol predicate of the match's pattern holds. `file_module` is the module path
+    /// of the file itself; inline `mod` blocks around the match are appended to it.
+    pub(crate) fn hold(&self, m: &tree_sitter::QueryMatch, src: &str, file_module: Option<&str>) -> bool {
+        let Some(preds) = self.per_pattern.get(m.pattern_index) else {
+            return true;
+        };
+        preds.iter().all(|p| {
+            let ok = match &p.check {
+                Check::IsPub { capture } => m.nodes_for_capture_index(*capture).all(|n| is_pub(item_of(n), src)),
+                Check::HasAttr { capture, path } => m
+                    .nodes_for_capture_index(*capture)
+                    .all(|n| has_attr(item_of(n), src, path)),
+                Check::InModule { path } => {
+                    let node = m.captures.first().map(|c| c.node);
+                    let module = module_at(node, src, file_module.unwrap_or("crate"));
+                    module == *path || module.strip_prefix(path.as_str()).is_some_and(|r| r.starts_with("::"))
+                }
+                Check::LineCountGt { capture, lines } => m
+                    .nodes_for_capture_index(*capture)
+                    .all(|n| n.end_position().row - n.start_position().row + 1 > *lines),
+            };
+            ok != p.negated
+        })
+    }
+}
+
+fn predicate_error(query_src: &str, offset: usize, message: String) -> ArbolError {
+    let before = &query_src[..offset];
+    let row = before.matches('\n').count();
+    let column = offset - before.rfind('\n').map_or(0, |i| i + 1);
+    let err = QueryError {
+        row,
+        column,
+        offset,
+        message,
+        kind: tree_sitter::QueryErrorKind::Predicate,
+    };
+    ArbolError::QueryCompile(Box::new(QueryDiagnostic::new(query_src, &err)))
+}
+
+/// The item a capture refers to: the node itself, or its parent when the capture is the item's name.
+fn item_of(node: Node) -> Node {
+    match node.parent() {
+        Some(parent) if parent.child_by_field_name("name") == Some(node) => parent,
+        _ => node,
+    }
+}
+
+fn is_pub(item: Node, src: &str) -> bool {
+    item.children(&mut item.walk())
+        .any(|c| c.kind() == "visibility_modifier" && c.utf8_text(src.as_bytes()) == Ok("pub"))
+}
+
+/// Whether one of the outer attributes directly above `item` has the given path.
+fn has_attr(item: Node, src: &str, path: &str) -> bool {
+    let mut cur = item.prev_named_sibling();
+    while let Some(sib) = cur {
+        match sib.kind() {
+            "attribute_item" => {
+                let attr_path = sib
+                    .named_child(0)
+                    .filter(|a| a.kind() == "attribute")
+                    .and_then(|a| a.named_child(0))
+                    .and_then(|p| p.utf8_text(src.as_bytes()).ok());
+                if attr_path == Some(path) {
+                    return true;
+                }
+            }
+            "line_comment" | "block_comment" => {}
+            _ => break,
+        }
+        cur = sib.prev_named_sibling();
+    }
+    false
+}
+
+/// `file_module` extended with the inline `mod a { .. }` blocks enclosing `node`.
+fn module_at(node: Option<Node>, src: &str, file_module: &str) -> String {
+    let mut inline = Vec::new();
+    let mut child = node;
+    let mut cur = node.and_then(|n| n.parent());
+    while let Some(n) = cur {
+        if n.kind() == "mod_item"
+            && n.child_by_field_name("body") == child
+            && let Some(name) = n.child_by_field_name("name")
+            && let Ok(name) = name.utf8_text(src.as_bytes())
+        {
+            inline.push(name.trim_start_matches("r#"));
+        }
+        child = Some(n);
+        cur = n.parent();
+    }
+    let mut module = file_module.to_string();
+    for name in inline.iter().rev() {
+        module.push_str("::");
+        module.push_str(name);
+    }
+    module
+}
@@ -1,14 +1,22 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tracing::warn;
-use tree_sitter::{Parser as TsParser, StreamingIterator};
+use tree_sitter::{Node, Parser as TsParser, Query, StreamingIterator, Tree};
 
-use crate::error::{ArbolError, Result};
-use crate::fs::collect_rust_files;
-use crate::types::{Capture, CrateCaptures};
-use crate::workspace::{crate_for_file, discover_crate_roots};
+use crate::cache::{CacheScope, QueryCache};
+use crate::error::{ArbolError, QueryDiagnostic, Result};
+use crate::fs::{discover_sources, file_diagnostic, for_each_ordered, read_source, Discovery, PathFilter, SourceFile};
+use crate::predicates::{rewrite_aliases, Predicates};
+use crate::suppress::Suppressions;
+use crate::types::{
+    Capture, CapturedNode, CrateCaptures, CrateInfo, CrateMatches, FileDiagnostic, MatchOutput, QueryEvent, QueryMatch,
+    QueryOutput, Suppression, Target, TargetKind,
+};
+use crate::workspace::{crate_for_file, discover_crates, infer_module_path, target_for_file, CrateRoot};
 
 pub fn execute_query(
     lang: &tree_sitter::Language,
@@ -16,111 +24,564 @@ pub fn execute_query(
     include_tests: bool,
     query_src: &str,
     context: bool,
-    skip_dirs: &[std::path::PathBuf],
-) -> Result<Vec<CrateCaptures>> {
-    let files = collect_rust_files(root, include_tests, skip_dirs)?;
-    let crate_roots = discover_crate_roots(root)?;
-    let ts_query = match tree_sitter::Query::new(lang, query_src) {
-        Ok(q) => q,
-        Err(_) => {
-            return Err(ArbolError::QueryCompile);
-        }
-    };
-    let capture_names = ts_query.capture_names().to_vec();
-    // Validate language can be set once (avoid per-thread expect/unwrap)
-    {
-        let mut test_parser = TsParser::new();
-        test_parser
-            .set_language(lang)
-            .map_err(|e| ArbolError::SetLanguage(e.to_string()))?;
-    }
+    filter: &PathFilter,
+    discovery: Discovery,
+) -> Result<QueryOutput> {
+    let files = discover_sources(root, include_tests, filter, discovery)?;
+    query_sources(lang, root, &files, query_src, context, None)
+}
 
-    let captures: Vec<Capture> = files
-        .par_iter()
-        .map(|p| {
-            let mut parser = TsParser::new();
-            if let Err(e) = parser.set_language(lang) {
-                warn!("tree-sitter: set_language failed for {}: {}", p.display(), e);
-                return Vec::new();
-            }
-            let src = match std::fs::read_to_string(p) {
-                Ok(s) => s,
-                Err(e) => {
-                    warn!("io: failed to read {}: {}", p.display(), e);
-                    return Vec::new();
-                }
-            };
-            let tree = match parser.parse(&src, None) {
-                Some(t) => t,
-                None => {
-                    warn!("tree-sitter: parse returned None for {}", p.display());
-                    return Vec::new();
-                }
-            };
-            let mut cursor = tree_sitter::QueryCursor::new();
-            let root_node = tree.root_node();
-            let crate_path = crate_for_file(p, &crate_roots).unwrap_or_default();
-            let lines: Vec<&str> = if context { src.lines().collect() } else { Vec::new() };
-            let file_path = p.display().to_string();
-            let mut out = Vec::new();
-            let mut matches = cursor.matches(&ts_query, root_node, src.as_bytes());
-            while let Some(m) = matches.next() {
-                for cap in m.captures.iter() {
-                    let node = cap.node;
-                    let pos = node.start_position();
-                    let text = match node.utf8_text(src.as_bytes()) {
-                        Ok(t) => t.to_string(),
-                        Err(e) => {
-                            warn!("tree-sitter: utf8_text error in {}: {}", file_path, e);
-                            String::new()
-                        }
-                    };
-                    let line_text = if context {
-                        lines.get(pos.row).copied().unwrap_or("").trim().to_string()
-                    } else {
-                        String::new()
-                    };
-                    out.push(Capture {
-                        crate_path: crate_path.clone(),
-                        file: file_path.clone(),
-                        line: pos.row + 1,
-                        column: pos.column + 1,
-                        name: capture_names[cap.index as usize].to_string(),
-                        text,
-                        line_text,
-                    });
-                }
+/// [`execute_query`] over already discovered `files`, reusing the results of unchanged files from
+/// `cache` when given.
+pub fn query_sources(
+    lang: &tree_sitter::Language,
+    root: &Path,
+    files: &[SourceFile],
+    query_src: &str,
+    context: bool,
+    cache: Option<&QueryCache>,
+) -> Result<QueryOutput> {
+    let crate_roots = discover_crates(root)?;
+    let compiled = compile_query(lang, query_src)?;
+    let capture_names = compiled.query.capture_names().to_vec();
+    let scope = cache.map(|c| c.scope(lang, query_src, "captures", context));
+
+    let (captures, diagnostics, unused_suppressions) = run_query(
+        lang,
+        files,
+        &crate_roots,
+        &compiled,
+        context,
+        scope.as_ref(),
+        |ctx, m, out| push_captures(ctx, m, &capture_names, out),
+    )?;
+    Ok(captures_output(captures, diagnostics, unused_suppressions))
+}
+
+/// Streaming form of [`execute_query`] over already discovered `files`: files are queried in
+/// parallel a small window at a time and each capture is handed to `on_event` once its window is
+/// done, in file order (captures within a file by position). Unreadable files arrive as [`QueryEvent::Diagnostic`],
+/// and a file's unused suppressions follow its captures as [`QueryEvent::UnusedSuppression`]. Return
+/// `false` from `on_event` to stop early. Query compile errors are returned before any event.
+pub fn stream_captures(
+    lang: &tree_sitter::Language,
+    root: &Path,
+    files: &[SourceFile],
+    query_src: &str,
+    context: bool,
+    cache: Option<&QueryCache>,
+    mut on_event: impl FnMut(QueryEvent) -> bool,
+) -> Result<()> {
+    let crate_roots = discover_crates(root)?;
+    let compiled = compile_query(lang, query_src)?;
+    let capture_names = compiled.query.capture_names().to_vec();
+    let scope = cache.map(|c| c.scope(lang, query_src, "captures", context));
+    let build = |ctx: &FileContext, m: &tree_sitter::QueryMatch, out: &mut Vec<Capture>| {
+        push_captures(ctx, m, &capture_names, out)
+    };
+    for_each_ordered(
+        files,
+        |source| query_file(lang, source, &crate_roots, &compiled, context, scope.as_ref(), &build),
+        |r| match r {
+            Ok((mut captures, unused)) => {
+                captures.sort_by(capture_order);
+                captures.into_iter().all(|c| on_event(QueryEvent::Capture(Box::new(c))))
+                    && unused.into_iter().all(|s| on_event(QueryEvent::UnusedSuppression(s)))
             }
-            out
-        })
-        .reduce(Vec::new, |mut a, mut b| {
-            a.append(&mut b);
-            a
-        });
+            Err(d) => on_event(QueryEvent::Diagnostic(d)),
+        },
+    );
+    Ok(())
+}
+
+/// Like [`execute_query`], but keeps each match together: one [`QueryMatch`] per pattern match,
+/// with its captured nodes keyed by capture name.
+pub fn execute_query_matches(
+    lang: &tree_sitter::Language,
+    root: &Path,
+    include_tests: bool,
+    query_src: &str,
+    context: bool,
+    filter: &PathFilter,
+    discovery: Discovery,
+) -> Result<MatchOutput> {
+    let files = discover_sources(root, include_tests, filter, discovery)?;
+    query_matches_sources(lang, root, &files, query_src, context, None)
+}
+
+/// [`execute_query_matches`] over already discovered `files`, with an optional `cache` as in
+/// [`query_sources`].
+pub fn query_matches_sources(
+    lang: &tree_sitter::Language,
+    root: &Path,
+    files: &[SourceFile],
+    query_src: &str,
+    context: bool,
+    cache: Option<&QueryCache>,
+) -> Result<MatchOutput> {
+    let crate_roots = discover_crates(root)?;
+    let compiled = compile_query(lang, query_src)?;
+    let capture_names = compiled.query.capture_names().to_vec();
+    let scope = cache.map(|c| c.scope(lang, query_src, "matches", context));
+
+    let (matches, diagnostics, unused_suppressions) = run_query(
+        lang,
+        files,
+        &crate_roots,
+        &compiled,
+        context,
+        scope.as_ref(),
+        |ctx, m, out| push_match(ctx, m, &capture_names, out),
+    )?;
+    Ok(matches_output(matches, diagnostics, unused_suppressions))
+}
 
-    let mut captures = captures;
+/// Sort captures of many files and group them by crate.
+pub(crate) fn captures_output(
+    mut captures: Vec<Capture>,
+    diagnostics: Vec<FileDiagnostic>,
+    unused_suppressions: Vec<Suppression>,
+) -> QueryOutput {
     captures.sort_by(|a, b| {
-        a.crate_path
-            .cmp(&b.crate_path)
+        crate_key(&a.krate, &a.crate_path)
+            .cmp(&crate_key(&b.krate, &b.crate_path))
             .then_with(|| a.file.cmp(&b.file))
-            .then_with(|| a.line.cmp(&b.line))
-            .then_with(|| a.column.cmp(&b.column))
-            .then_with(|| a.name.cmp(&b.name))
-            .then_with(|| a.text.cmp(&b.text))
+            .then_with(|| capture_order(a, b))
     });
 
-    let mut grouped: HashMap<String, Vec<Capture>> = HashMap::new();
-    for c in captures {
-        grouped.entry(c.crate_path.clone()).or_default().push(c);
+    let crates = runs_by_crate(captures, |c| &c.crate_path)
+        .into_iter()
+        .map(|captures| CrateCaptures {
+            crate_path: captures[0].crate_path.clone(),
+            krate: captures[0].krate.clone(),
+            captures,
+        })
+        .collect();
+    QueryOutput {
+        crates,
+        diagnostics,
+        unused_suppressions,
     }
+}
 
-    let mut crates: Vec<CrateCaptures> = grouped
+/// [`captures_output`] for match-grouped results.
+pub(crate) fn matches_output(
+    mut matches: Vec<QueryMatch>,
+    diagnostics: Vec<FileDiagnostic>,
+    unused_suppressions: Vec<Suppression>,
+) -> MatchOutput {
+    matches.sort_by(|a, b| {
+        crate_key(&a.krate, &a.crate_path)
+            .cmp(&crate_key(&b.krate, &b.crate_path))
+            .then_with(|| a.file.cmp(&b.file))
+            .then_with(|| a.line.cmp(&b.line))
+            .then_with(|| a.column.cmp(&b.column))
+            .then_with(|| a.pattern_index.cmp(&b.pattern_index))
+    });
+
+    let crates = runs_by_crate(matches, |m| &m.crate_path)
         .into_iter()
-        .map(|(k, v)| CrateCaptures {
-            crate_path: k,
-            captures: v,
+        .map(|matches| CrateMatches {
+            crate_path: matches[0].crate_path.clone(),
+            krate: matches[0].krate.clone(),
+            matches,
         })
         .collect();
-    crates.sort_by(|a, b| a.crate_path.cmp(&b.crate_path));
-    Ok(crates)
+    MatchOutput {
+        crates,
+        diagnostics,
+        unused_suppressions,
+    }
+}
+
+/// A compiled tree-sitter query together with its validated arbol predicates.
+pub(crate) struct CompiledQuery {
+    pub(crate) query: Query,
+    pub(crate) predicates: Predicates,
+}
+
+pub(crate) fn compile_query(lang: &tree_sitter::Language, query_src: &str) -> Result<CompiledQuery> {
+    let query = Query::new(lang, &rewrite_aliases(query_src))
+        .map_err(|e| ArbolError::QueryCompile(Box::new(QueryDiagnostic::new(query_src, &e))))?;
+    let predicates = Predicates::new(&query, query_src)?;
+    // Validate language can be set once (avoid per-thread expect/unwrap)
+    let mut test_parser = TsParser::new();
+    test_parser
+        .set_language(lang)
+        .map_err(|e| ArbolError::SetLanguage(e.to_string()))?;
+    Ok(CompiledQuery { query, predicates })
+}
+
+/// Per-file details shared by every result built from that file.
+pub(crate) struct FileContext<'a> {
+    pub(crate) source: &'a SourceFile,
+    pub(crate) file: String,
+    pub(crate) crate_path: String,
+    pub(crate) krate: Option<CrateInfo>,
+    pub(crate) target: Option<Target>,
+    /// Module path of the file for `#in-module?`: known in module-tree mode, otherwise inferred from
+    /// its location.
+    pub(crate) module: Option<String>,
+    pub(crate) src: &'a str,
+    /// Source lines, only split when line context was requested.
+    pub(crate) lines: Vec<&'a str>,
+    /// `arbol-ignore` comments of the file.
+    pub(crate) suppressions: Suppressions,
+}
+
+impl FileContext<'_> {
+    pub(crate) fn text(&self, node: Node) -> String {
+        match node.utf8_text(self.src.as_bytes()) {
+            Ok(t) => t.to_string(),
+            Err(e) => {
+                warn!("tree-sitter: utf8_text error in {}: {}", self.file, e);
+                String::new()
+            }
+        }
+    }
+
+    pub(crate) fn line_text(&self, row: usize) -> String {
+        self.lines.get(row).copied().unwrap_or("").trim().to_string()
+    }
+}
+
+/// Results of one file and its unused suppressions, or why the file was skipped.
+pub(crate) type FileResults<T> = std::result::Result<(Vec<T>, Vec<Suppression>), FileDiagnostic>;
+
+/// Run [`query_file`] over every file in parallel, collecting files that fail to read or parse as
+/// diagnostics.
+fn run_query<T, F>(
+    lang: &tree_sitter::Language,
+    files: &[SourceFile],
+    crate_roots: &[CrateRoot],
+    compiled: &CompiledQuery,
+    context: bool,
+    cache: Option<&CacheScope>,
+    build: F,
+) -> Result<(Vec<T>, Vec<FileDiagnostic>, Vec<Suppression>)>
+where
+    T: Send + Serialize + DeserializeOwned,
+    F: Fn(&FileContext, &tree_sitter::QueryMatch, &mut Vec<T>) + Sync,
+{
+    let results: Vec<FileResults<T>> = files
+        .par_iter()
+        .map(|source| query_file(lang, source, crate_roots, compiled, context, cache, &build))
+        .collect();
+
+    let mut items = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut unused = Vec::new();
+    for r in results {
+        match r {
+            Ok((mut v, mut u)) => {
+                items.append(&mut v);
+                unused.append(&mut u);
+            }
+            Err(d) => diagnostics.push(d),
+        }
+    }
+    diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    unused.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+    Ok((items, diagnostics, unused))
+}
+
+/// Parse one file and hand each query match whose arbol predicates hold to `build`. Capture names
+/// act as rule ids for `arbol-ignore` comments; suppressions for one of the query's captures that
+/// silenced nothing are returned alongside the results. With a `cache`, an unchanged file's results
+/// are loaded instead and fresh results are stored.
+fn query_file<T, F>(
+    lang: &tree_sitter::Language,
+    source: &SourceFile,
+    crate_roots: &[CrateRoot],
+    compiled: &CompiledQuery,
+    context: bool,
+    cache: Option<&CacheScope>,
+    build: &F,
+) -> FileResults<T>
+where
+    T: Serialize + DeserializeOwned,
+    F: Fn(&FileContext, &tree_sitter::QueryMatch, &mut Vec<T>),
+{
+    let p = &source.path;
+    let src = read_source(p).map_err(|e| file_diagnostic(p, &e))?;
+    let origin = FileOrigin::new(source, crate_roots);
+    // A file shared by several targets is queried once per target; each gets its own entry.
+    let entry = match &origin.target {
+        Some(t) if source.target.is_some() => format!("{} {:?} {}", origin.file, t.kind, t.name),
+        _ => origin.file.clone(),
+    };
+    let cached = cache.map(|c| (c, c.key(&entry, &src, &format!("{origin:?}"))));
+    if let Some((cache, key)) = &cached
+        && let Some(hit) = cache.load(key)
+    {
+        return Ok(hit);
+    }
+    let (results, unused) = with_parsed_source(lang, source, origin, &src, context, |ctx, tree| {
+        let mut out = Vec::new();
+        for_each_match(ctx, tree, compiled, |m| build(ctx, m, &mut out));
+        (out, ctx.suppressions.unused(compiled.query.capture_names()))
+    })?;
+    if let Some((cache, key)) = &cached {
+        cache.store(key, &results, &unused);
+    }
+    Ok((results, unused))
+}
+
+/// Where a file sits in the workspace: everything results carry about it besides its contents.
+#[derive(Debug)]
+struct FileOrigin {
+    file: String,
+    crate_path: String,
+    krate: Option<CrateInfo>,
+    target: Option<Target>,
+    module: Option<String>,
+}
+
+impl FileOrigin {
+    fn new(source: &SourceFile, crate_roots: &[CrateRoot]) -> Self {
+        let p = &source.path;
+        let krate = crate_for_file(p, crate_roots);
+        Self {
+            file: p.display().to_string(),
+            crate_path: krate.map(|c| c.path.display().to_string()).unwrap_or_default(),
+            krate: krate.and_then(|c| c.info.clone()),
+            target: source
+                .target
+                .clone()
+                .or_else(|| krate.and_then(|c| target_for_file(p, c))),
+            module: source
+                .module_path
+                .clone()
+                .or_else(|| krate.and_then(|c| infer_module_path(p, c))),
+        }
+    }
+}
+
+/// Read and parse `source` once and hand it to `f` together with its per-file context.
+pub(crate) fn with_parsed_file<R>(
+    lang: &tree_sitter::Language,
+    source: &SourceFile,
+    crate_roots: &[CrateRoot],
+    context: bool,
+    f: impl FnOnce(&FileContext, &Tree) -> R,
+) -> std::result::Result<R, FileDiagnostic> {
+    let p = &source.path;
+    let src = read_source(p).map_err(|e| file_diagnostic(p, &e))?;
+    with_parsed_source(lang, source, FileOrigin::new(source, crate_roots), &src, context, f)
+}
+
+/// [`with_parsed_file`] for a file whose contents were already read.
+fn with_parsed_source<R>(
+    lang: &tree_sitter::Language,
+    source: &SourceFile,
+    origin: FileOrigin,
+    src: &str,
+    context: bool,
+    f: impl FnOnce(&FileContext, &Tree) -> R,
+) -> std::result::Result<R, FileDiagnostic> {
+    let p = &source.path;
+    let mut parser = TsParser::new();
+    parser
+        .set_language(lang)
+        .map_err(|e| file_diagnostic(p, &ArbolError::SetLanguage(e.to_string())))?;
+    let tree = parser
+        .parse(src, None)
+        .ok_or_else(|| file_diagnostic(p, &ArbolError::ParseFailed))?;
+    let ctx = file_context(source, origin, src, &tree, context);
+    Ok(f(&ctx, &tree))
+}
+
+fn file_context<'a>(
+    source: &'a SourceFile,
+    origin: FileOrigin,
+    src: &'a str,
+    tree: &Tree,
+    context: bool,
+) -> FileContext<'a> {
+    FileContext {
+        source,
+        suppressions: Suppressions::parse(tree, src, &origin.file),
+        file: origin.file,
+        crate_path: origin.crate_path,
+        krate: origin.krate,
+        target: origin.target,
+        module: origin.module,
+        src,
+        lines: if context { src.lines().collect() } else { Vec::new() },
+    }
+}
+
+/// Run `compiled` over a file parsed by the caller, for callers that keep trees between runs.
+/// Returns the results built by `build` and the file's unused suppressions, as [`query_file`] does.
+pub(crate) fn query_tree<T>(
+    source: &SourceFile,
+    crate_roots: &[CrateRoot],
+    compiled: &CompiledQuery,
+    src: &str,
+    tree: &Tree,
+    context: bool,
+    build: impl Fn(&FileContext, &tree_sitter::QueryMatch, &mut Vec<T>),
+) -> (Vec<T>, Vec<Suppression>) {
+    with_tree(source, crate_roots, src, tree, context, |ctx| {
+        let mut out = Vec::new();
+        for_each_match(ctx, tree, compiled, |m| build(ctx, m, &mut out));
+        (out, ctx.suppressions.unused(compiled.query.capture_names()))
+    })
+}
+
+/// Hand a file parsed by the caller to `f` together with its per-file context.
+pub(crate) fn with_tree<R>(
+    source: &SourceFile,
+    crate_roots: &[CrateRoot],
+    src: &str,
+    tree: &Tree,
+    context: bool,
+    f: impl FnOnce(&FileContext) -> R,
+) -> R {
+    f(&file_context(
+        source,
+        FileOrigin::new(source, crate_roots),
+        src,
+        tree,
+        context,
+    ))
+}
+
+/// Run `compiled` over a parsed file, calling `f` for each match whose arbol predicates hold.
+pub(crate) fn for_each_match(
+    ctx: &FileContext,
+    tree: &Tree,
+    compiled: &CompiledQuery,
+    mut f: impl FnMut(&tree_sitter::QueryMatch),
+) {
+    let mut cursor = tree_sitter::QueryCursor::new();
+    let mut matches = cursor.matches(&compiled.query, tree.root_node(), ctx.src.as_bytes());
+    while let Some(m) = matches.next() {
+        if compiled.predicates.hold(m, ctx.src, ctx.module.as_deref()) {
+            f(m);
+        }
+    }
+}
+
+/// One [`QueryMatch`] per match, unless every capture of it was suppressed.
+pub(crate) fn push_match(
+    ctx: &FileContext,
+    m: &tree_sitter::QueryMatch,
+    capture_names: &[&str],
+    out: &mut Vec<QueryMatch>,
+) {
+    let mut captures: BTreeMap<String, Vec<CapturedNode>> = BTreeMap::new();
+    let mut start = None;
+    for cap in m.captures.iter() {
+        let node = cap.node;
+        let pos = node.start_position();
+        let end = node.end_position();
+        let name = capture_names[cap.index as usize];
+        if ctx.suppressions.suppresses(name, pos.row + 1) {
+            continue;
+        }
+        let at = (pos.row, pos.column);
+        start = Some(start.map_or(at, |s: (usize, usize)| s.min(at)));
+        captures.entry(name.to_string()).or_default().push(CapturedNode {
+            line: pos.row + 1,
+            column: pos.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+            text: ctx.text(node),
+            line_text: ctx.line_text(pos.row),
+            has_error: node.has_error(),
+        });
+    }
+    // Every capture of the match was suppressed.
+    let Some((row, column)) = start else {
+        return;
+    };
+    out.push(QueryMatch {
+        crate_path: ctx.crate_path.clone(),
+        krate: ctx.krate.clone(),
+        target: ctx.target.clone(),
+        file: ctx.file.clone(),
+        module_path: ctx.source.module_path.clone(),
+        line: row + 1,
+        column: column + 1,
+        pattern_index: m.pattern_index,
+        captures,
+    });
+}
+
+pub(crate) fn push_captures(
+    ctx: &FileContext,
+    m: &tree_sitter::QueryMatch,
+    capture_names: &[&str],
+    out: &mut Vec<Capture>,
+) {
+    for cap in m.captures.iter() {
+        let node = cap.node;
+        let pos = node.start_position();
+        let end = node.end_position();
+        let name = capture_names[cap.index as usize];
+        if ctx.suppressions.suppresses(name, pos.row + 1) {
+            continue;
+        }
+        out.push(Capture {
+            crate_path: ctx.crate_path.clone(),
+            krate: ctx.krate.clone(),
+            target: ctx.target.clone(),
+            file: ctx.file.clone(),
+            module_path: ctx.source.module_path.clone(),
+            line: pos.row + 1,
+            column: pos.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+            name: name.to_string(),
+            text: ctx.text(node),
+            line_text: ctx.line_text(pos.row),
+            has_error: node.has_error(),
+        });
+    }
+}
+
+/// Order of captures within one file.
+pub(crate) fn capture_order(a: &Capture, b: &Capture) -> std::cmp::Ordering {
+    a.line
+        .cmp(&b.line)
+        .then_with(|| a.column.cmp(&b.column))
+        .then_with(|| a.name.cmp(&b.name))
+        .then_with(|| a.text.cmp(&b.text))
+}
+
+/// Grouping key: the package name when the manifest has one, with the path as a tie-breaker for
+/// same-named packages (and as the only key for virtual manifests or files outside any crate).
+fn crate_key<'a>(krate: &'a Option<CrateInfo>, crate_path: &'a str) -> (&'a str, &'a str) {
+    (krate.as_ref().map_or("", |k| k.name.as_str()), crate_path)
+}
+
+/// Split items already sorted by crate into one contiguous run per crate.
+fn runs_by_crate<T>(items: Vec<T>, crate_path: impl Fn(&T) -> &String) -> Vec<Vec<T>> {
+    let mut runs: Vec<Vec<T>> = Vec::new();
+    for item in items {
+        match runs.last_mut() {
+            Some(run) if crate_path(&run[0]) == crate_path(&item) => run.push(item),
+            _ => runs.push(vec![item]),
+        }
+    }
+    runs
+}
+
+/// Keep only captures from files attributed to one of `kinds`, dropping crates left empty.
+pub fn retain_target_kinds(crates: &mut Vec<CrateCaptures>, kinds: &[TargetKind]) {
+    for c in crates.iter_mut() {
+        c.captures
+            .retain(|cap| cap.target.as_ref().is_some_and(|t| kinds.contains(&t.kind)));
+    }
+    crates.retain(|c| !c.captures.is_empty());
+}
+
+/// [`retain_target_kinds`] for match-grouped results.
+pub fn retain_match_target_kinds(crates: &mut Vec<CrateMatches>, kinds: &[TargetKind]) {
+    for c in crates.iter_mut() {
+        c.matches
+            .retain(|m| m.target.as_ref().is_some_and(|t| kinds.contains(&t.kind)));
+    }
+    crates.retain(|c| !c.matches.is_empty());
 }
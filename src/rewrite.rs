@@ -0,0 +1,481 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+use tree_sitter::{Node, Query, QueryMatch, Tree};
+
+use crate::check::syntax_errors;
+use crate::error::{ArbolError, Result};
+use crate::fs::{parse_src_lang, relative_to_root, SourceFile};
+use crate::query::{compile_query, for_each_match, with_parsed_file, CompiledQuery, FileContext};
+use crate::types::{
+    Edit, EditConflict, FileDiagnostic, FileErrorKind, FileRewrite, RejectedEdit, RewriteOutput, SyntaxError,
+};
+use crate::workspace::discover_crates;
+
+/// Lines of unchanged context around each hunk of [`unified_diff`].
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Capture(String),
+}
+
+/// Replacement text with `{{name}}` placeholders. A placeholder expands to the source text of that
+/// capture; when a quantified capture bound several nodes (`{{args}}` for `((_) @args (",")?)+`) it expands to
+/// the span from the first node to the last, separators included. Optional captures that bound
+/// nothing expand to the empty string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = src;
+        while let Some(open) = rest.find("{{") {
+            literal.push_str(&rest[..open]);
+            let after = &rest[open + 2..];
+            let name = after.find("}}").map(|close| (close, after[..close].trim()));
+            match name {
+                Some((close, name)) if is_capture_name(name) => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Capture(name.to_string()));
+                    rest = &after[close + 2..];
+                }
+                _ => {
+                    literal.push_str("{{");
+                    rest = after;
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Self { parts }
+    }
+
+    /// Capture names the template refers to, in order of first use.
+    pub fn captures(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for part in &self.parts {
+            if let Part::Capture(name) = part
+                && !names.contains(&name.as_str())
+            {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    fn render<'a>(&self, lookup: impl Fn(&str) -> &'a str) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(text) => text.as_str(),
+                Part::Capture(name) => lookup(name),
+            })
+            .collect()
+    }
+}
+
+fn is_capture_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// What to rewrite: a query, the template each match is replaced with, and optionally the capture
+/// whose node is replaced (by default the outermost capture of the match).
+pub struct RewriteSpec<'a> {
+    pub query: &'a str,
+    pub template: &'a Template,
+    pub capture: Option<&'a str>,
+}
+
+type FileResult = std::result::Result<(FileRewrite, Vec<EditConflict>, Vec<RejectedEdit>), FileDiagnostic>;
+
+struct CompiledRewrite {
+    compiled: CompiledQuery,
+    target: RewriteTarget,
+}
+
+/// A template checked against a compiled query, with the capture index it replaces.
+#[derive(Debug, Clone)]
+pub(crate) struct RewriteTarget {
+    template: Template,
+    capture: Option<u32>,
+}
+
+/// Compute the edits `spec` makes to each of `files`. Nothing is written; see [`apply_rewrites`].
+/// Per file, edits are kept in source order and an edit overlapping an earlier one is dropped and
+/// reported as an [`EditConflict`]; identical edits produced by several patterns are merged. The
+/// rewritten source is parsed again and edits introducing syntax errors are dropped as well, see
+/// [`RejectedEdit`].
+pub fn rewrite_sources(
+    lang: &tree_sitter::Language,
+    root: &Path,
+    files: &[SourceFile],
+    spec: &RewriteSpec,
+) -> Result<RewriteOutput> {
+    let crate_roots = discover_crates(root)?;
+    let rewrite = compile_rewrite(lang, spec)?;
+
+    let results: Vec<FileResult> = files
+        .par_iter()
+        .map(|source| {
+            with_parsed_file(lang, source, &crate_roots, false, |ctx, tree| {
+                let mut edits = Vec::new();
+                for_each_match(ctx, tree, &rewrite.compiled, |m| {
+                    edits.extend(edit_for(ctx, &rewrite.compiled.query, &rewrite.target, m))
+                });
+                let (edits, conflicts) = resolve_overlaps(edits);
+                let (edits, rejected) = validate_edits(lang, ctx, tree, edits);
+                let rewritten = apply_edits(ctx.src, &edits);
+                let file = FileRewrite {
+                    file: ctx.file.clone(),
+                    edits,
+                    source: ctx.src.to_string(),
+                    rewritten,
+                };
+                (file, conflicts, rejected)
+            })
+        })
+        .collect();
+
+    let mut output = RewriteOutput::default();
+    for r in results {
+        match r {
+            Ok((file, mut conflicts, mut rejected)) => {
+                if !file.edits.is_empty() {
+                    output.files.push(file);
+                }
+                output.conflicts.append(&mut conflicts);
+                output.rejected.append(&mut rejected);
+            }
+            Err(d) => output.diagnostics.push(d),
+        }
+    }
+    output.files.sort_by(|a, b| a.file.cmp(&b.file));
+    output
+        .conflicts
+        .sort_by(|a, b| (&a.dropped.file, a.dropped.start_byte).cmp(&(&b.dropped.file, b.dropped.start_byte)));
+    output
+        .rejected
+        .sort_by(|a, b| (&a.edit.file, a.edit.start_byte).cmp(&(&b.edit.file, b.edit.start_byte)));
+    output.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(output)
+}
+
+fn compile_rewrite(lang: &tree_sitter::Language, spec: &RewriteSpec) -> Result<CompiledRewrite> {
+    let compiled = compile_query(lang, spec.query)?;
+    let target = rewrite_target(&compiled.query, spec.template, spec.capture)?;
+    Ok(CompiledRewrite { compiled, target })
+}
+
+/// Check that `template` only refers to captures of `query` and resolve the replaced `capture`.
+pub(crate) fn rewrite_target(query: &Query, template: &Template, capture: Option<&str>) -> Result<RewriteTarget> {
+    let names = query.capture_names();
+    let unknown: Vec<String> = template
+        .captures()
+        .into_iter()
+        .filter(|name| !names.contains(name))
+        .map(|name| format!("`{{{{{name}}}}}`"))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(ArbolError::InvalidRewrite(format!(
+            "template refers to captures missing from the query: {}",
+            unknown.join(", ")
+        )));
+    }
+    let capture =
+        match capture {
+            Some(name) => Some(query.capture_index_for_name(name).ok_or_else(|| {
+                ArbolError::InvalidRewrite(format!("capture `@{name}` does not appear in the query"))
+            })?),
+            None => None,
+        };
+    Ok(RewriteTarget {
+        template: template.clone(),
+        capture,
+    })
+}
+
+/// The edit `target` makes for match `m` of `query`.
+pub(crate) fn edit_for(ctx: &FileContext, query: &Query, target: &RewriteTarget, m: &QueryMatch) -> Option<Edit> {
+    let node = match target.capture {
+        Some(i) => m.nodes_for_capture_index(i).next()?,
+        None => m
+            .captures
+            .iter()
+            .map(|c| c.node)
+            .min_by_key(|n| (n.start_byte(), std::cmp::Reverse(n.end_byte())))?,
+    };
+    let replacement = target.template.render(|name| {
+        let nodes: Vec<Node> = query
+            .capture_index_for_name(name)
+            .map(|i| m.nodes_for_capture_index(i).collect())
+            .unwrap_or_default();
+        let start = nodes.iter().map(Node::start_byte).min();
+        let end = nodes.iter().map(Node::end_byte).max();
+        match (start, end) {
+            (Some(start), Some(end)) => ctx.src.get(start..end).unwrap_or(""),
+            _ => "",
+        }
+    });
+    let (start, end) = (node.start_position(), node.end_position());
+    Some(Edit {
+        file: ctx.file.clone(),
+        start_byte: node.start_byte(),
+        end_byte: node.end_byte(),
+        line: start.row + 1,
+        column: start.column + 1,
+        end_line: end.row + 1,
+        end_column: end.column + 1,
+        original: ctx.text(node),
+        replacement,
+    })
+}
+
+/// Sort `edits` by position, merge duplicates and drop those overlapping an earlier kept edit.
+pub(crate) fn resolve_overlaps(mut edits: Vec<Edit>) -> (Vec<Edit>, Vec<EditConflict>) {
+    edits.sort_by_key(|e| (e.start_byte, std::cmp::Reverse(e.end_byte)));
+    edits.dedup();
+    let mut kept: Vec<Edit> = Vec::new();
+    let mut conflicts = Vec::new();
+    for edit in edits {
+        match kept.last() {
+            // Two insertions at the same offset would overlap as well: their order is ambiguous.
+            Some(prev) if edit.start_byte < prev.end_byte || edit.start_byte == prev.start_byte => {
+                conflicts.push(EditConflict {
+                    dropped: edit,
+                    kept: prev.clone(),
+                })
+            }
+            _ => kept.push(edit),
+        }
+    }
+    (kept, conflicts)
+}
+
+/// Drop the edits whose result has `ERROR` / `MISSING` nodes the original tree did not have. When all
+/// edits together break the file, each is tried on its own against the original to find the culprits;
+/// if the remaining edits still break it in combination, they are all rejected. `edits` must be sorted
+/// and non-overlapping.
+pub(crate) fn validate_edits(
+    lang: &tree_sitter::Language,
+    ctx: &FileContext,
+    tree: &Tree,
+    edits: Vec<Edit>,
+) -> (Vec<Edit>, Vec<RejectedEdit>) {
+    if edits.is_empty() {
+        return (edits, Vec::new());
+    }
+    let original = syntax_errors(tree, ctx.src, &ctx.file);
+    let introduced = |edits: &[Edit]| new_syntax_errors(lang, &apply_edits(ctx.src, edits), &ctx.file, &original);
+    if introduced(&edits).is_some_and(|errors| errors.is_empty()) {
+        return (edits, Vec::new());
+    }
+
+    let mut kept = Vec::new();
+    let mut rejected = Vec::new();
+    for edit in edits {
+        match introduced(std::slice::from_ref(&edit)) {
+            Some(errors) if errors.is_empty() => kept.push(edit),
+            errors => rejected.push(RejectedEdit {
+                edit,
+                errors: errors.unwrap_or_default(),
+            }),
+        }
+    }
+    match introduced(&kept) {
+        Some(errors) if errors.is_empty() => {}
+        errors => {
+            let errors = errors.unwrap_or_default();
+            rejected.extend(kept.drain(..).map(|edit| RejectedEdit {
+                edit,
+                errors: errors.clone(),
+            }));
+        }
+    }
+    (kept, rejected)
+}
+
+/// Syntax errors of `src` beyond those in `original`, compared by kind and message since positions
+/// shift with the edits; `None` when `src` could not be parsed at all.
+fn new_syntax_errors(
+    lang: &tree_sitter::Language,
+    src: &str,
+    file: &str,
+    original: &[SyntaxError],
+) -> Option<Vec<SyntaxError>> {
+    let tree = parse_src_lang(lang, src).ok()?;
+    let mut known: Vec<&SyntaxError> = original.iter().collect();
+    let errors = syntax_errors(&tree, src, file)
+        .into_iter()
+        .filter(
+            |e| match known.iter().position(|k| k.kind == e.kind && k.message == e.message) {
+                Some(i) => {
+                    known.swap_remove(i);
+                    false
+                }
+                None => true,
+            },
+        )
+        .collect();
+    Some(errors)
+}
+
+/// Apply sorted, non-overlapping `edits` to `src`.
+pub fn apply_edits(src: &str, edits: &[Edit]) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut pos = 0;
+    for edit in edits {
+        out.push_str(&src[pos..edit.start_byte]);
+        out.push_str(&edit.replacement);
+        pos = edit.end_byte;
+    }
+    out.push_str(&src[pos..]);
+    out
+}
+
+/// Write every rewritten file back to disk. Files whose content changed since they were read are left
+/// alone and moved from `files` to `diagnostics`. All other files are first written to temporary files
+/// next to them and only then renamed over the originals, so a failed write never leaves a truncated
+/// or half-rewritten set of files behind.
+pub fn apply_rewrites(output: &mut RewriteOutput) -> Result<()> {
+    let (files, modified): (Vec<_>, Vec<_>) = std::mem::take(&mut output.files)
+        .into_iter()
+        .partition(|f| std::fs::read_to_string(&f.file).is_ok_and(|src| src == f.source));
+    output.diagnostics.extend(modified.into_iter().map(|f| FileDiagnostic {
+        file: f.file,
+        kind: FileErrorKind::Modified,
+        reason: "changed on disk since it was read, not rewritten".into(),
+    }));
+    output.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    output.files = files;
+
+    let mut staged = Vec::new();
+    for file in &output.files {
+        let tmp = format!("{}.{}.arbol.tmp", file.file, std::process::id());
+        let written = std::fs::write(&tmp, &file.rewritten)
+            .and_then(|()| std::fs::set_permissions(&tmp, std::fs::metadata(&file.file)?.permissions()));
+        if let Err(e) = written {
+            for tmp in staged.iter().chain(std::iter::once(&tmp)) {
+                let _ = std::fs::remove_file(tmp);
+            }
+            return Err(e.into());
+        }
+        staged.push(tmp);
+    }
+    for (tmp, file) in staged.iter().zip(&output.files) {
+        std::fs::rename(tmp, &file.file)?;
+    }
+    Ok(())
+}
+
+/// A unified diff (`diff -u` style, [`DIFF_CONTEXT`] lines of context) of the edits to one file, with
+/// paths shown relative to `root`. Hunks come straight from the edit ranges, so no line matching is
+/// involved.
+pub fn unified_diff(file: &FileRewrite, root: &Path) -> String {
+    let name = relative_to_root(&file.file, root).unwrap_or_else(|| file.file.clone());
+    let src = file.source.as_str();
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+        .filter(|&i| i < src.len() || i == 0)
+        .collect();
+    let line_of = |byte: usize| line_starts.partition_point(|&s| s <= byte).saturating_sub(1);
+    let line_end = |line: usize| line_starts.get(line + 1).copied().unwrap_or(src.len());
+
+    // Changed line blocks: edits on the same or adjacent lines share one block.
+    let mut blocks: Vec<(usize, usize, Vec<&Edit>)> = Vec::new();
+    for edit in &file.edits {
+        let first = line_of(edit.start_byte);
+        let last = line_of(edit.end_byte.max(edit.start_byte + 1) - 1).max(first);
+        match blocks.last_mut() {
+            Some((_, end, edits)) if first <= *end + 1 => {
+                *end = (*end).max(last);
+                edits.push(edit);
+            }
+            _ => blocks.push((first, last, vec![edit])),
+        }
+    }
+
+    let mut out = format!("--- a/{name}\n+++ b/{name}\n");
+    let mut delta: isize = 0;
+    let mut i = 0;
+    while i < blocks.len() {
+        // Blocks whose context would touch go in the same hunk.
+        let mut j = i;
+        while j + 1 < blocks.len() && blocks[j + 1].0 <= blocks[j].1 + 2 * DIFF_CONTEXT + 1 {
+            j += 1;
+        }
+        let hunk_start = blocks[i].0.saturating_sub(DIFF_CONTEXT);
+        let hunk_end = (blocks[j].1 + DIFF_CONTEXT).min(line_starts.len() - 1);
+        let mut body = String::new();
+        let (mut old_count, mut new_count) = (0, 0);
+        let mut line = hunk_start;
+        for (first, last, edits) in &blocks[i..=j] {
+            for l in line..*first {
+                push_diff_line(&mut body, ' ', &src[line_starts[l]..line_end(l)]);
+                old_count += 1;
+                new_count += 1;
+            }
+            let (from, to) = (line_starts[*first], line_end(*last));
+            for l in *first..=*last {
+                push_diff_line(&mut body, '-', &src[line_starts[l]..line_end(l)]);
+                old_count += 1;
+            }
+            let mut replaced = String::new();
+            let mut pos = from;
+            for edit in edits {
+                replaced.push_str(&src[pos..edit.start_byte]);
+                replaced.push_str(&edit.replacement);
+                pos = edit.end_byte;
+            }
+            replaced.push_str(&src[pos..to]);
+            for new_line in replaced.split_inclusive('\n') {
+                push_diff_line(&mut body, '+', new_line);
+                new_count += 1;
+            }
+            line = last + 1;
+        }
+        for l in line..=hunk_end {
+            push_diff_line(&mut body, ' ', &src[line_starts[l]..line_end(l)]);
+            old_count += 1;
+            new_count += 1;
+        }
+        let new_start = (hunk_start as isize + delta) as usize;
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n{body}",
+            hunk_range(hunk_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        delta += new_count as isize - old_count as isize;
+        i = j + 1;
+    }
+    out
+}
+
+/// `start,count` with a 1-based start, or the line before the range when it is empty.
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{start},0")
+    } else {
+        format!("{},{count}", start + 1)
+    }
+}
+
+fn push_diff_line(out: &mut String, tag: char, line: &str) {
+    out.push(tag);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
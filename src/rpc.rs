@@ -0,0 +1,191 @@
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Result;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Server-defined: the query in the request did not compile; `data` holds the diagnostic.
+pub const QUERY_COMPILE_ERROR: i64 = -32001;
+
+/// Largest `Content-Length` accepted by [`read_framed_message`]; a bigger header is malformed input,
+/// not a reason to allocate that much.
+pub const MAX_FRAMED_MESSAGE: usize = 64 * 1024 * 1024;
+
+/// A JSON-RPC 2.0 request, or a notification when it has no `id`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Response {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(e: impl std::fmt::Display) -> Self {
+        Self::new(INVALID_PARAMS, format!("invalid params: {e}"))
+    }
+}
+
+impl Response {
+    pub fn new(id: Value, outcome: std::result::Result<Value, RpcError>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            outcome: match outcome {
+                Ok(v) => Outcome::Result(v),
+                Err(e) => Outcome::Error(e),
+            },
+        }
+    }
+}
+
+/// A notification sent to the client.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub jsonrpc: &'static str,
+    pub method: &'static str,
+    pub params: Value,
+}
+
+impl Notification {
+    pub fn new(method: &'static str, params: Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            method,
+            params,
+        }
+    }
+}
+
+/// Decode a request's `params`, answering malformed ones with `invalid params`.
+pub fn params<T: serde::de::DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    // Methods without parameters may omit them.
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+/// Next non-blank line of newline-delimited JSON; `None` at end of input.
+pub fn read_line_message(input: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            return Ok(Some(line));
+        }
+    }
+}
+
+/// Write `message` as one line of JSON and flush it.
+pub fn write_line_message(output: &mut impl Write, message: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *output, message)?;
+    output.write_all(b"\n")?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Next message framed by a `Content-Length` header, as the Language Server Protocol sends them;
+/// `None` at end of input. Other headers are ignored. A length above [`MAX_FRAMED_MESSAGE`] is an
+/// `InvalidData` error.
+pub fn read_framed_message(input: &mut impl BufRead) -> Result<Option<String>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            match length {
+                Some(length) => {
+                    let mut body = vec![0; length];
+                    input.read_exact(&mut body)?;
+                    return String::from_utf8(body).map(Some).map_err(|e| invalid_data(e).into());
+                }
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            let value = value.trim().parse::<usize>().map_err(invalid_data)?;
+            if value > MAX_FRAMED_MESSAGE {
+                return Err(invalid_data(format!(
+                    "Content-Length {value} exceeds the {MAX_FRAMED_MESSAGE} byte limit"
+                ))
+                .into());
+            }
+            length = Some(value);
+        }
+    }
+}
+
+/// Write `message` with a `Content-Length` header and flush it.
+pub fn write_framed_message(output: &mut impl Write, message: &impl Serialize) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n", body.len())?;
+    output.write_all(&body)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("malformed message: {e}"))
+}
+
+/// Decode one message, answering undecodable ones with the JSON-RPC error response to send back.
+pub fn decode(message: &str) -> std::result::Result<Request, Response> {
+    let value: Value = serde_json::from_str(message).map_err(|e| {
+        Response::new(
+            Value::Null,
+            Err(RpcError::new(PARSE_ERROR, format!("parse error: {e}"))),
+        )
+    })?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| Response::new(id, Err(RpcError::new(INVALID_REQUEST, format!("invalid request: {e}")))))
+}
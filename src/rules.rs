@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::error::{ArbolError, Result};
+
+/// How serious a finding is. Maps onto SARIF levels and compiler-style diagnostic labels.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Note,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+
+    /// A misspelled severity is an error rather than a silent fallback to the default, which would
+    /// quietly downgrade an `error` rule.
+    fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "note" | "info" | "hint" => Ok(Self::Note),
+            "warning" | "warn" => Ok(Self::Warning),
+            "error" | "deny" => Ok(Self::Error),
+            _ => Err(ArbolError::InvalidRule(format!(
+                "unknown severity `{s}` (expected note, warning or error)"
+            ))),
+        }
+    }
+}
+
+/// Metadata declared in the leading comment block of a `.scm` query, one `; key: value` line each:
+///
+/// ```scm
+/// ; id: pub-fn-too-long
+/// ; message: `{fn.name}` is longer than 50 lines
+/// ; severity: warning
+/// ; help: https://example.com/rules/pub-fn-too-long
+/// ; fix: split it into smaller functions
+/// ; capture: fn.name
+/// ((function_item name: (identifier) @fn.name) @fn (#line-count>? @fn 50))
+/// ```
+///
+/// `; rewrite: <template>` attaches a suggested replacement, in `arbol rewrite` template syntax, for
+/// the capture named by `; rewrite-capture:` (the outermost capture by default). Editors offer it as
+/// a quick fix.
+///
+/// Unknown keys and comment lines without a `key:` prefix are ignored, so ordinary comments can live
+/// in the same block. An unknown `severity` value is an error.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueryMetadata {
+    pub id: Option<String>,
+    /// Message template; see [`render_message`].
+    pub message: Option<String>,
+    pub severity: Option<Severity>,
+    pub help_uri: Option<String>,
+    /// Hint on how to resolve a finding.
+    pub fix: Option<String>,
+    /// Capture a finding is reported at; defaults to the earliest capture of the match.
+    pub capture: Option<String>,
+    /// Replacement template for the suggested fix.
+    pub rewrite: Option<String>,
+    /// Capture the suggested fix replaces; defaults to the outermost capture of the match.
+    pub rewrite_capture: Option<String>,
+}
+
+impl QueryMetadata {
+    pub fn parse(query_src: &str) -> Result<Self> {
+        let mut meta = Self::default();
+        for line in query_src.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+            let Some(comment) = line.strip_prefix(';') else {
+                break;
+            };
+            let Some((key, value)) = comment.trim_start_matches(';').split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "id" => meta.id = Some(value),
+                "message" => meta.message = Some(value),
+                "severity" | "level" => meta.severity = Some(Severity::parse(&value)?),
+                "help" | "help_uri" | "help-uri" => meta.help_uri = Some(value),
+                "fix" | "hint" => meta.fix = Some(value),
+                "capture" => meta.capture = Some(value.trim_start_matches('@').to_string()),
+                "rewrite" => meta.rewrite = Some(value),
+                "rewrite-capture" | "rewrite_capture" => {
+                    meta.rewrite_capture = Some(value.trim_start_matches('@').to_string())
+                }
+                _ => {}
+            }
+        }
+        Ok(meta)
+    }
+}
+
+/// Fill `{name}` placeholders in `template` from `lookup`; placeholders it does not know are kept
+/// verbatim.
+pub fn render_message<'a>(template: &str, lookup: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after
+            .find('}')
+            .and_then(|close| Some((close, lookup(&after[..close])?)))
+        {
+            Some((close, value)) => {
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A lint rule: a `.scm` query and the metadata from its header comments. The id defaults to the
+/// file stem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub id: String,
+    pub path: PathBuf,
+    pub query: String,
+    pub message: Option<String>,
+    pub severity: Severity,
+    pub help_uri: Option<String>,
+    pub fix: Option<String>,
+    pub capture: Option<String>,
+    pub rewrite: Option<String>,
+    pub rewrite_capture: Option<String>,
+}
+
+impl Rule {
+    /// Fails with [`ArbolError::InvalidRule`] naming `path` when the metadata is invalid.
+    pub fn from_source(path: &Path, query: String) -> Result<Self> {
+        let meta = QueryMetadata::parse(&query).map_err(|e| match e {
+            ArbolError::InvalidRule(msg) => ArbolError::InvalidRule(format!("{}: {msg}", path.display())),
+            e => e,
+        })?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+        Ok(Self {
+            id: meta.id.or(stem).unwrap_or_default(),
+            path: path.to_path_buf(),
+            query,
+            message: meta.message,
+            severity: meta.severity.unwrap_or_default(),
+            help_uri: meta.help_uri,
+            fix: meta.fix,
+            capture: meta.capture,
+            rewrite: meta.rewrite,
+            rewrite_capture: meta.rewrite_capture,
+        })
+    }
+}
+
+/// Load rules from `.scm` files and directories of them (searched recursively, in path order).
+/// Rule ids must be unique across the whole set.
+pub fn load_rules(paths: &[PathBuf]) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for path in paths {
+        if !path.exists() {
+            return Err(ArbolError::InvalidRule(format!(
+                "{}: no such file or directory",
+                path.display()
+            )));
+        }
+        let files: Vec<PathBuf> = if path.is_dir() {
+            WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file() && e.path().extension().and_then(|s| s.to_str()) == Some("scm"))
+                .map(|e| e.into_path())
+                .collect()
+        } else {
+            vec![path.clone()]
+        };
+        for file in files {
+            let src = std::fs::read_to_string(&file)?;
+            rules.push(Rule::from_source(&file, src)?);
+        }
+    }
+    let mut seen = HashSet::new();
+    for rule in &rules {
+        if !seen.insert(rule.id.as_str()) {
+            return Err(ArbolError::InvalidRule(format!(
+                "{}: duplicate rule id `{}`",
+                rule.path.display(),
+                rule.id
+            )));
+        }
+    }
+    Ok(rules)
+}
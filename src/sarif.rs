@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::fs::relative_to_root;
+use crate::rules::{render_message, QueryMetadata};
+use crate::types::{Capture, FileDiagnostic, QueryOutput};
+
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+pub const SARIF_VERSION: &str = "2.1.0";
+
+/// A SARIF 2.1.0 log with a single run. Only the subset of the format arbol produces is modelled.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<SarifRun>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SarifRun {
+    pub tool: SarifTool,
+    pub results: Vec<SarifResult>,
+    pub invocations: Vec<SarifInvocation>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SarifTool {
+    pub driver: SarifDriver,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifDriver {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub information_uri: &'static str,
+    pub rules: Vec<SarifRule>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifRule {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_description: Option<SarifMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub help_uri: Option<String>,
+    pub default_configuration: SarifConfiguration,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SarifConfiguration {
+    pub level: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub rule_index: usize,
+    pub level: &'static str,
+    pub message: SarifMessage,
+    pub locations: Vec<SarifLocation>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SarifMessage {
+    pub text: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifLocation {
+    pub physical_location: SarifPhysicalLocation,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifPhysicalLocation {
+    pub artifact_location: SarifArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<SarifRegion>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifArtifactLocation {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri_base_id: Option<&'static str>,
+}
+
+/// 1-based, with columns in UTF-16 code units and `end_column` pointing one past the last character as SARIF
+/// expects.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifRegion {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifInvocation {
+    pub execution_successful: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_execution_notifications: Vec<SarifNotification>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SarifNotification {
+    pub level: &'static str,
+    pub message: SarifMessage,
+    pub locations: Vec<SarifLocation>,
+}
+
+impl SarifLog {
+    pub fn new(rules: Vec<SarifRule>, results: Vec<SarifResult>, diagnostics: &[FileDiagnostic], root: &Path) -> Self {
+        let notifications = diagnostics
+            .iter()
+            .map(|d| SarifNotification {
+                level: "warning",
+                message: SarifMessage { text: d.reason.clone() },
+                locations: vec![location(&d.file, None, root)],
+            })
+            .collect();
+        Self {
+            schema: SARIF_SCHEMA,
+            version: SARIF_VERSION,
+            runs: vec![SarifRun {
+                tool: SarifTool {
+                    driver: SarifDriver {
+                        name: "arbol",
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: env!("CARGO_PKG_REPOSITORY"),
+                        rules,
+                    },
+                },
+                results,
+                invocations: vec![SarifInvocation {
+                    execution_successful: true,
+                    tool_execution_notifications: notifications,
+                }],
+            }],
+        }
+    }
+}
+
+/// Convert query captures to SARIF: one rule per capture name, one result per capture. `meta`
+/// supplies the message template (`{name}` is the capture name, `{text}` and `{<capture name>}`
+/// its text), severity and help URI shared by every rule of the query.
+pub fn query_to_sarif(output: &QueryOutput, meta: &QueryMetadata, root: &Path) -> SarifLog {
+    let level = meta.severity.unwrap_or_default().as_str();
+    let mut rules: Vec<SarifRule> = Vec::new();
+    let mut results = Vec::new();
+    let mut sources: HashMap<&str, Option<String>> = HashMap::new();
+    for cap in output.crates.iter().flat_map(|c| &c.captures) {
+        let rule_index = match rules.iter().position(|r| r.id == cap.name) {
+            Some(i) => i,
+            None => {
+                rules.push(SarifRule {
+                    id: cap.name.clone(),
+                    short_description: meta.message.clone().map(|text| SarifMessage { text }),
+                    help_uri: meta.help_uri.clone(),
+                    default_configuration: SarifConfiguration { level },
+                });
+                rules.len() - 1
+            }
+        };
+        let source = sources
+            .entry(cap.file.as_str())
+            .or_insert_with(|| std::fs::read_to_string(&cap.file).ok())
+            .as_deref();
+        results.push(SarifResult {
+            rule_id: cap.name.clone(),
+            rule_index,
+            level,
+            message: SarifMessage {
+                text: capture_message(cap, meta),
+            },
+            locations: vec![location(&cap.file, Some(capture_region(cap, source)), root)],
+        });
+    }
+    SarifLog::new(rules, results, &output.diagnostics, root)
+}
+
+fn capture_message(cap: &Capture, meta: &QueryMetadata) -> String {
+    match &meta.message {
+        Some(template) => render_message(template, |key| match key {
+            "name" => Some(cap.name.as_str()),
+            "text" => Some(cap.text.as_str()),
+            k if k == cap.name => Some(cap.text.as_str()),
+            _ => None,
+        }),
+        None => format!("{}: `{}`", cap.name, cap.text),
+    }
+}
+
+/// Captures carry byte columns; SARIF's default `columnKind` counts UTF-16 code units, so columns are
+/// converted against `source`. Without the source the byte columns are kept.
+fn capture_region(cap: &Capture, source: Option<&str>) -> SarifRegion {
+    let line = |row: usize| source.and_then(|s| s.split('\n').nth(row - 1));
+    SarifRegion {
+        start_line: cap.line,
+        start_column: utf16_column(line(cap.line), cap.column),
+        end_line: cap.end_line,
+        end_column: utf16_column(line(cap.end_line), cap.end_column),
+    }
+}
+
+/// 1-based UTF-16 column of the 1-based byte `column` in `line`.
+fn utf16_column(line: Option<&str>, column: usize) -> usize {
+    match line.and_then(|l| l.get(..column - 1)) {
+        Some(prefix) => prefix.encode_utf16().count() + 1,
+        None => column,
+    }
+}
+
+/// SARIF location for `file`: relative to the scan root (`%SRCROOT%`) when below it, otherwise an
+/// absolute `file://` URI. Both are percent-encoded.
+pub fn location(file: &str, region: Option<SarifRegion>, root: &Path) -> SarifLocation {
+    let artifact_location = match relative_to_root(file, root) {
+        Some(rel) => SarifArtifactLocation {
+            uri: percent_encode(&rel, b"/"),
+            uri_base_id: Some("%SRCROOT%"),
+        },
+        None => SarifArtifactLocation {
+            uri: file_uri(Path::new(file)),
+            uri_base_id: None,
+        },
+    };
+    SarifLocation {
+        physical_location: SarifPhysicalLocation {
+            artifact_location,
+            region,
+        },
+    }
+}
+
+fn file_uri(path: &Path) -> String {
+    let abs = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut path = abs.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        path.insert(0, '/');
+    }
+    format!("file://{}", percent_encode(&path, b"/:"))
+}
+
+/// Percent-encode every byte outside RFC 3986's unreserved set and `keep`.
+fn percent_encode(s: &str, keep: &[u8]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) || keep.contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
@@ -0,0 +1,148 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{ArbolError, Result};
+use crate::index::SourceIndex;
+use crate::rpc::{self, RpcError, METHOD_NOT_FOUND, QUERY_COMPILE_ERROR};
+use crate::types::{FileChange, FileDiagnostic};
+
+/// `arbol serve`: JSON-RPC 2.0 over newline-delimited JSON, answering requests from a
+/// [`SourceIndex`] so that repeated queries neither walk the workspace nor parse files again.
+///
+/// Methods: `query`, `dumpFile`, `outline`, `fileChanged` and `shutdown`. Requests without an `id`
+/// are notifications and get no response.
+pub struct Server {
+    index: SourceIndex,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryParams {
+    query: String,
+    #[serde(default)]
+    context: bool,
+    #[serde(default)]
+    matches: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DumpFileParams {
+    path: PathBuf,
+    #[serde(default)]
+    with_source: bool,
+    max_depth: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct PathParams {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct FileChangedParams {
+    path: PathBuf,
+    /// New contents; read from disk when omitted.
+    text: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FileChangedResult {
+    change: Option<FileChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagnostic: Option<FileDiagnostic>,
+}
+
+impl Server {
+    pub fn new(index: SourceIndex) -> Self {
+        Self { index }
+    }
+
+    /// Answer requests from `input` until `shutdown` or end of input.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> Result<()> {
+        while let Some(message) = rpc::read_line_message(&mut input)? {
+            let request = match rpc::decode(&message) {
+                Ok(request) => request,
+                Err(response) => {
+                    rpc::write_line_message(&mut output, &response)?;
+                    continue;
+                }
+            };
+            let shutdown = request.method == "shutdown";
+            let outcome = self.handle(&request.method, request.params);
+            if let Some(id) = request.id {
+                rpc::write_line_message(&mut output, &rpc::Response::new(id, outcome))?;
+            }
+            if shutdown {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one method.
+    pub fn handle(&mut self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        match method {
+            "query" => {
+                let p: QueryParams = rpc::params(params)?;
+                let result = if p.matches {
+                    self.index.query_matches(&p.query, p.context).map(to_value)
+                } else {
+                    self.index.query(&p.query, p.context).map(to_value)
+                };
+                result.map_err(query_error)
+            }
+            "dumpFile" => {
+                let p: DumpFileParams = rpc::params(params)?;
+                let ast = self
+                    .index
+                    .dump(&p.path, p.with_source, p.max_depth.unwrap_or(usize::MAX))
+                    .ok_or_else(|| not_indexed(&p.path))?;
+                Ok(to_value(ast))
+            }
+            "outline" => {
+                let p: PathParams = rpc::params(params)?;
+                let outline = self.index.outline(&p.path).ok_or_else(|| not_indexed(&p.path))?;
+                Ok(to_value(outline))
+            }
+            "fileChanged" => {
+                let p: FileChangedParams = rpc::params(params)?;
+                let result = match self.index.update_file(&p.path, p.text) {
+                    Ok(change) => FileChangedResult {
+                        change: Some(change),
+                        diagnostic: None,
+                    },
+                    Err(d) => FileChangedResult {
+                        change: None,
+                        diagnostic: Some(d),
+                    },
+                };
+                Ok(to_value(result))
+            }
+            "shutdown" => Ok(Value::Null),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method not found: {method}"))),
+        }
+    }
+}
+
+fn to_value(v: impl Serialize) -> Value {
+    serde_json::to_value(v).unwrap_or(Value::Null)
+}
+
+fn not_indexed(path: &Path) -> RpcError {
+    RpcError::invalid_params(format!("file not indexed: {}", path.display()))
+}
+
+fn query_error(e: ArbolError) -> RpcError {
+    match e {
+        ArbolError::QueryCompile(diag) => RpcError {
+            code: QUERY_COMPILE_ERROR,
+            message: format!("Query compile error: {diag}"),
+            data: Some(to_value(&*diag)),
+        },
+        e => RpcError::invalid_params(e),
+    }
+}
@@ -0,0 +1,149 @@
+use std::cell::Cell;
+
+use tree_sitter::{Node, Tree};
+
+use crate::types::{Suppression, SuppressionScope};
+
+/// Comment marker silencing the listed rules on the next line of code.
+pub const IGNORE_NEXT_LINE: &str = "arbol-ignore";
+/// Comment marker silencing the listed rules in the whole file.
+pub const IGNORE_FILE: &str = "arbol-ignore-file";
+
+struct Entry {
+    suppression: Suppression,
+    /// 1-based line the suppression applies to; `None` for file-wide suppressions.
+    target_line: Option<usize>,
+    used: Cell<bool>,
+}
+
+/// The `// arbol-ignore: rule-a, rule-b` and `// arbol-ignore-file: rule` comments of one file.
+/// Anything after ` -- ` is a free-form reason. Ids are lint rule ids, or capture names for plain
+/// queries.
+#[derive(Default)]
+pub(crate) struct Suppressions {
+    entries: Vec<Entry>,
+}
+
+impl Suppressions {
+    pub(crate) fn parse(tree: &Tree, src: &str, file: &str) -> Self {
+        let mut out = Self::default();
+        // Cheap pre-check: most files have no suppressions, so skip the tree walk.
+        if !src.contains(IGNORE_NEXT_LINE) {
+            return out;
+        }
+        let lines: Vec<&str> = src.lines().collect();
+        let mut comments = Vec::new();
+        collect_comments(tree.root_node(), &mut comments);
+        for node in comments {
+            let Ok(text) = node.utf8_text(src.as_bytes()) else {
+                continue;
+            };
+            let body = if let Some(rest) = text.strip_prefix("//") {
+                rest
+            } else if let Some(rest) = text.strip_prefix("/*") {
+                rest.trim_end_matches("*/")
+            } else {
+                continue;
+            };
+            let body = body.trim();
+            let (scope, rest) = if let Some(rest) = body.strip_prefix(IGNORE_FILE) {
+                (SuppressionScope::File, rest)
+            } else if let Some(rest) = body.strip_prefix(IGNORE_NEXT_LINE) {
+                (SuppressionScope::NextLine, rest)
+            } else {
+                continue;
+            };
+            let Some(ids) = rest.trim_start().strip_prefix(':') else {
+                continue;
+            };
+            let ids = ids.split(" -- ").next().unwrap_or("");
+            let start = node.start_position();
+            let target_line = match scope {
+                SuppressionScope::File => None,
+                SuppressionScope::NextLine => Some(target_line(node, &lines)),
+            };
+            for rule in ids.split([',', ' ', '\t']).map(str::trim).filter(|s| !s.is_empty()) {
+                out.entries.push(Entry {
+                    suppression: Suppression {
+                        file: file.to_string(),
+                        line: start.row + 1,
+                        column: start.column + 1,
+                        rule: rule.to_string(),
+                        scope,
+                    },
+                    target_line,
+                    used: Cell::new(false),
+                });
+            }
+        }
+        out
+    }
+
+    /// Whether `rule` is suppressed at 1-based `line`; marks the matching suppressions as used.
+    pub(crate) fn suppresses(&self, rule: &str, line: usize) -> bool {
+        let mut hit = false;
+        for e in &self.entries {
+            if e.suppression.rule == rule && e.target_line.is_none_or(|l| l == line) {
+                e.used.set(true);
+                hit = true;
+            }
+        }
+        hit
+    }
+
+    /// Suppressions for one of the `active` rules that silenced nothing. Suppressions naming rules
+    /// that did not run are left alone, since another query or rule pack may need them.
+    pub(crate) fn unused(&self, active: &[&str]) -> Vec<Suppression> {
+        self.entries
+            .iter()
+            .filter(|e| !e.used.get() && active.contains(&e.suppression.rule.as_str()))
+            .map(|e| e.suppression.clone())
+            .collect()
+    }
+}
+
+fn collect_comments<'t>(node: Node<'t>, out: &mut Vec<Node<'t>>) {
+    if matches!(node.kind(), "line_comment" | "block_comment") {
+        out.push(node);
+        return;
+    }
+    for child in node.children(&mut node.walk()) {
+        collect_comments(child, out);
+    }
+}
+
+/// 1-based line an `arbol-ignore` comment applies to: where the next item, statement or expression
+/// after it starts, past further comments and attributes, so stacked suppressions and `#[derive(..)]`
+/// lines do not take the suppression themselves. Falls back to [`next_code_line`] when nothing follows
+/// the comment on a later line within its parent.
+fn target_line(comment: Node, lines: &[&str]) -> usize {
+    let end = comment.end_position().row;
+    let mut next = comment.next_named_sibling();
+    while let Some(n) = next {
+        if !matches!(
+            n.kind(),
+            "line_comment" | "block_comment" | "attribute_item" | "inner_attribute_item"
+        ) {
+            if n.start_position().row > end {
+                return n.start_position().row + 1;
+            }
+            break;
+        }
+        next = n.next_named_sibling();
+    }
+    next_code_line(lines, end)
+}
+
+/// 1-based number of the first line after 0-based `row` holding code rather than blanks, comments or
+/// attributes.
+fn next_code_line(lines: &[&str], row: usize) -> usize {
+    let mut next = row + 1;
+    while let Some(line) = lines.get(next) {
+        let t = line.trim_start();
+        if !t.is_empty() && !t.starts_with("//") && !t.starts_with("/*") && !t.starts_with("#[") {
+            break;
+        }
+        next += 1;
+    }
+    next + 1
+}
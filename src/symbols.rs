@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rayon::prelude::*;
+use tree_sitter::Node;
+
+use crate::error::Result;
+use crate::fs::{read_source, relative_to_root, SourceFile};
+use crate::outline::{item_kind, item_name};
+use crate::query::{with_parsed_file, FileContext};
+use crate::types::{FileDiagnostic, ItemKind, Symbol, SymbolKind, SymbolOutput};
+use crate::workspace::discover_crates;
+
+/// Every named item of `files` with its fully qualified path, sorted by file and position.
+///
+/// Paths start at the file's module path (from `--module-tree` discovery, otherwise inferred from the
+/// file's place in its Cargo target) and follow inline modules, traits and impls: a method of
+/// `impl Display for Config` in `src/config.rs` is `crate::config::Config::fmt`. Items of files
+/// outside every target get paths relative to the file. Function bodies are not descended into.
+pub fn symbols_sources(lang: &tree_sitter::Language, root: &Path, files: &[SourceFile]) -> Result<SymbolOutput> {
+    let crate_roots = discover_crates(root)?;
+    let results: Vec<std::result::Result<Vec<Symbol>, FileDiagnostic>> = files
+        .par_iter()
+        .map(|source| {
+            with_parsed_file(lang, source, &crate_roots, false, |ctx, tree| {
+                let mut out = Vec::new();
+                collect_symbols(ctx, tree.root_node(), ctx.module.as_deref(), false, &mut out);
+                out
+            })
+        })
+        .collect();
+
+    let mut output = SymbolOutput::default();
+    for r in results {
+        match r {
+            Ok(mut symbols) => output.symbols.append(&mut symbols),
+            Err(d) => output.diagnostics.push(d),
+        }
+    }
+    output
+        .symbols
+        .sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+    output.diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(output)
+}
+
+/// `associated` is set inside impls and traits, where functions are methods.
+fn collect_symbols(ctx: &FileContext, parent: Node, prefix: Option<&str>, associated: bool, out: &mut Vec<Symbol>) {
+    for node in parent.named_children(&mut parent.walk()) {
+        if node.kind() == "foreign_mod_item" {
+            if let Some(body) = node.child_by_field_name("body") {
+                collect_symbols(ctx, body, prefix, false, out);
+            }
+            continue;
+        }
+        let Some(item) = item_kind(node.kind()) else {
+            continue;
+        };
+        let body = node.child_by_field_name("body");
+        if item == ItemKind::Impl {
+            // Associated items are named after the implementing type, whichever trait they implement.
+            if let (Some(ty), Some(body)) = (node.child_by_field_name("type"), body) {
+                let path = qualify(prefix, &type_name(ty, ctx.src));
+                collect_symbols(ctx, body, Some(&path), true, out);
+            }
+            continue;
+        }
+        let Some(name) = item_name(node, ctx.src) else {
+            continue;
+        };
+        let path = qualify(prefix, &name);
+        if matches!(item, ItemKind::Mod | ItemKind::Trait)
+            && let Some(body) = body
+        {
+            collect_symbols(ctx, body, Some(&path), item == ItemKind::Trait, out);
+        }
+        let kind = match item {
+            ItemKind::Fn if associated => SymbolKind::Method,
+            ItemKind::Fn => SymbolKind::Fn,
+            ItemKind::Mod => SymbolKind::Mod,
+            ItemKind::Struct => SymbolKind::Struct,
+            ItemKind::Enum => SymbolKind::Enum,
+            ItemKind::Union => SymbolKind::Union,
+            ItemKind::Trait => SymbolKind::Trait,
+            ItemKind::Type => SymbolKind::Type,
+            ItemKind::Const => SymbolKind::Const,
+            ItemKind::Static => SymbolKind::Static,
+            ItemKind::Macro => SymbolKind::Macro,
+            ItemKind::Impl => continue,
+        };
+        let visibility = node
+            .named_children(&mut node.walk())
+            .find(|c| c.kind() == "visibility_modifier")
+            .map(|v| collapse(ctx.text(v).as_str()));
+        let (start, end) = (node.start_position(), node.end_position());
+        out.push(Symbol {
+            path,
+            name,
+            kind,
+            visibility,
+            krate: ctx.krate.as_ref().map(|k| k.name.clone()),
+            file: ctx.file.clone(),
+            line: start.row + 1,
+            column: start.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+            doc: doc_summary(node, ctx.src),
+        });
+    }
+}
+
+fn qualify(prefix: Option<&str>, name: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}::{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// The name of an impl's self type without generics or references: `Config` for `&'a Config<T>`.
+pub(crate) fn type_name(ty: Node, src: &str) -> String {
+    match ty.kind() {
+        "generic_type" | "reference_type" | "pointer_type" => match ty.child_by_field_name("type") {
+            Some(inner) => type_name(inner, src),
+            None => collapse(ty.utf8_text(src.as_bytes()).unwrap_or("")),
+        },
+        _ => collapse(ty.utf8_text(src.as_bytes()).unwrap_or("")),
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// First non-empty line of the `///` or `/** */` comments right above `node`; attributes and plain
+/// comments in between are skipped.
+fn doc_summary(node: Node, src: &str) -> Option<String> {
+    let mut docs = Vec::new();
+    let mut prev = node.prev_sibling();
+    while let Some(p) = prev {
+        match p.kind() {
+            "attribute_item" => {}
+            "line_comment" | "block_comment" => docs.push(p.utf8_text(src.as_bytes()).ok()?),
+            _ => break,
+        }
+        prev = p.prev_sibling();
+    }
+    docs.iter().rev().find_map(|comment| {
+        if let Some(line) = comment.strip_prefix("///").filter(|l| !l.starts_with('/')) {
+            Some(line.trim()).filter(|l| !l.is_empty()).map(str::to_string)
+        } else if let Some(block) = comment.strip_prefix("/**").filter(|b| !b.starts_with('*')) {
+            block
+                .trim_end_matches("*/")
+                .lines()
+                .map(|l| l.trim().trim_start_matches('*').trim())
+                .find(|l| !l.is_empty())
+                .map(str::to_string)
+        } else {
+            None
+        }
+    })
+}
+
+/// Universal Ctags' one-letter kinds for Rust.
+fn ctags_kind(kind: SymbolKind) -> char {
+    match kind {
+        SymbolKind::Mod => 'n',
+        SymbolKind::Fn => 'f',
+        SymbolKind::Method => 'P',
+        SymbolKind::Struct | SymbolKind::Union => 's',
+        SymbolKind::Enum => 'g',
+        SymbolKind::Trait => 'i',
+        SymbolKind::Type => 't',
+        SymbolKind::Const => 'C',
+        SymbolKind::Static => 'v',
+        SymbolKind::Macro => 'M',
+    }
+}
+
+fn tag_file(file: &str, root: &Path) -> String {
+    relative_to_root(file, root).unwrap_or_else(|| file.to_string())
+}
+
+/// A sorted ctags file (extended format, line-number addresses) with paths relative to `root`, for
+/// a `tags` file at the root. The qualified path is kept in a `path:` field.
+pub fn render_ctags(symbols: &[Symbol], root: &Path) -> String {
+    let mut lines: Vec<String> = symbols
+        .iter()
+        .map(|s| {
+            format!(
+                "{}\t{}\t{};\"\t{}\tpath:{}",
+                s.name,
+                tag_file(&s.file, root),
+                s.line,
+                ctags_kind(s.kind),
+                s.path
+            )
+        })
+        .collect();
+    lines.sort();
+    let mut out = String::from(
+        "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+         !_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n\
+         !_TAG_PROGRAM_NAME\tarbol\t//\n",
+    );
+    out.push_str(&format!("!_TAG_PROGRAM_VERSION\t{}\t//\n", env!("CARGO_PKG_VERSION")));
+    for line in lines {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// An Emacs `TAGS` file with paths relative to `root`. Files are read again for the source line of each
+/// tag and its byte offset.
+pub fn render_etags(symbols: &[Symbol], root: &Path) -> Result<String> {
+    let mut by_file: BTreeMap<&str, Vec<&Symbol>> = BTreeMap::new();
+    for s in symbols {
+        by_file.entry(&s.file).or_default().push(s);
+    }
+    let mut out = String::new();
+    for (file, symbols) in by_file {
+        let src = read_source(Path::new(file))?;
+        let mut starts = vec![0];
+        starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        let mut section = String::new();
+        for s in symbols {
+            let offset = starts.get(s.line - 1).copied().unwrap_or(0);
+            let line = src[offset..].lines().next().unwrap_or("");
+            // The tag text runs up to the end of the name, as etags writes it.
+            let text = match line.find(&s.name) {
+                Some(i) => &line[..i + s.name.len()],
+                None => line.trim_end(),
+            };
+            section.push_str(&format!("{text}\x7f{}\x01{},{offset}\n", s.name, s.line));
+        }
+        out.push_str(&format!("\x0c\n{},{}\n{section}", tag_file(file, root), section.len()));
+    }
+    Ok(out)
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::api::{api_sources, diff_api};
+use crate::rust_language;
+use crate::types::{ApiItem, ApiKind};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn manifest(root: &Path) {
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='my-lib'\nversion='0.0.0'\nedition='2021'\n",
+    );
+}
+
+fn api(root: &Path) -> Vec<ApiItem> {
+    let output = api_sources(&rust_language(), root).unwrap();
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    output.items
+}
+
+#[test]
+fn api_lists_reachable_public_items_with_normalized_signatures() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    manifest(root);
+    write(
+        &root.join("src/lib.rs"),
+        r#"//! Crate docs.
+mod internal;
+pub mod config;
+pub use internal::{helper as run, Kind};
+pub use serde_json::Value;
+
+pub mod prelude {
+    pub use super::config::*;
+    pub use super::*;
+}
+
+#[doc(hidden)]
+pub fn hidden() {}
+pub(crate) fn private() {}
+
+#[macro_export]
+macro_rules! make {
+    () => {};
+}
+
+#[cfg(test)]
+mod tests {
+    pub fn t() {}
+}
+"#,
+    );
+    write(
+        &root.join("src/config.rs"),
+        r#"use std::fmt;
+
+/// Settings.
+pub struct Config<T: Clone = u8> {
+    pub name: String,
+    secret: T,
+}
+
+impl<T: Clone> Config<T> {
+    pub fn load(
+        path: &std::path::Path, // where from
+        retries: u32,
+    ) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        todo!()
+    }
+
+    fn internal(&self) {}
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+pub const LIMIT: usize = 10;
+
+pub trait Source {
+    type Item;
+    fn read(&self) -> &'static str;
+}
+"#,
+    );
+    write(
+        &root.join("src/internal.rs"),
+        "pub fn helper(x: &mut [u8; 4]) {}\npub fn unexported() {}\npub enum Kind {\n    A,\n    B(u8),\n    C { x: i32 },\n}\n",
+    );
+
+    let items = api(root);
+    let find = |path: &str| {
+        items
+            .iter()
+            .find(|i| i.path == path)
+            .map(|i| (i.kind, i.signature.as_str()))
+            .unwrap_or_else(|| panic!("{path} missing from {items:#?}"))
+    };
+    assert_eq!(
+        find("my_lib::config::Config::load"),
+        (
+            ApiKind::Method,
+            "pub fn load(path: &std::path::Path, retries: u32) -> Result<Self, String> where T: Default"
+        )
+    );
+    assert_eq!(
+        find("my_lib::config::Config"),
+        (ApiKind::Struct, "pub struct Config<T: Clone = u8>")
+    );
+    assert_eq!(
+        find("my_lib::config::Config::name"),
+        (ApiKind::Field, "pub name: String")
+    );
+    assert_eq!(
+        find("<my_lib::config::Config as Display>"),
+        (ApiKind::Impl, "impl fmt::Display for Config")
+    );
+    assert_eq!(
+        find("my_lib::config::LIMIT"),
+        (ApiKind::Const, "pub const LIMIT: usize")
+    );
+    assert_eq!(
+        find("my_lib::config::Source::read"),
+        (ApiKind::Method, "fn read(&self) -> &'static str")
+    );
+    assert_eq!(find("my_lib::config::Source::Item"), (ApiKind::Type, "type Item"));
+    assert_eq!(find("my_lib::run"), (ApiKind::Fn, "pub fn helper(x: &mut [u8; 4])"));
+    assert_eq!(find("my_lib::Kind::C"), (ApiKind::Variant, "C { x: i32 }"));
+    assert_eq!(find("my_lib::Value"), (ApiKind::Use, "pub use serde_json::Value"));
+    assert_eq!(find("my_lib::make"), (ApiKind::Macro, "macro_rules! make"));
+    // Glob re-exports name the same items again, and the cycle back to the root ends.
+    assert_eq!(find("my_lib::prelude::Config").0, ApiKind::Struct);
+    assert_eq!(find("my_lib::prelude::run").0, ApiKind::Fn);
+    assert!(!items.iter().any(|i| i.path.starts_with("my_lib::prelude::prelude::")));
+
+    let paths: Vec<&str> = items.iter().map(|i| i.path.as_str()).collect();
+    for private in ["hidden", "private", "secret", "internal", "unexported", "tests", "fmt"] {
+        assert!(!paths.iter().any(|p| p.ends_with(&format!("::{private}"))), "{paths:?}");
+    }
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(paths, sorted);
+}
+
+#[test]
+fn api_diff_classifies_additions_removals_and_signature_changes() {
+    let old = TempDir::new().unwrap();
+    manifest(old.path());
+    write(
+        &old.path().join("src/lib.rs"),
+        "pub fn parse(s: String) -> u8 { 0 }\npub fn gone() {}\npub fn layout(a: u8, b: u8) {}\n",
+    );
+    let new = TempDir::new().unwrap();
+    manifest(new.path());
+    write(
+        &new.path().join("src/lib.rs"),
+        "pub fn parse(s: &str) -> u8 { 0 }\npub fn added() {}\n\n/// Docs do not count.\npub fn layout(\n    a: u8,\n    b: u8,\n) {\n}\n",
+    );
+
+    let diff = diff_api(&api(old.path()), &api(new.path()));
+    let paths = |items: &[ApiItem]| items.iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths(&diff.added), ["my_lib::added"]);
+    assert_eq!(paths(&diff.removed), ["my_lib::gone"]);
+    assert_eq!(diff.changed.len(), 1, "{:?}", diff.changed);
+    let change = &diff.changed[0];
+    assert_eq!(change.path, "my_lib::parse");
+    assert_eq!(change.old, "pub fn parse(s: String) -> u8");
+    assert_eq!(change.new, "pub fn parse(s: &str) -> u8");
+    assert_eq!(change.line, 1);
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::baseline::Baseline;
+use crate::{execute_query, rust_language, ArbolError};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+const UNWRAPS: &str =
+    "(call_expression function: (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\")))";
+
+#[test]
+fn baseline_hides_known_captures_after_lines_shift() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='b'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "mod inner {\n    impl Display for Foo {\n        fn fmt() { a.unwrap(); }\n    }\n}\nfn two() { b.unwrap(); b.unwrap(); }\n",
+    );
+    let lang = rust_language();
+    let baseline = Baseline::from_output(&lang, &execute_query(root, false, UNWRAPS, false).unwrap(), root);
+    let recorded: Vec<_> = baseline
+        .entries
+        .iter()
+        .map(|e| (e.file.as_str(), e.fingerprint.as_str()))
+        .collect();
+    assert_eq!(
+        recorded,
+        vec![
+            ("src/lib.rs", "inner::<impl Display for Foo>::fmt"),
+            ("src/lib.rs", "two"),
+            ("src/lib.rs", "two"),
+        ]
+    );
+
+    // Shift everything down, add a third unwrap to `two` and a new one in `three`.
+    write(
+        &root.join("src/lib.rs"),
+        "\n\nmod inner {\n    impl Display for Foo {\n        fn fmt() {\n            a.unwrap();\n        }\n    }\n}\nfn two() { b.unwrap(); b.unwrap(); b.unwrap(); }\nfn three() { c.unwrap(); }\n",
+    );
+    let mut output = execute_query(root, false, UNWRAPS, false).unwrap();
+    assert_eq!(baseline.filter(&lang, &mut output, root), 3);
+    let new: Vec<_> = output.crates[0].captures.iter().map(|c| (c.line, c.column)).collect();
+    assert_eq!(new, vec![(10, 38), (11, 16)]);
+}
+
+#[test]
+fn baseline_round_trips_and_rejects_other_versions() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().join("baseline.json");
+    let baseline = Baseline {
+        version: crate::baseline::BASELINE_VERSION,
+        entries: Vec::new(),
+    };
+    baseline.save(&path).unwrap();
+    assert_eq!(Baseline::load(&path).unwrap(), baseline);
+
+    write(&path, "{\"version\": 99, \"entries\": []}");
+    let err = Baseline::load(&path).unwrap_err();
+    assert!(matches!(err, ArbolError::InvalidBaseline(_)), "{err:?}");
+    assert!(err.to_string().contains("unsupported version 99"), "{err}");
+}
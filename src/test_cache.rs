@@ -0,0 +1,68 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::cache::QueryCache;
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::query::{query_matches_sources, query_sources};
+use crate::rust_language;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn cache_entries(dir: &Path) -> Vec<std::path::PathBuf> {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect()
+}
+
+#[test]
+fn unchanged_files_are_served_from_the_cache() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='c'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "fn alpha() {}\n");
+    let lang = rust_language();
+    let cache = QueryCache::new(td.path().join("cache"));
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let q = "(function_item name: (identifier) @fn.name)";
+
+    let fresh = query_sources(&lang, root, &files, q, false, Some(&cache)).unwrap();
+    let entries = cache_entries(cache.dir());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        query_sources(&lang, root, &files, q, false, Some(&cache)).unwrap(),
+        fresh
+    );
+
+    // Doctor the entry: an unchanged file is not parsed again, so the doctored text comes back.
+    let doctored = fs::read_to_string(&entries[0]).unwrap().replace("alpha", "cached");
+    fs::write(&entries[0], doctored).unwrap();
+    let hit = query_sources(&lang, root, &files, q, false, Some(&cache)).unwrap();
+    assert_eq!(hit.crates[0].captures[0].text, "cached");
+    // Other result shapes and context settings have their own entries.
+    let matches = query_matches_sources(&lang, root, &files, q, false, Some(&cache)).unwrap();
+    assert_eq!(matches.crates[0].matches[0].captures["fn.name"][0].text, "alpha");
+    assert_eq!(cache_entries(cache.dir()).len(), 2);
+
+    write(&root.join("src/lib.rs"), "fn beta() {}\n");
+    let changed = query_sources(&lang, root, &files, q, false, Some(&cache)).unwrap();
+    assert_eq!(changed.crates[0].captures[0].text, "beta");
+
+    assert_eq!(cache.clear().unwrap(), 2);
+    assert!(!cache.dir().exists());
+    assert_eq!(cache.clear().unwrap(), 0);
+}
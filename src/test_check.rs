@@ -0,0 +1,69 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::types::SyntaxErrorKind;
+use crate::{check_syntax, dump_json, execute_query};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+#[test]
+fn check_reports_error_and_missing_nodes() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn ok() {}\n");
+    write(
+        &root.join("src/broken.rs"),
+        "fn a() {\n    let x = ;\n}\nfn b() { foo(1 }\n",
+    );
+    let out = check_syntax(root, false).unwrap();
+    assert!(out.diagnostics.is_empty());
+    assert!(
+        out.errors.iter().all(|e| e.file.ends_with("broken.rs")),
+        "clean file has no errors: {:?}",
+        out.errors
+    );
+    let first = &out.errors[0];
+    assert_eq!((first.kind, first.line, first.column), (SyntaxErrorKind::Error, 2, 11));
+    assert_eq!(first.message, "unexpected `=`");
+    assert!(
+        out.errors.iter().any(|e| e.line == 4),
+        "expected an error on line 4: {:?}",
+        out.errors
+    );
+    let lines: Vec<_> = out.errors.iter().map(|e| (e.line, e.column)).collect();
+    let mut sorted = lines.clone();
+    sorted.sort();
+    assert_eq!(lines, sorted, "errors are in source order");
+}
+
+#[test]
+fn dump_and_query_flag_error_regions() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "fn good() {}\nfn bad() { let = 1; }\n");
+    let dump = dump_json(root, false, false, usize::MAX).unwrap().files;
+    let nodes = &dump[0].nodes;
+    assert!(nodes[0].has_error, "root contains the error");
+    assert!(nodes.iter().any(|n| n.is_error || n.is_missing));
+    let good = nodes.iter().find(|n| n.kind == "function_item").unwrap();
+    assert!(!good.has_error, "first function parses cleanly");
+
+    let q = "(function_item name: (identifier) @name) @fn";
+    let caps = execute_query(root, false, q, false).unwrap().crates;
+    let flagged: Vec<_> = caps[0]
+        .captures
+        .iter()
+        .filter(|c| c.name == "fn")
+        .map(|c| c.has_error)
+        .collect();
+    assert_eq!(flagged, vec![false, true]);
+}
@@ -4,7 +4,8 @@ use std::path::Path;
 
 use tempfile::TempDir;
 
-use crate::workspace::{crate_for_file, discover_crate_roots};
+use crate::types::{CrateInfo, Target, TargetKind};
+use crate::workspace::{crate_for_file, discover_crate_roots, discover_crates, infer_module_path, target_for_file};
 
 fn write(path: &Path, content: &str) {
     if let Some(parent) = path.parent() {
@@ -56,13 +57,15 @@ fn crate_for_file_picks_deepest_match() {
     );
     write(&root.join("outer/inner/src/lib.rs"), "pub fn x(){}\n");
 
-    let crates = discover_crate_roots(root).unwrap();
+    let crates = discover_crates(root).unwrap();
     let file = root.join("outer/inner/src/lib.rs");
     let found = crate_for_file(&file, &crates).unwrap();
     assert!(
-        found.ends_with("outer/inner"),
-        "expected deepest crate root, got {found}"
+        found.path.ends_with("outer/inner"),
+        "expected deepest crate root, got {}",
+        found.path.display()
     );
+    assert_eq!(found.info.as_ref().unwrap().name, "inner");
 }
 
 #[test]
@@ -75,7 +78,154 @@ fn crate_for_file_none_when_outside() {
     );
     let outside = root.join("no_crate/file.rs");
     write(&outside, "fn main(){}\n");
-    let crates = discover_crate_roots(root).unwrap();
+    let crates = discover_crates(root).unwrap();
     let res = crate_for_file(&outside, &crates);
     assert!(res.is_none(), "file outside crate roots should yield None");
 }
+
+#[test]
+fn parses_manifest_identity_and_workspace_membership() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[workspace]\nmembers = ['crates/*']\nexclude = ['crates/skipped']\n\n\
+         [workspace.package]\nversion = '1.2.3'\nedition = '2024'\n",
+    );
+    write(
+        &root.join("crates/core/Cargo.toml"),
+        "[package]\nname = 'core-lib'\nversion.workspace = true\nedition.workspace = true\n",
+    );
+    write(
+        &root.join("crates/skipped/Cargo.toml"),
+        "[package]\nname = 'skipped'\nversion = '0.1.0'\n",
+    );
+    write(
+        &root.join("tools/gen/Cargo.toml"),
+        "[package]\nname = 'gen'\nversion = '0.2.0'\nedition = '2021'\n",
+    );
+
+    let crates = discover_crates(root).unwrap();
+    let info = |rel: &str| {
+        crates
+            .iter()
+            .find(|c| c.path == root.join(rel))
+            .unwrap_or_else(|| panic!("missing crate {rel}"))
+            .info
+            .clone()
+    };
+    assert_eq!(info(""), None, "virtual manifest has no package identity");
+    assert_eq!(
+        info("crates/core"),
+        Some(CrateInfo {
+            name: "core-lib".into(),
+            version: "1.2.3".into(),
+            edition: "2024".into(),
+            workspace_member: true,
+        })
+    );
+    assert_eq!(
+        info("crates/skipped"),
+        Some(CrateInfo {
+            name: "skipped".into(),
+            version: "0.1.0".into(),
+            edition: "2015".into(),
+            workspace_member: false,
+        }),
+        "excluded crate is not a member and defaults to the 2015 edition"
+    );
+    assert!(
+        !info("tools/gen").unwrap().workspace_member,
+        "not matched by members globs"
+    );
+}
+
+#[test]
+fn attributes_files_to_cargo_targets() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='my-pkg'\nversion='0.1.0'\nedition='2021'\nautoexamples=false\n\n\
+         [[bin]]\nname='custom'\npath='tools/custom.rs'\n\n\
+         [[example]]\nname='declared'\n",
+    );
+    for f in [
+        "src/lib.rs",
+        "src/util.rs",
+        "src/main.rs",
+        "src/bin/tool.rs",
+        "src/bin/multi/main.rs",
+        "tools/custom.rs",
+        "tools/helper.rs",
+        "examples/declared.rs",
+        "examples/undeclared.rs",
+        "tests/it.rs",
+        "tests/other.rs",
+        "tests/common/mod.rs",
+        "benches/speed/main.rs",
+        "build.rs",
+    ] {
+        write(&root.join(f), "fn x(){}\n");
+    }
+    let crates = discover_crates(root).unwrap();
+    let krate = &crates[0];
+    let target = |rel: &str| target_for_file(&root.join(rel), krate);
+    let t = |kind, name: &str| {
+        Some(Target {
+            kind,
+            name: name.to_string(),
+        })
+    };
+    assert_eq!(target("src/lib.rs"), t(TargetKind::Lib, "my_pkg"));
+    assert_eq!(
+        target("src/util.rs"),
+        t(TargetKind::Lib, "my_pkg"),
+        "shared src modules go to lib"
+    );
+    assert_eq!(target("src/main.rs"), t(TargetKind::Bin, "my-pkg"));
+    assert_eq!(target("src/bin/tool.rs"), t(TargetKind::Bin, "tool"));
+    assert_eq!(target("src/bin/multi/main.rs"), t(TargetKind::Bin, "multi"));
+    assert_eq!(target("tools/custom.rs"), t(TargetKind::Bin, "custom"));
+    assert_eq!(
+        target("tools/helper.rs"),
+        t(TargetKind::Bin, "custom"),
+        "`mod helper;` in tools/custom.rs resolves next to it"
+    );
+    assert_eq!(target("examples/declared.rs"), t(TargetKind::Example, "declared"));
+    assert_eq!(
+        target("examples/undeclared.rs"),
+        t(TargetKind::Example, "declared"),
+        "autoexamples = false: not a target itself, only a possible module of `declared`"
+    );
+    assert_eq!(target("tests/it.rs"), t(TargetKind::Test, "it"));
+    assert_eq!(
+        target("tests/common/mod.rs"),
+        None,
+        "shared test helpers belong to no single target"
+    );
+    assert_eq!(target("benches/speed/main.rs"), t(TargetKind::Bench, "speed"));
+    assert_eq!(target("build.rs"), t(TargetKind::BuildScript, "build-script-build"));
+}
+
+#[test]
+fn non_default_root_files_resolve_modules_next_to_themselves() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='p'\nversion='0.1.0'\nedition='2021'\n\n[lib]\npath='src/mylib.rs'\n",
+    );
+    for f in ["src/mylib.rs", "src/util.rs", "src/util/deep.rs"] {
+        write(&root.join(f), "fn x(){}\n");
+    }
+    let crates = discover_crates(root).unwrap();
+    let module = |rel: &str| infer_module_path(&root.join(rel), &crates[0]);
+    assert_eq!(module("src/mylib.rs").as_deref(), Some("crate"));
+    assert_eq!(module("src/util.rs").as_deref(), Some("crate::util"));
+    assert_eq!(module("src/util/deep.rs").as_deref(), Some("crate::util::deep"));
+    assert_eq!(
+        target_for_file(&root.join("src/util.rs"), &crates[0]).map(|t| t.kind),
+        Some(TargetKind::Lib)
+    );
+}
@@ -1,10 +1,12 @@
 use std::fs;
 use std::io::Write;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use tempfile::TempDir;
 
-use crate::dump_json;
+use crate::fs::{discover_sources, for_each_ordered, ordered_window, stream_dump, Discovery, PathFilter};
+use crate::{dump_json, rust_language, DumpEvent};
 
 fn write(path: &Path, content: &str) {
     if let Some(parent) = path.parent() {
@@ -23,8 +25,8 @@ fn depth_zero_returns_only_root_node() {
         "[package]\nname='d0'\nversion='0.0.0'\nedition='2021'\n",
     );
     write(&root.join("src/lib.rs"), "pub fn alpha() {}\n");
-    let full = dump_json(root, false, false, usize::MAX).unwrap();
-    let shallow = dump_json(root, false, false, 0).unwrap();
+    let full = dump_json(root, false, false, usize::MAX).unwrap().files;
+    let shallow = dump_json(root, false, false, 0).unwrap().files;
     assert_eq!(full.len(), shallow.len(), "same file count");
     assert_eq!(shallow[0].nodes.len(), 1, "depth 0 should include only the root node");
 }
@@ -38,7 +40,7 @@ fn with_source_includes_short_text() {
         "[package]\nname='src'\nversion='0.0.0'\nedition='2021'\n",
     );
     write(&root.join("src/lib.rs"), "pub fn beta() { }\n");
-    let asts = dump_json(root, false, true, 4).unwrap();
+    let asts = dump_json(root, false, true, 4).unwrap().files;
     assert!(!asts.is_empty());
     let has_text = asts[0].nodes.iter().any(|n| n.text.as_ref().is_some());
     assert!(
@@ -61,7 +63,7 @@ fn large_source_omits_long_text() {
         &root.join("src/lib.rs"),
         &format!("pub fn big() {{ /*{}*/ }}\n", big_body),
     );
-    let asts = dump_json(root, false, true, usize::MAX).unwrap();
+    let asts = dump_json(root, false, true, usize::MAX).unwrap().files;
     // At least one node (likely root) should have text omitted due to length
     let any_none = asts[0].nodes.iter().any(|n| n.text.is_none());
     assert!(
@@ -79,8 +81,8 @@ fn deterministic_multiple_runs() {
         "[package]\nname='det'\nversion='0.0.0'\nedition='2021'\n",
     );
     write(&root.join("src/lib.rs"), "pub fn a() {} pub fn b() {} pub fn c() {}\n");
-    let run1 = dump_json(root, false, false, usize::MAX).unwrap();
-    let run2 = dump_json(root, false, false, usize::MAX).unwrap();
+    let run1 = dump_json(root, false, false, usize::MAX).unwrap().files;
+    let run2 = dump_json(root, false, false, usize::MAX).unwrap().files;
     assert_eq!(run1, run2, "dump_json output should be deterministic across runs");
     // Byte ranges should be coherent
     for file in run1 {
@@ -95,3 +97,70 @@ fn deterministic_multiple_runs() {
         }
     }
 }
+
+#[test]
+fn unreadable_files_become_diagnostics() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn ok() {}\n");
+    fs::write(root.join("src/latin1.rs"), b"// caf\xe9\n").unwrap();
+    let out = dump_json(root, false, false, 0).unwrap();
+    assert_eq!(out.files.len(), 1, "valid file still dumped");
+    assert_eq!(out.diagnostics.len(), 1);
+    let diag = &out.diagnostics[0];
+    assert!(diag.file.ends_with("latin1.rs"));
+    assert_eq!(diag.kind, crate::FileErrorKind::InvalidUtf8);
+    assert!(diag.reason.starts_with("UTF-8:"), "reason: {}", diag.reason);
+}
+
+#[test]
+fn stream_dump_matches_batch_dump() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='sd'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    for name in ["a", "b", "c", "d"] {
+        write(&root.join(format!("src/{name}.rs")), &format!("pub fn {name}() {{}}\n"));
+    }
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let mut streamed = Vec::new();
+    stream_dump(&rust_language(), &files, false, 1, |e| {
+        if let DumpEvent::File(ast) = e {
+            streamed.push(ast);
+        }
+        true
+    });
+    assert_eq!(streamed, dump_json(root, false, false, 1).unwrap().files);
+}
+
+#[test]
+fn ordered_streaming_buffers_at_most_one_window() {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    pool.install(|| {
+        let items: Vec<usize> = (0..2000).collect();
+        let (buffered, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let mut seen = Vec::new();
+        for_each_ordered(
+            &items,
+            |&i| {
+                let now = buffered.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                i
+            },
+            |i| {
+                buffered.fetch_sub(1, Ordering::SeqCst);
+                seen.push(i);
+                true
+            },
+        );
+        assert_eq!(seen, items);
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(
+            peak <= ordered_window(),
+            "{peak} results buffered, window is {}",
+            ordered_window()
+        );
+    });
+}
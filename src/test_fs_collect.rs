@@ -4,7 +4,7 @@ use std::path::Path;
 
 use tempfile::TempDir;
 
-use crate::fs::collect_rust_files;
+use crate::fs::{collect_rust_files, PathFilter};
 
 fn write(path: &Path, content: &str) {
     if let Some(parent) = path.parent() {
@@ -32,8 +32,8 @@ fn collects_basic_rs_files_and_skips_ignored_and_generated() {
     // .gitignore to exclude one file
     write(&root.join(".gitignore"), "src/ignored.rs\n");
 
-    let without_tests = collect_rust_files(root, false, &[]).unwrap();
-    let with_tests = collect_rust_files(root, true, &[]).unwrap();
+    let without_tests = collect_rust_files(root, false, &PathFilter::default(), true).unwrap();
+    let with_tests = collect_rust_files(root, true, &PathFilter::default(), true).unwrap();
 
     // Should contain only src/lib.rs without tests
     let mut without: Vec<String> = without_tests
@@ -41,11 +41,7 @@ fn collects_basic_rs_files_and_skips_ignored_and_generated() {
         .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().to_string())
         .collect();
     without.sort();
-    assert_eq!(
-        without,
-        vec!["src/ignored.rs", "src/lib.rs"],
-        "no gitignore filtering: both files present"
-    );
+    assert_eq!(without, vec!["src/lib.rs"], "gitignored file should be filtered out");
 
     // With tests includes tests/test_thing.rs
     let mut rels: Vec<_> = with_tests
@@ -55,8 +51,42 @@ fn collects_basic_rs_files_and_skips_ignored_and_generated() {
     rels.sort();
     assert_eq!(
         rels,
-        vec!["src/ignored.rs", "src/lib.rs", "tests/test_thing.rs"],
-        "no gitignore filtering: tests flag adds tests plus all src files"
+        vec!["src/lib.rs", "tests/test_thing.rs"],
+        "tests flag adds tests but keeps gitignore filtering"
+    );
+}
+
+#[test]
+fn honours_arbolignore_and_no_ignore_switch() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='x'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn a(){}\n");
+    write(&root.join("vendor/dep/src/lib.rs"), "pub fn vendored(){}\n");
+    write(&root.join("src/fixtures/ignored.rs"), "pub fn fixture(){}\n");
+    write(&root.join(".arbolignore"), "vendor/\n");
+    write(&root.join("src/.ignore"), "fixtures/\n");
+
+    let respected = collect_rust_files(root, false, &PathFilter::default(), true).unwrap();
+    let rels: Vec<_> = respected
+        .iter()
+        .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(rels, vec!["src/lib.rs"], "ignore files should hide vendor and fixtures");
+
+    let everything = collect_rust_files(root, false, &PathFilter::default(), false).unwrap();
+    let mut rels: Vec<_> = everything
+        .iter()
+        .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().to_string())
+        .collect();
+    rels.sort();
+    assert_eq!(
+        rels,
+        vec!["src/fixtures/ignored.rs", "src/lib.rs", "vendor/dep/src/lib.rs"],
+        "disabling ignore handling should visit every file"
     );
 }
 
@@ -73,7 +103,8 @@ fn collects_with_skip_dirs_excludes_specified_paths() {
     write(&root.join("examples/demo.rs"), "pub fn demo(){}\n");
     // Provide skip dirs relative and absolute
     let abs_skip = root.join("examples");
-    let res = collect_rust_files(root, false, &[Path::new("src/skip_me").to_path_buf(), abs_skip]).unwrap();
+    let filter = PathFilter::from_skip_dirs(&[Path::new("src/skip_me").to_path_buf(), abs_skip]);
+    let res = collect_rust_files(root, false, &filter, true).unwrap();
     let mut rels: Vec<_> = res
         .iter()
         .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().to_string())
@@ -81,3 +112,52 @@ fn collects_with_skip_dirs_excludes_specified_paths() {
     rels.sort();
     assert_eq!(rels, vec!["src/lib.rs"], "expected only lib.rs after skips");
 }
+
+#[test]
+fn skip_dirs_match_path_prefixes_not_substrings() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn a(){}\n");
+    write(&root.join("src/rapid_api.rs"), "pub fn rapid(){}\n");
+    write(&root.join("api/mod.rs"), "pub fn api(){}\n");
+    let filter = PathFilter::from_skip_dirs(&[Path::new("api").to_path_buf()]);
+    let mut rels = rels_of(root, &filter);
+    rels.sort();
+    assert_eq!(rels, vec!["src/lib.rs", "src/rapid_api.rs"]);
+}
+
+#[test]
+fn include_and_exclude_globs_with_negation() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn a(){}\n");
+    write(&root.join("src/api/mod.rs"), "pub fn api(){}\n");
+    write(&root.join("src/api/keep.rs"), "pub fn keep(){}\n");
+    write(&root.join("examples/demo.rs"), "fn main(){}\n");
+
+    let include = vec!["src/".to_string()];
+    let exclude = vec!["api/".to_string(), "!src/api/keep.rs".to_string()];
+    let filter = PathFilter::new(&[], &include, &exclude).unwrap();
+    let mut rels = rels_of(root, &filter);
+    rels.sort();
+    assert_eq!(rels, vec!["src/api/keep.rs", "src/lib.rs"]);
+
+    let filter = PathFilter::new(&[], &["*.rs".to_string(), "!mod.rs".to_string()], &[]).unwrap();
+    let mut rels = rels_of(root, &filter);
+    rels.sort();
+    assert_eq!(rels, vec!["examples/demo.rs", "src/api/keep.rs", "src/lib.rs"]);
+}
+
+#[test]
+fn invalid_glob_is_rejected() {
+    let err = PathFilter::new(&[], &["src/{a,b".to_string()], &[]).unwrap_err();
+    assert!(matches!(err, crate::ArbolError::InvalidGlob(_)), "got {err:?}");
+}
+
+fn rels_of(root: &Path, filter: &PathFilter) -> Vec<String> {
+    collect_rust_files(root, false, filter, true)
+        .unwrap()
+        .iter()
+        .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().to_string())
+        .collect()
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::lint::{lint_sources, render_finding};
+use crate::rules::load_rules;
+use crate::types::SuppressionScope;
+use crate::{rust_language, ArbolError, Severity};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn fixture() -> TempDir {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='l'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "pub fn ab() {}\nfn descriptive() {\n    x.unwrap();\n}\n",
+    );
+    write(
+        &root.join("rules/naming/short-name.scm"),
+        "; message: `{fn.name}` is too short\n; severity: error\n; fix: rename it\n; capture: fn.name\n\
+         ((function_item name: (identifier) @fn.name) @fn (#match? @fn.name \"^..?$\"))\n",
+    );
+    write(
+        &root.join("rules/unwrap.scm"),
+        "; id: no-unwrap\n; message: `{text}` may panic\n\
+         (call_expression function: (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\"))) @call\n",
+    );
+    td
+}
+
+#[test]
+fn rules_load_recursively_with_ids_from_metadata_or_stem() {
+    let td = fixture();
+    let rules = load_rules(&[td.path().join("rules")]).unwrap();
+    let ids: Vec<_> = rules.iter().map(|r| (r.id.as_str(), r.severity)).collect();
+    assert_eq!(
+        ids,
+        vec![("short-name", Severity::Error), ("no-unwrap", Severity::Warning)]
+    );
+    assert_eq!(rules[0].fix.as_deref(), Some("rename it"));
+
+    write(&td.path().join("more/no-unwrap.scm"), "(identifier) @x\n");
+    let err = load_rules(&[td.path().join("rules"), td.path().join("more")]).unwrap_err();
+    assert!(err.to_string().contains("duplicate rule id `no-unwrap`"), "{err}");
+}
+
+#[test]
+fn misspelled_severity_fails_loading_with_the_file_and_value() {
+    let td = fixture();
+    let bad = td.path().join("typo/strict.scm");
+    write(
+        &bad,
+        "; severity: eror
+(identifier) @x
+",
+    );
+    let err = load_rules(&[td.path().join("typo")]).unwrap_err();
+    assert!(matches!(err, ArbolError::InvalidRule(_)), "{err:?}");
+    let msg = err.to_string();
+    assert!(msg.contains(&bad.display().to_string()), "{msg}");
+    assert!(msg.contains("unknown severity `eror`"), "{msg}");
+}
+
+#[test]
+fn lint_reports_each_match_at_its_anchor() {
+    let td = fixture();
+    let root = td.path();
+    let rules = load_rules(&[root.join("rules")]).unwrap();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let out = lint_sources(&rust_language(), root, &files, &rules).unwrap();
+    let found: Vec<_> = out
+        .findings
+        .iter()
+        .map(|f| (f.rule.as_str(), f.line, f.column, f.message.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("short-name", 1, 8, "`ab` is too short"),
+            ("no-unwrap", 3, 5, "`x.unwrap()` may panic"),
+        ]
+    );
+    assert_eq!(
+        render_finding(&out.findings[0]),
+        format!(
+            "error[short-name]: `ab` is too short\n --> {}:1:8\n  |\n1 | pub fn ab() {{}}\n  |        ^^\n  = help: rename it",
+            out.findings[0].file
+        )
+    );
+}
+
+#[test]
+fn broken_rule_names_its_file() {
+    let td = fixture();
+    let root = td.path();
+    write(&root.join("bad/typo.scm"), "(function_item nme: (identifier))\n");
+    let rules = load_rules(&[root.join("bad")]).unwrap();
+    let err = lint_sources(&rust_language(), root, &[], &rules).unwrap_err();
+    let ArbolError::RuleCompile { path, diag } = err else {
+        panic!("expected RuleCompile, got {err:?}");
+    };
+    assert!(path.ends_with("typo.scm"));
+    assert_eq!(diag.token, "nme");
+}
+
+#[test]
+fn suppression_comments_silence_findings_and_unused_ones_are_reported() {
+    let td = fixture();
+    let root = td.path();
+    write(
+        &root.join("src/lib.rs"),
+        "// arbol-ignore-file: short-name\npub fn ab() {}\nfn descriptive() {\n    // arbol-ignore: no-unwrap -- checked above\n    // arbol-ignore: other-pack-rule\n    x.unwrap();\n    // arbol-ignore: no-unwrap\n    y.clone();\n    z.unwrap();\n}\n",
+    );
+    let rules = load_rules(&[root.join("rules")]).unwrap();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let out = lint_sources(&rust_language(), root, &files, &rules).unwrap();
+    let found: Vec<_> = out.findings.iter().map(|f| (f.rule.as_str(), f.line)).collect();
+    assert_eq!(found, vec![("no-unwrap", 9)]);
+    // Only the suppression above `y.clone()` is stale; `other-pack-rule` is not a loaded rule.
+    let unused: Vec<_> = out
+        .unused_suppressions
+        .iter()
+        .map(|s| (s.rule.as_str(), s.line, s.column, s.scope))
+        .collect();
+    assert_eq!(unused, vec![("no-unwrap", 7, 5, SuppressionScope::NextLine)]);
+}
+
+#[test]
+fn suppression_comments_skip_attributes_and_block_comments_before_the_item() {
+    let td = fixture();
+    let root = td.path();
+    write(
+        &root.join("src/lib.rs"),
+        "// arbol-ignore: short-name\n#[derive(\n    Debug,\n)]\n/* a struct */\n#[allow(dead_code)]\npub fn ab() {}\n\n\
+         // arbol-ignore: short-name\n#[cfg(test)] fn cd() {}\nfn ef() {}\n",
+    );
+    let rules = load_rules(&[root.join("rules")]).unwrap();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let out = lint_sources(&rust_language(), root, &files, &rules).unwrap();
+    let found: Vec<_> = out.findings.iter().map(|f| (f.rule.as_str(), f.line)).collect();
+    assert_eq!(found, vec![("short-name", 11)]);
+    assert!(out.unused_suppressions.is_empty(), "{:?}", out.unused_suppressions);
+}
+
+#[test]
+fn rewrite_metadata_attaches_a_suggested_edit() {
+    let td = fixture();
+    let root = td.path();
+    write(
+        &root.join("rules/unwrap.scm"),
+        "; id: no-unwrap\n; rewrite: expect(\"{{m}} failed\")\n; rewrite-capture: @m\n\
+         (call_expression function: (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\")) \
+         arguments: (arguments) @args) @call\n",
+    );
+    let rules = load_rules(&[root.join("rules/unwrap.scm")]).unwrap();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let out = lint_sources(&rust_language(), root, &files, &rules).unwrap();
+    let edit = out.findings[0].suggestion.as_ref().unwrap();
+    assert_eq!((edit.line, edit.column, edit.end_column), (3, 7, 13));
+    assert_eq!(edit.original, "unwrap");
+    assert_eq!(edit.replacement, "expect(\"unwrap failed\")");
+
+    // A rewrite that would leave a syntax error behind is not suggested; the finding still is.
+    write(
+        &root.join("rules/unwrap.scm"),
+        "; id: no-unwrap\n; rewrite: {{m}}(\n; rewrite-capture: @m\n\
+         (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\"))\n",
+    );
+    let rules = load_rules(&[root.join("rules/unwrap.scm")]).unwrap();
+    let out = lint_sources(&rust_language(), root, &files, &rules).unwrap();
+    assert_eq!(out.findings.len(), 1);
+    assert_eq!(out.findings[0].suggestion, None);
+
+    // Suggestions of one file are checked together: a broken one overlapping a valid one only drops itself.
+    write(
+        &root.join("rules/expect.scm"),
+        "; id: use-expect\n; rewrite: expect(\"{{m}} failed\")\n; rewrite-capture: @m\n\
+         (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\"))\n",
+    );
+    let rules = load_rules(&[root.join("rules/unwrap.scm"), root.join("rules/expect.scm")]).unwrap();
+    let out = lint_sources(&rust_language(), root, &files, &rules).unwrap();
+    let suggested: Vec<_> = out
+        .findings
+        .iter()
+        .map(|f| (f.rule.as_str(), f.suggestion.as_ref().map(|e| e.replacement.as_str())))
+        .collect();
+    assert_eq!(
+        suggested,
+        [("no-unwrap", None), ("use-expect", Some("expect(\"unwrap failed\")"))]
+    );
+
+    write(&root.join("rules/unwrap.scm"), "; rewrite: {{nope}}\n(identifier) @x\n");
+    let rules = load_rules(&[root.join("rules/unwrap.scm")]).unwrap();
+    let err = lint_sources(&rust_language(), root, &files, &rules).unwrap_err();
+    assert!(matches!(err, ArbolError::InvalidRule(_)), "{err}");
+    assert!(err.to_string().contains("`{{nope}}`"), "{err}");
+}
@@ -0,0 +1,172 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use crate::lsp::LanguageServer;
+use crate::rpc::read_framed_message;
+use crate::rules::load_rules;
+use crate::rust_language;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn frame(messages: &[Value]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for m in messages {
+        let body = m.to_string();
+        write!(out, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    }
+    out
+}
+
+fn lsp(root: &Path, messages: &[Value]) -> Vec<Value> {
+    let rules = load_rules(&[root.join("rules")]).unwrap();
+    let mut server = LanguageServer::new(&rust_language(), root, &rules).unwrap();
+    let mut output = Vec::new();
+    server.run(frame(messages).as_slice(), &mut output).unwrap();
+    let mut input = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(message) = read_framed_message(&mut input).unwrap() {
+        replies.push(serde_json::from_str(&message).unwrap());
+    }
+    replies
+}
+
+fn notify(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+#[test]
+fn lsp_publishes_findings_for_open_buffers_and_offers_rewrites() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("rules/no-unwrap.scm"),
+        "; message: `{text}` may panic\n; severity: error\n; rewrite: expect(\"TODO\")\n; rewrite-capture: call\n\
+         (call_expression function: (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\"))) @call\n",
+    );
+    // The buffer is never written to disk.
+    let uri = format!("file://{}/src/l%C3%A9.rs", root.display());
+    let src = "fn f() {\n    let s = g(\"\u{e9}\u{1f980}\").unwrap();\n}\n";
+    let whole = json!({"start": {"line": 0, "character": 0}, "end": {"line": 3, "character": 0}});
+    let replies = lsp(
+        root,
+        &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+            notify("initialized", json!({})),
+            notify(
+                "textDocument/didOpen",
+                json!({"textDocument": {"uri": uri, "languageId": "rust", "version": 1, "text": src}}),
+            ),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/codeAction", "params": {
+                "textDocument": {"uri": uri}, "range": whole, "context": {"diagnostics": []}}}),
+            notify(
+                "textDocument/didChange",
+                json!({"textDocument": {"uri": uri, "version": 2}, "contentChanges": [{"text": "fn f() {}\n"}]}),
+            ),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/codeAction", "params": {
+                "textDocument": {"uri": uri}, "range": whole, "context": {"diagnostics": []}}}),
+            notify("textDocument/didClose", json!({"textDocument": {"uri": uri}})),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "textDocument/hover", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "shutdown"}),
+            notify("exit", Value::Null),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "shutdown"}),
+        ],
+    );
+    let kinds: Vec<_> = replies
+        .iter()
+        .map(|r| r.get("method").cloned().unwrap_or_else(|| r["id"].clone()))
+        .collect();
+    let publish = json!("textDocument/publishDiagnostics");
+    assert_eq!(
+        kinds,
+        vec![
+            json!(1),
+            publish.clone(),
+            json!(2),
+            publish.clone(),
+            json!(3),
+            publish,
+            json!(4),
+            json!(5)
+        ]
+    );
+    assert_eq!(replies[0]["result"]["capabilities"]["textDocumentSync"]["change"], 1);
+
+    let diagnostics = &replies[1]["params"]["diagnostics"];
+    assert_eq!(replies[1]["params"]["uri"], uri);
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    let d = &diagnostics[0];
+    assert_eq!(d["code"], "no-unwrap");
+    assert_eq!(d["severity"], 1);
+    // `é` is one UTF-16 unit and two bytes; the crab is two units and four bytes.
+    assert_eq!(
+        d["range"],
+        json!({"start": {"line": 1, "character": 12}, "end": {"line": 1, "character": 29}})
+    );
+
+    let actions = replies[2]["result"].as_array().unwrap();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0]["kind"], "quickfix");
+    assert_eq!(actions[0]["title"], "no-unwrap: replace with `expect(\"TODO\")`");
+    assert_eq!(
+        actions[0]["edit"]["changes"][&uri],
+        json!([{"range": d["range"], "newText": "expect(\"TODO\")"}])
+    );
+
+    assert_eq!(replies[3]["params"]["diagnostics"], json!([]));
+    assert_eq!(replies[4]["result"], json!([]));
+    assert_eq!(replies[5]["params"]["diagnostics"], json!([]));
+    assert_eq!(replies[6]["error"]["code"], -32601);
+    assert_eq!(replies[7]["result"], Value::Null);
+}
+
+#[test]
+fn lsp_reports_unused_suppressions_and_answers_malformed_messages() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("rules/short-name.scm"),
+        "((function_item name: (identifier) @n) (#match? @n \"^.$\"))\n",
+    );
+    let uri = "untitled:Untitled-1";
+    let src = "// arbol-ignore: short-name\nfn long_name() {}\nfn a() {}\n";
+    let mut input = frame(&[notify(
+        "textDocument/didOpen",
+        json!({"textDocument": {"uri": uri, "languageId": "rust", "version": 1, "text": src}}),
+    )]);
+    input.extend_from_slice(b"Content-Length: 8\r\n\r\nnot json");
+    let rules = load_rules(&[root.join("rules")]).unwrap();
+    let mut server = LanguageServer::new(&rust_language(), root, &rules).unwrap();
+    let mut output = Vec::new();
+    server.run(input.as_slice(), &mut output).unwrap();
+
+    let mut output = output.as_slice();
+    let published: Value = serde_json::from_str(&read_framed_message(&mut output).unwrap().unwrap()).unwrap();
+    let diagnostics = published["params"]["diagnostics"].as_array().unwrap();
+    let summary: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d["range"]["start"]["line"].clone(), d["severity"].clone()))
+        .collect();
+    assert_eq!(summary, vec![(json!(2), json!(2)), (json!(0), json!(4))]);
+    assert_eq!(diagnostics[1]["tags"], json!([1]));
+    assert_eq!(diagnostics[1]["message"], "`short-name` suppressed nothing");
+
+    let error: Value = serde_json::from_str(&read_framed_message(&mut output).unwrap().unwrap()).unwrap();
+    assert_eq!(error["error"]["code"], -32700);
+}
+
+#[test]
+fn oversized_content_length_is_rejected_without_allocating() {
+    let mut input: &[u8] = b"Content-Length: 99999999999\r\n\r\n{}";
+    let err = read_framed_message(&mut input).unwrap_err();
+    assert!(err.to_string().contains("exceeds the"), "{err}");
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use crate::fs::{Discovery, PathFilter};
+use crate::mcp::{McpServer, DEFAULT_MAX_OUTPUT};
+use crate::rust_language;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn server(root: &Path, max_output: usize) -> McpServer {
+    McpServer::new(
+        &rust_language(),
+        root,
+        false,
+        PathFilter::default(),
+        Discovery::default(),
+        max_output,
+    )
+}
+
+/// Call `tool` and return its text content parsed as JSON, or as a string when it is not JSON.
+fn call(server: &McpServer, tool: &str, arguments: Value) -> (Value, bool) {
+    let result = server
+        .handle("tools/call", json!({"name": tool, "arguments": arguments}))
+        .unwrap();
+    let text = result["content"][0]["text"].as_str().unwrap();
+    let value = serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()));
+    (value, result["isError"].as_bool().unwrap())
+}
+
+fn fixture() -> TempDir {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='m'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "mod parse;\npub struct Config;\nimpl Config {\n    pub fn load() -> Self {\n        Config\n    }\n}\n",
+    );
+    write(&root.join("src/parse.rs"), "pub fn load() {}\nfn helper() {}\n");
+    td
+}
+
+#[test]
+fn mcp_lists_tools_and_answers_tool_calls() {
+    let td = fixture();
+    let root = td.path();
+    let server = server(root, DEFAULT_MAX_OUTPUT);
+    let init = server
+        .handle(
+            "initialize",
+            json!({"protocolVersion": "2024-11-05", "capabilities": {}}),
+        )
+        .unwrap();
+    assert_eq!(init["protocolVersion"], "2024-11-05");
+    assert!(init["capabilities"]["tools"].is_object());
+    let tools = server.handle("tools/list", Value::Null).unwrap();
+    let names: Vec<_> = tools["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "run_query",
+            "list_node_kinds",
+            "outline_file",
+            "find_symbol",
+            "dump_subtree"
+        ]
+    );
+
+    let (found, is_error) = call(&server, "find_symbol", json!({"name": "load"}));
+    assert!(!is_error);
+    assert_eq!(found["total"], 2);
+    assert_eq!(found["symbols"][0]["container"], "impl Config");
+    assert_eq!(found["symbols"][1]["container"], Value::Null);
+    let (found, _) = call(
+        &server,
+        "find_symbol",
+        json!({"name": "Conf", "partial": true, "kind": "struct"}),
+    );
+    assert_eq!(found["symbols"][0]["line"], 2);
+
+    let (kinds, _) = call(&server, "list_node_kinds", json!({"contains": "function"}));
+    let kinds: Vec<_> = kinds["kinds"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|k| k.as_str().unwrap())
+        .collect();
+    assert!(kinds.contains(&"function_item"), "{kinds:?}");
+    assert!(kinds.iter().all(|k| k.contains("function")));
+
+    let (outline, _) = call(&server, "outline_file", json!({"path": "src/lib.rs"}));
+    assert_eq!(outline["items"][2]["children"][0]["name"], "load");
+
+    // Without a column the whole item starting on the line is dumped.
+    let (dump, _) = call(
+        &server,
+        "dump_subtree",
+        json!({"path": "src/lib.rs", "line": 4, "max_depth": 0}),
+    );
+    assert_eq!(dump["root_kind"], "function_item");
+    assert_eq!(dump["total"], 1);
+    let (dump, _) = call(
+        &server,
+        "dump_subtree",
+        json!({"path": "src/lib.rs", "line": 4, "column": 12}),
+    );
+    assert_eq!(dump["root_kind"], "identifier");
+
+    let (error, is_error) = call(&server, "outline_file", json!({"path": "../elsewhere.rs"}));
+    assert!(is_error);
+    assert!(error.as_str().unwrap().contains("elsewhere.rs"), "{error}");
+    let (error, _) = call(&server, "dump_subtree", json!({"path": "/"}));
+    assert_eq!(error, "MCP: /: outside of the workspace root");
+    let (error, is_error) = call(&server, "run_query", json!({"query": "(function_item"}));
+    assert!(is_error);
+    assert!(error.as_str().unwrap().starts_with("Query compile error"), "{error}");
+    assert_eq!(
+        server
+            .handle("tools/call", json!({"name": "nope", "arguments": {}}))
+            .unwrap_err()
+            .code,
+        -32602
+    );
+}
+
+#[test]
+fn mcp_results_are_limited_with_truncation_markers() {
+    let td = fixture();
+    let root = td.path();
+    let long = format!("fn long() {{ {} }}\n", "x;".repeat(300));
+    write(&root.join("src/long.rs"), &long);
+    let q = "(function_item name: (identifier) @name) @fn";
+
+    let (result, _) = call(
+        &server(root, DEFAULT_MAX_OUTPUT),
+        "run_query",
+        json!({"query": q, "limit": 2}),
+    );
+    assert_eq!(result["total"], 8);
+    assert_eq!(result["captures"].as_array().unwrap().len(), 2);
+    assert!(result["truncated"]
+        .as_str()
+        .unwrap()
+        .starts_with("showing 2 of 8 captures"));
+    let (result, _) = call(&server(root, DEFAULT_MAX_OUTPUT), "run_query", json!({"query": q}));
+    let texts: Vec<_> = result["captures"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["text"].as_str().unwrap())
+        .collect();
+    let cut = texts.iter().find(|t| t.starts_with("fn long")).unwrap();
+    assert!(cut.ends_with("… [414 more bytes]"), "{cut}");
+    assert!(result.get("truncated").is_none());
+
+    let (text, _) = call(&server(root, 100), "run_query", json!({"query": q}));
+    let text = text.as_str().unwrap();
+    assert!(text.len() < 200);
+    assert!(text.contains("[truncated: "), "{text}");
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::query::{query_sources, retain_target_kinds};
+use crate::rust_language;
+use crate::types::TargetKind;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn module_files(root: &Path, include_tests: bool) -> Vec<(String, String)> {
+    let discovery = Discovery {
+        module_tree: true,
+        ..Discovery::default()
+    };
+    discover_sources(root, include_tests, &PathFilter::default(), discovery)
+        .unwrap()
+        .into_iter()
+        .map(|f| {
+            (
+                f.path.strip_prefix(root).unwrap().to_string_lossy().to_string(),
+                f.module_path.unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn follows_mod_declarations_from_target_roots() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='m'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "mod a;\npub mod b;\n/// docs\n#[path = \"../other/c_impl.rs\"]\n#[cfg(feature = \"c\")]\nmod c;\n\
+         mod inline {\n    mod deep;\n    #[path = \"renamed.rs\"]\n    mod r#type;\n}\n",
+    );
+    write(&root.join("src/a.rs"), "mod child;\n");
+    write(&root.join("src/a/child.rs"), "pub fn x() {}\n");
+    write(&root.join("src/b/mod.rs"), "mod grand;\n");
+    write(&root.join("src/b/grand.rs"), "\n");
+    write(&root.join("other/c_impl.rs"), "mod sub;\n");
+    write(&root.join("other/sub.rs"), "\n");
+    write(&root.join("src/inline/deep.rs"), "\n");
+    write(&root.join("src/inline/renamed.rs"), "\n");
+    write(&root.join("src/orphan.rs"), "pub fn unused() {}\n");
+    write(&root.join("src/main.rs"), "mod cli;\nfn main() {}\n");
+    write(&root.join("src/cli.rs"), "\n");
+    write(&root.join("tests/it.rs"), "mod common;\n");
+    write(&root.join("tests/common/mod.rs"), "\n");
+
+    let expected: Vec<(String, String)> = [
+        ("other/c_impl.rs", "crate::c"),
+        ("other/sub.rs", "crate::c::sub"),
+        ("src/a/child.rs", "crate::a::child"),
+        ("src/a.rs", "crate::a"),
+        ("src/b/grand.rs", "crate::b::grand"),
+        ("src/b/mod.rs", "crate::b"),
+        ("src/cli.rs", "crate::cli"),
+        ("src/inline/deep.rs", "crate::inline::deep"),
+        ("src/inline/renamed.rs", "crate::inline::type"),
+        ("src/lib.rs", "crate"),
+        ("src/main.rs", "crate"),
+    ]
+    .iter()
+    .map(|(f, m)| (f.to_string(), m.to_string()))
+    .collect();
+    assert_eq!(
+        module_files(root, false),
+        expected,
+        "orphans and test targets are left out"
+    );
+
+    let with_tests = module_files(root, true);
+    assert!(with_tests.contains(&("tests/common/mod.rs".to_string(), "crate::common".to_string())));
+    assert!(!with_tests.iter().any(|(f, _)| f == "src/orphan.rs"));
+}
+
+#[test]
+fn files_shared_by_several_targets_are_listed_for_each() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='m'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub mod util;\n");
+    write(
+        &root.join("src/main.rs"),
+        "#[path = \"util.rs\"]\nmod helpers;\nfn main() {}\n",
+    );
+    write(&root.join("src/util.rs"), "pub fn shared() {}\n");
+
+    let discovery = Discovery {
+        module_tree: true,
+        ..Discovery::default()
+    };
+    let files = discover_sources(root, false, &PathFilter::default(), discovery).unwrap();
+    let util: Vec<_> = files
+        .iter()
+        .filter(|f| f.path.ends_with("util.rs"))
+        .map(|f| (f.target.as_ref().unwrap().kind, f.module_path.as_deref().unwrap()))
+        .collect();
+    assert_eq!(
+        util,
+        [(TargetKind::Lib, "crate::util"), (TargetKind::Bin, "crate::helpers")]
+    );
+
+    // `--target-kind bin` keeps the bin's view of the shared file.
+    let q = "(function_item name: (identifier) @fn)";
+    let mut output = query_sources(&rust_language(), root, &files, q, false, None).unwrap();
+    retain_target_kinds(&mut output.crates, &[TargetKind::Bin]);
+    let bin: Vec<_> = output.crates[0]
+        .captures
+        .iter()
+        .map(|c| (c.text.as_str(), c.module_path.as_deref().unwrap()))
+        .collect();
+    assert_eq!(bin, [("main", "crate"), ("shared", "crate::helpers")]);
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::outline::{outline_file, render_outline};
+use crate::rust_language;
+use crate::types::ItemKind;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+#[test]
+fn outline_nests_items_and_renders_a_tree() {
+    let td = TempDir::new().unwrap();
+    let file = td.path().join("lib.rs");
+    write(
+        &file,
+        "mod parse {\n    pub fn tokenize() {\n        fn inner() {}\n    }\n    const N: u8 = 1;\n}\n\
+         struct Config;\n\
+         impl std::fmt::Display for Config {\n    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {\n        Ok(())\n    }\n}\n\
+         extern \"C\" {\n    fn abs(x: i32) -> i32;\n}\n",
+    );
+    let outline = outline_file(&rust_language(), &file).unwrap();
+    let top: Vec<_> = outline.items.iter().map(|i| (i.kind, i.name.as_str())).collect();
+    assert_eq!(
+        top,
+        vec![
+            (ItemKind::Mod, "parse"),
+            (ItemKind::Struct, "Config"),
+            (ItemKind::Impl, "impl std::fmt::Display for Config"),
+            (ItemKind::Fn, "abs"),
+        ]
+    );
+    // Function bodies are not descended into.
+    assert_eq!(outline.items[0].children.len(), 2);
+    assert!(outline.items[0].children[0].children.is_empty());
+
+    let rendered = render_outline(&outline);
+    let expected = format!(
+        "{}\n\
+         ├── mod parse  1-6\n\
+         │   ├── fn tokenize  2-4\n\
+         │   └── const N  5\n\
+         ├── struct Config  7\n\
+         ├── impl std::fmt::Display for Config  8-12\n\
+         │   └── fn fmt  9-11\n\
+         └── fn abs  14",
+        file.display()
+    );
+    assert_eq!(rendered, expected);
+}
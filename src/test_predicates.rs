@@ -0,0 +1,108 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::{execute_query, ArbolError, QueryErrorKind};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn fixture() -> TempDir {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='p'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "pub mod api;\npub fn exported() {}\npub(crate) fn internal() {}\nfn private() {\n    let a = 1;\n    let b = 2;\n}\n\
+         #[cfg(test)]\nmod tests {\n    // helper\n    #[test]\n    fn works() {}\n}\n",
+    );
+    write(&root.join("src/api/mod.rs"), "pub mod v1;\npub fn api_root() {}\n");
+    write(&root.join("src/api/v1.rs"), "pub fn v1_fn() {}\n");
+    td
+}
+
+fn names(root: &Path, query: &str) -> Vec<String> {
+    let out = execute_query(root, false, query, false).unwrap();
+    let mut names: Vec<_> = out
+        .crates
+        .iter()
+        .flat_map(|c| c.captures.iter().filter(|k| k.name == "name").map(|k| k.text.clone()))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn unknown_predicate_is_a_compile_error() {
+    let td = fixture();
+    let q = "((function_item name: (identifier) @name)\n (#is-public? @name))";
+    let err = execute_query(td.path(), false, q, false).unwrap_err();
+    let ArbolError::QueryCompile(diag) = err else {
+        panic!("expected QueryCompile, got {err:?}");
+    };
+    assert_eq!(diag.kind, QueryErrorKind::Predicate);
+    assert_eq!((diag.row, diag.column), (2, 3));
+    assert_eq!(diag.token, "#is-public?");
+    assert!(
+        diag.message.contains("unknown predicate `#is-public?`"),
+        "{}",
+        diag.message
+    );
+
+    let q = "((function_item body: (_) @body) (#line-count>? @body \"many\"))";
+    let err = execute_query(td.path(), false, q, false).unwrap_err();
+    assert!(err.to_string().contains("expects a line count"), "{err}");
+}
+
+#[test]
+fn builtin_text_predicates_still_apply() {
+    let td = fixture();
+    let q = "((function_item name: (identifier) @name) (#match? @name \"^v1\"))";
+    assert_eq!(names(td.path(), q), vec!["v1_fn"]);
+}
+
+#[test]
+fn is_pub_only_counts_bare_pub() {
+    let td = fixture();
+    let q = "((function_item name: (identifier) @name) (#is-pub? @name))";
+    assert_eq!(names(td.path(), q), vec!["api_root", "exported", "v1_fn"]);
+    let q = "((function_item name: (identifier) @name) (#not-is-pub? @name))";
+    assert_eq!(names(td.path(), q), vec!["internal", "private", "works"]);
+}
+
+#[test]
+fn has_attr_looks_past_comments() {
+    let td = fixture();
+    let q = "((function_item name: (identifier) @name) (#has-attr? @name \"test\"))";
+    assert_eq!(names(td.path(), q), vec!["works"]);
+    let q = "((mod_item name: (identifier) @name) @item (#has-attr? @item \"cfg\"))";
+    assert_eq!(names(td.path(), q), vec!["tests"]);
+}
+
+#[test]
+fn in_module_covers_descendants_and_inline_modules() {
+    let td = fixture();
+    let q = "((function_item name: (identifier) @name) (#in-module? \"crate::api\"))";
+    assert_eq!(names(td.path(), q), vec!["api_root", "v1_fn"]);
+    let q = "((function_item name: (identifier) @name) (#in-module? \"crate::tests\"))";
+    assert_eq!(names(td.path(), q), vec!["works"]);
+    let q = "((function_item name: (identifier) @name) (#not-in-module? \"crate::api\"))";
+    assert_eq!(names(td.path(), q), vec!["exported", "internal", "private", "works"]);
+}
+
+#[test]
+fn line_count_compares_node_span() {
+    let td = fixture();
+    let q = "((function_item name: (identifier) @name) @fn (#line-count>? @fn 2))";
+    assert_eq!(names(td.path(), q), vec!["private"]);
+}
@@ -3,7 +3,9 @@ use std::io::Write;
 
 use tempfile::TempDir;
 
-use crate::{dump_json, execute_query};
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::query::stream_captures;
+use crate::{dump_json, execute_query, execute_query_matches, rust_language, ArbolError, QueryErrorKind, QueryEvent};
 
 fn write(path: &std::path::Path, content: &str) {
     if let Some(parent) = path.parent() {
@@ -24,8 +26,8 @@ fn query_captures_function_names_deterministically() {
     write(&root.join("src/lib.rs"), r#"pub fn alpha(){} pub fn beta(){}"#);
 
     let q = "(function_item name: (identifier) @fn.name)";
-    let run1 = execute_query(root, false, q, false).unwrap();
-    let run2 = execute_query(root, false, q, false).unwrap();
+    let run1 = execute_query(root, false, q, false).unwrap().crates;
+    let run2 = execute_query(root, false, q, false).unwrap().crates;
     assert_eq!(run1, run2, "results should be deterministic");
 
     // Flatten captures
@@ -46,8 +48,8 @@ fn dump_json_respects_max_depth() {
         "[package]\nname='d'\nversion='0.0.0'\nedition='2021'\n",
     );
     write(&root.join("src/lib.rs"), r#"pub fn alpha(){}"#);
-    let all = dump_json(root, false, false, usize::MAX).unwrap();
-    let shallow = dump_json(root, false, false, 0).unwrap();
+    let all = dump_json(root, false, false, usize::MAX).unwrap().files;
+    let shallow = dump_json(root, false, false, 0).unwrap().files;
     assert_eq!(all.len(), shallow.len(), "same number of files");
     assert!(all[0].nodes.len() >= shallow[0].nodes.len());
     assert_eq!(shallow[0].nodes.len(), 1, "depth 0 should have only root node");
@@ -63,7 +65,7 @@ fn query_with_context_includes_line_text() {
     );
     write(&root.join("src/lib.rs"), "pub fn gamma(){}\n");
     let q = "(function_item name: (identifier) @fn.name)";
-    let res = execute_query(root, false, q, true).unwrap();
+    let res = execute_query(root, false, q, true).unwrap().crates;
     let cap = &res[0].captures[0];
     assert!(!cap.line_text.is_empty(), "expected line_text when context=true");
 }
@@ -78,7 +80,7 @@ fn query_without_context_has_empty_line_text() {
     );
     write(&root.join("src/lib.rs"), "pub fn delta(){}\n");
     let q = "(function_item name: (identifier) @fn.name)";
-    let res = execute_query(root, false, q, false).unwrap();
+    let res = execute_query(root, false, q, false).unwrap().crates;
     let cap = &res[0].captures[0];
     assert!(cap.line_text.is_empty(), "expected empty line_text when context=false");
 }
@@ -115,7 +117,7 @@ fn multi_crate_grouping() {
     );
     write(&root.join("b/src/lib.rs"), "pub fn b_fn(){}\n");
     let q = "(function_item name: (identifier) @fn.name)";
-    let res = execute_query(root, false, q, false).unwrap();
+    let res = execute_query(root, false, q, false).unwrap().crates;
     // We expect two crate groups
     assert_eq!(res.len(), 2, "expected captures grouped by crate");
     let mut names: Vec<_> = res
@@ -125,3 +127,152 @@ fn multi_crate_grouping() {
     names.sort();
     assert_eq!(names, vec!["a_fn", "b_fn"]);
 }
+
+#[test]
+fn captures_carry_package_identity_and_group_by_name() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    // Directory names sort opposite to package names
+    write(
+        &root.join("a_dir/Cargo.toml"),
+        "[package]\nname='zulu'\nversion='0.3.0'\nedition='2021'\n",
+    );
+    write(&root.join("a_dir/src/lib.rs"), "pub fn z_fn(){}\n");
+    write(
+        &root.join("b_dir/Cargo.toml"),
+        "[package]\nname='alpha'\nversion='1.0.0'\nedition='2024'\n",
+    );
+    write(&root.join("b_dir/src/lib.rs"), "pub fn a_fn(){}\n");
+    let q = "(function_item name: (identifier) @fn.name)";
+    let res = execute_query(root, false, q, false).unwrap().crates;
+    let names: Vec<_> = res.iter().map(|c| c.krate.as_ref().unwrap().name.clone()).collect();
+    assert_eq!(names, vec!["alpha", "zulu"], "groups ordered by package name");
+    let cap = &res[1].captures[0];
+    let krate = cap.krate.as_ref().unwrap();
+    assert_eq!((krate.version.as_str(), krate.edition.as_str()), ("0.3.0", "2021"));
+    assert!(!krate.workspace_member);
+}
+
+#[test]
+fn query_compile_error_is_structured() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn zeta(){}\n");
+    let q = "(function_item\n  name: (identifer) @fn.name)";
+    let err = execute_query(root, false, q, false).unwrap_err();
+    let ArbolError::QueryCompile(diag) = err else {
+        panic!("expected QueryCompile, got {err:?}");
+    };
+    assert_eq!(diag.kind, QueryErrorKind::NodeType);
+    assert_eq!((diag.row, diag.column), (2, 10));
+    assert_eq!(diag.token, "identifer");
+    assert_eq!(diag.snippet, "  name: (identifer) @fn.name)\n         ^");
+
+    let err = execute_query(root, false, "(function_item nme: (identifier))", false).unwrap_err();
+    let ArbolError::QueryCompile(diag) = err else {
+        panic!("expected QueryCompile, got {err:?}");
+    };
+    assert_eq!((diag.kind, diag.token.as_str()), (QueryErrorKind::Field, "nme"));
+}
+
+#[test]
+fn matches_group_captures_per_pattern_match() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='m'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "pub fn one(a: u8) {}\nfn two(b: u8, c: u16) {}\nstruct S;\n",
+    );
+    let q = "(function_item name: (identifier) @fn.name parameters: (parameters (parameter pattern: (identifier) @param) (parameter pattern: (identifier) @param)))\n(struct_item name: (type_identifier) @struct.name)";
+    let out = execute_query_matches(root, false, q, false).unwrap();
+    let matches = &out.crates[0].matches;
+    assert_eq!(matches.len(), 2, "one result per match, not per capture");
+
+    let two = &matches[0];
+    assert_eq!((two.line, two.column, two.pattern_index), (2, 4, 0));
+    assert_eq!(two.captures["fn.name"][0].text, "two");
+    let params: Vec<_> = two.captures["param"].iter().map(|n| n.text.as_str()).collect();
+    assert_eq!(params, vec!["b", "c"]);
+
+    let s = &matches[1];
+    assert_eq!(s.pattern_index, 1);
+    assert_eq!(s.captures.keys().collect::<Vec<_>>(), vec!["struct.name"]);
+    assert_eq!(s.captures["struct.name"][0].end_column, 9);
+}
+
+#[test]
+fn stream_captures_in_file_order_and_stops_early() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='s'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "fn z(){}\nfn y(){}\n");
+    write(&root.join("src/a.rs"), "fn x(){}\n");
+    fs::write(root.join("src/bad.rs"), b"fn \xff(){}\n").unwrap();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let q = "(function_item name: (identifier) @fn.name)";
+
+    let mut events = Vec::new();
+    stream_captures(&rust_language(), root, &files, q, false, None, |e| {
+        events.push(match e {
+            QueryEvent::Capture(c) => c.text,
+            QueryEvent::Diagnostic(d) => format!("skipped {}", d.file.rsplit('/').next().unwrap()),
+            QueryEvent::UnusedSuppression(s) => format!("unused {}", s.rule),
+        });
+        true
+    })
+    .unwrap();
+    assert_eq!(events, vec!["x", "skipped bad.rs", "z", "y"]);
+
+    let mut seen = 0;
+    stream_captures(&rust_language(), root, &files, q, false, None, |_| {
+        seen += 1;
+        false
+    })
+    .unwrap();
+    assert_eq!(seen, 1, "returning false stops the stream");
+}
+
+#[test]
+fn ignore_comments_suppress_captures_by_name() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='q'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "// arbol-ignore: fn.name\nfn hidden() {}\n/* arbol-ignore: fn.name, unrelated */\n\nfn shown_after_blank_is_hidden() {}\nfn shown() {}\n// arbol-ignore: fn.name\nstruct S;\n",
+    );
+    write(
+        &root.join("src/other.rs"),
+        "// arbol-ignore-file: fn.name\nfn a() {}\nfn b() {}\n",
+    );
+
+    let out = execute_query(root, false, "(function_item name: (identifier) @fn.name)", false).unwrap();
+    let names: Vec<_> = out.crates[0].captures.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(names, vec!["shown"]);
+    let unused: Vec<_> = out
+        .unused_suppressions
+        .iter()
+        .map(|s| (s.rule.as_str(), s.line))
+        .collect();
+    assert_eq!(unused, vec![("fn.name", 7)]);
+
+    let matches = execute_query_matches(root, false, "(function_item name: (identifier) @fn.name) @fn", false).unwrap();
+    let kept: Vec<_> = matches.crates[0]
+        .matches
+        .iter()
+        .map(|m| m.captures.keys().cloned().collect::<Vec<_>>())
+        .collect();
+    // `@fn` is never suppressed, so every match survives with only its `@fn.name` dropped.
+    assert_eq!(kept.len(), 5);
+    assert_eq!(kept[2], vec!["fn".to_string(), "fn.name".to_string()]);
+}
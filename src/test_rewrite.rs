@@ -0,0 +1,171 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::rewrite::{apply_rewrites, rewrite_sources, unified_diff, RewriteSpec, Template};
+use crate::{rust_language, ArbolError, FileErrorKind};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+const SWAP: &str =
+    "(call_expression function: (identifier) @f (#eq? @f \"foo\") arguments: (arguments (_) @a (_) @b)) @call";
+
+fn fixture(src: &str) -> TempDir {
+    let td = TempDir::new().unwrap();
+    write(
+        &td.path().join("Cargo.toml"),
+        "[package]\nname='r'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&td.path().join("src/lib.rs"), src);
+    td
+}
+
+#[test]
+fn template_interpolates_captures_and_keeps_other_braces() {
+    let t = Template::parse("bar({{b}}, {{ a }}, \"{{not a capture}} {}\")");
+    assert_eq!(t.captures(), vec!["b", "a"]);
+
+    let td = fixture("fn f() { foo(1, 2); }\n");
+    let root = td.path();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let spec = RewriteSpec {
+        query: SWAP,
+        template: &t,
+        capture: None,
+    };
+    let out = rewrite_sources(&rust_language(), root, &files, &spec).unwrap();
+    assert_eq!(
+        out.files[0].rewritten,
+        "fn f() { bar(2, 1, \"{{not a capture}} {}\"); }\n"
+    );
+
+    let t = Template::parse("{{missing}}");
+    let spec = RewriteSpec {
+        query: SWAP,
+        template: &t,
+        capture: None,
+    };
+    let err = rewrite_sources(&rust_language(), root, &files, &spec).unwrap_err();
+    assert!(matches!(err, ArbolError::InvalidRewrite(_)), "{err:?}");
+    assert!(err.to_string().contains("`{{missing}}`"), "{err}");
+}
+
+#[test]
+fn quantified_captures_expand_to_their_whole_span() {
+    let td = fixture("fn f() { foo(1,  2, 3); }\n");
+    let root = td.path();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let t = Template::parse("bar({{args}})");
+    let spec = RewriteSpec {
+        query: "(call_expression function: (identifier) @f arguments: (arguments ((_) @args (\",\")?)+)) @call",
+        template: &t,
+        capture: None,
+    };
+    let out = rewrite_sources(&rust_language(), root, &files, &spec).unwrap();
+    assert_eq!(out.files[0].rewritten, "fn f() { bar(1,  2, 3); }\n");
+}
+
+#[test]
+fn overlapping_edits_are_reported_and_diff_is_unified() {
+    let td = fixture("fn f() {\n    foo(foo(1, 2), 3);\n}\n\n\n\n\n\n\n\nfn g() { foo(4, 5) }");
+    let root = td.path();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let t = Template::parse("bar({{b}}, {{a}})");
+    let spec = RewriteSpec {
+        query: SWAP,
+        template: &t,
+        capture: None,
+    };
+    let out = rewrite_sources(&rust_language(), root, &files, &spec).unwrap();
+    assert_eq!(out.conflicts.len(), 1);
+    assert_eq!((out.conflicts[0].dropped.line, out.conflicts[0].dropped.column), (2, 9));
+    assert_eq!((out.conflicts[0].kept.line, out.conflicts[0].kept.column), (2, 5));
+
+    let file = &out.files[0];
+    assert_eq!(file.edits.len(), 2);
+    assert_eq!(
+        unified_diff(file, root),
+        "--- a/src/lib.rs\n+++ b/src/lib.rs\n\
+         @@ -1,5 +1,5 @@\n fn f() {\n-    foo(foo(1, 2), 3);\n+    bar(3, foo(1, 2));\n }\n \n \n\
+         @@ -8,4 +8,4 @@\n \n \n \n-fn g() { foo(4, 5) }\n\\ No newline at end of file\n+fn g() { bar(5, 4) }\n\\ No newline at end of file\n"
+    );
+}
+
+#[test]
+fn edits_introducing_syntax_errors_are_rejected() {
+    // The file already has a syntax error (`let = 1;`); only new errors count against an edit.
+    let td = fixture("fn f() {\n    let = 1;\n    foo(1, 2);\n    foo(3, 4);\n}\n");
+    let root = td.path();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let t = Template::parse("{{a}} + {{b}}");
+    let spec = RewriteSpec {
+        query: "(call_expression function: (identifier) @f arguments: (arguments (_) @a (_) @b (#eq? @a \"1\")))",
+        template: &t,
+        capture: Some("f"),
+    };
+    let out = rewrite_sources(&rust_language(), root, &files, &spec).unwrap();
+    assert!(out.rejected.is_empty(), "{:?}", out.rejected);
+    assert_eq!(out.files[0].edits.len(), 1);
+
+    let t = Template::parse("bar({{a}}, {{b}}");
+    let spec = RewriteSpec {
+        query: "(call_expression arguments: (arguments (_) @a (_) @b (#eq? @a \"3\"))) @call",
+        template: &t,
+        capture: None,
+    };
+    let out = rewrite_sources(&rust_language(), root, &files, &spec).unwrap();
+    assert!(out.files.is_empty());
+    assert_eq!(out.rejected.len(), 1);
+    let rejected = &out.rejected[0];
+    assert_eq!((rejected.edit.line, rejected.edit.column), (4, 5));
+    assert_eq!(rejected.errors[0].message, "missing `)`");
+}
+
+#[test]
+fn apply_skips_files_changed_since_they_were_read() {
+    let td = fixture("mod other;\nfn f() { foo(1, 2); }\n");
+    let root = td.path();
+    write(&root.join("src/other.rs"), "fn g() { foo(3, 4); }\n");
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let t = Template::parse("foo({{b}}, {{a}})");
+    let spec = RewriteSpec {
+        query: SWAP,
+        template: &t,
+        capture: None,
+    };
+    let mut out = rewrite_sources(&rust_language(), root, &files, &spec).unwrap();
+    assert_eq!(out.files.len(), 2);
+    write(
+        &root.join("src/other.rs"),
+        "fn g() { foo(3, 4); } // edited meanwhile\n",
+    );
+
+    apply_rewrites(&mut out).unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.rs")).unwrap(),
+        "mod other;\nfn f() { foo(2, 1); }\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("src/other.rs")).unwrap(),
+        "fn g() { foo(3, 4); } // edited meanwhile\n"
+    );
+    assert_eq!(out.files.len(), 1);
+    assert_eq!(out.diagnostics.len(), 1);
+    assert!(out.diagnostics[0].file.ends_with("other.rs"), "{:?}", out.diagnostics);
+    assert_eq!(out.diagnostics[0].kind, FileErrorKind::Modified);
+    let leftovers: Vec<_> = fs::read_dir(root.join("src"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .filter(|n| n.to_string_lossy().ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
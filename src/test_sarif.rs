@@ -0,0 +1,110 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::execute_query;
+use crate::rules::{render_message, QueryMetadata, Severity};
+use crate::sarif::{location, query_to_sarif};
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+#[test]
+fn metadata_is_read_from_leading_comments() {
+    let src = ";; Finds functions.\n; id: fns\n; message: found `{fn.name}`\n; severity: error\n; help: https://example.com/fns\n\n(function_item name: (identifier) @fn.name)\n; severity: note\n";
+    let meta = QueryMetadata::parse(src).unwrap();
+    assert_eq!(meta.id.as_deref(), Some("fns"));
+    assert_eq!(meta.message.as_deref(), Some("found `{fn.name}`"));
+    assert_eq!(
+        meta.severity,
+        Some(Severity::Error),
+        "comments after the query are not metadata"
+    );
+    assert_eq!(meta.help_uri.as_deref(), Some("https://example.com/fns"));
+    assert_eq!(
+        QueryMetadata::parse("(identifier) @x").unwrap(),
+        QueryMetadata::default()
+    );
+}
+
+#[test]
+fn message_template_keeps_unknown_placeholders() {
+    let rendered = render_message("`{a}` and {b} {", |k| (k == "a").then_some("x"));
+    assert_eq!(rendered, "`x` and {b} {");
+}
+
+#[test]
+fn captures_map_to_sarif_results() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='s'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn alpha() {}\nstruct Beta;\n");
+    let q = "; message: found {text}\n; severity: note\n(function_item name: (identifier) @fn.name)\n(struct_item name: (type_identifier) @struct.name)";
+    let output = execute_query(root, false, q, false).unwrap();
+    let log = query_to_sarif(&output, &QueryMetadata::parse(q).unwrap(), root);
+    let v = serde_json::to_value(&log).unwrap();
+    assert_eq!(v["version"], "2.1.0");
+    let run = &v["runs"][0];
+    let rule_ids: Vec<_> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(rule_ids, vec!["fn.name", "struct.name"]);
+    let result = &run["results"][1];
+    assert_eq!(result["ruleId"], "struct.name");
+    assert_eq!(result["ruleIndex"], 1);
+    assert_eq!(result["level"], "note");
+    assert_eq!(result["message"]["text"], "found Beta");
+    let loc = &result["locations"][0]["physicalLocation"];
+    assert_eq!(loc["artifactLocation"]["uri"], "src/lib.rs");
+    assert_eq!(
+        loc["region"],
+        serde_json::json!({"startLine": 2, "startColumn": 8, "endLine": 2, "endColumn": 12})
+    );
+}
+
+#[test]
+fn sarif_columns_count_utf16_code_units() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='s'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    // `é` is two bytes and one UTF-16 unit, `😀` four bytes and two units.
+    write(&root.join("src/lib.rs"), "const S: &str = \"é😀\"; fn gämma() {}\n");
+    let q = "(function_item name: (identifier) @fn.name)";
+    let output = execute_query(root, false, q, false).unwrap();
+    let log = query_to_sarif(&output, &QueryMetadata::default(), root);
+    let v = serde_json::to_value(&log).unwrap();
+    assert_eq!(
+        v["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"],
+        serde_json::json!({"startLine": 1, "startColumn": 27, "endLine": 1, "endColumn": 32})
+    );
+}
+
+#[test]
+fn sarif_uris_are_percent_encoded() {
+    let root = Path::new("/work/my crate");
+    let rel = location("/work/my crate/src/a b/ü.rs", None, root);
+    assert_eq!(rel.physical_location.artifact_location.uri, "src/a%20b/%C3%BC.rs");
+    assert_eq!(rel.physical_location.artifact_location.uri_base_id, Some("%SRCROOT%"));
+    let abs = location("/elsewhere/50%/x.rs", None, root);
+    assert_eq!(
+        abs.physical_location.artifact_location.uri,
+        "file:///elsewhere/50%25/x.rs"
+    );
+    assert_eq!(abs.physical_location.artifact_location.uri_base_id, None);
+}
@@ -0,0 +1,127 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::index::SourceIndex;
+use crate::rust_language;
+use crate::serve::Server;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn serve(root: &Path, requests: &[Value]) -> Vec<Value> {
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let index = SourceIndex::new(&rust_language(), root, &files).unwrap();
+    let input: String = requests.iter().map(|r| format!("{r}\n")).collect();
+    let mut output = Vec::new();
+    Server::new(index).run(input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect()
+}
+
+#[test]
+fn serve_answers_from_the_index_and_applies_file_changes() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='s'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "mod inner {\n    pub struct S;\n    impl S {\n        fn new() {}\n    }\n}\n",
+    );
+    let q = "(function_item name: (identifier) @fn.name)";
+    let responses = serve(
+        root,
+        &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "query", "params": {"query": q}}),
+            json!({"jsonrpc": "2.0", "method": "fileChanged", "params": {"path": "src/lib.rs", "text": "fn a() {}\nfn b() {}\n"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "query", "params": {"query": q, "matches": true}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "outline", "params": {"path": "src/lib.rs"}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "dumpFile", "params": {"path": "src/lib.rs", "maxDepth": 0}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "query", "params": {"query": "(function_item"}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "outline", "params": {"path": "src/missing.rs"}}),
+            json!({"jsonrpc": "2.0", "id": 7, "method": "nope"}),
+            json!({"jsonrpc": "2.0", "id": 8, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "id": 9, "method": "query", "params": {"query": q}}),
+        ],
+    );
+    let ids: Vec<_> = responses.iter().map(|r| r["id"].clone()).collect();
+    assert_eq!(ids, [1, 2, 3, 4, 5, 6, 7, 8].map(|i| json!(i)));
+
+    let captures = &responses[0]["result"]["crates"][0]["captures"];
+    assert_eq!(captures[0]["text"], "new");
+    // The notification replaced the buffer without touching the file on disk.
+    let matches = &responses[1]["result"]["crates"][0]["matches"];
+    assert_eq!(matches.as_array().unwrap().len(), 2);
+    assert_eq!(matches[1]["captures"]["fn.name"][0]["text"], "b");
+    assert!(fs::read_to_string(root.join("src/lib.rs"))
+        .unwrap()
+        .contains("mod inner"));
+
+    let items = &responses[2]["result"]["items"];
+    assert_eq!(items[0]["kind"], "fn");
+    assert_eq!(items[1]["name"], "b");
+    assert_eq!(responses[3]["result"]["root_kind"], "source_file");
+    assert_eq!(responses[3]["result"]["nodes"].as_array().unwrap().len(), 1);
+
+    assert_eq!(responses[4]["error"]["code"], -32001);
+    assert_eq!(responses[4]["error"]["data"]["kind"], "syntax");
+    assert_eq!(responses[5]["error"]["code"], -32602);
+    assert_eq!(responses[6]["error"]["code"], -32601);
+    assert_eq!(responses[7]["result"], Value::Null);
+}
+
+#[test]
+fn serve_reports_malformed_messages_and_reloads_files_from_disk() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "fn a() {}\n");
+    let changed = root.join("src/lib.rs");
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let mut server = Server::new(SourceIndex::new(&rust_language(), root, &files).unwrap());
+
+    write(&changed, "fn a() {}\nfn b() {}\n");
+    let reload = json!({"path": changed.canonicalize().unwrap()});
+    assert_eq!(
+        server.handle("fileChanged", reload.clone()).unwrap(),
+        json!({"change": "updated"})
+    );
+    assert_eq!(
+        server.handle("fileChanged", reload.clone()).unwrap(),
+        json!({"change": "unchanged"})
+    );
+    let outline = server.handle("outline", json!({"path": "src/lib.rs"})).unwrap();
+    assert_eq!(outline["items"].as_array().unwrap().len(), 2);
+    fs::remove_file(&changed).unwrap();
+    assert_eq!(
+        server.handle("fileChanged", reload).unwrap(),
+        json!({"change": "removed"})
+    );
+    assert!(server.handle("outline", json!({"path": "src/lib.rs"})).is_err());
+
+    let mut output = Vec::new();
+    server.run("not json\n{\"id\": 3}\n".as_bytes(), &mut output).unwrap();
+    let responses: Vec<Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(responses[0]["error"]["code"], -32700);
+    assert_eq!(responses[0]["id"], Value::Null);
+    assert_eq!(responses[1]["error"]["code"], -32600);
+    assert_eq!(responses[1]["id"], 3);
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::rust_language;
+use crate::symbols::{render_ctags, render_etags, symbols_sources};
+use crate::types::SymbolKind;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+fn fixture() -> TempDir {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='sym'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "pub mod config;\n\
+         /// Parses things.\n///\n/// More detail.\n#[derive(Debug)]\npub(crate) struct Parser;\n\
+         mod inner {\n    //// Not a doc comment.\n    pub const LIMIT: usize = 3;\n    \
+         macro_rules! m { () => {} }\n}\n\
+         pub trait Load {\n    /** Load it.\n     * Twice. */\n    fn load(&self);\n    type Out;\n}\n",
+    );
+    write(
+        &root.join("src/config/mod.rs"),
+        "pub struct Config<T>(T);\n\
+         impl<T> std::fmt::Display for Config<T> {\n    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {\n        fn local() {}\n        Ok(())\n    }\n}\n\
+         extern \"C\" {\n    pub static ERRNO: i32;\n}\n",
+    );
+    td
+}
+
+#[test]
+fn symbols_carry_qualified_paths_visibility_and_doc_summaries() {
+    let td = fixture();
+    let root = td.path();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let out = symbols_sources(&rust_language(), root, &files).unwrap();
+    let found: Vec<_> = out
+        .symbols
+        .iter()
+        .map(|s| (s.path.as_str(), s.kind, s.visibility.as_deref(), s.doc.as_deref()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("crate::config::Config", SymbolKind::Struct, Some("pub"), None),
+            ("crate::config::Config::fmt", SymbolKind::Method, None, None),
+            ("crate::config::ERRNO", SymbolKind::Static, Some("pub"), None),
+            ("crate::config", SymbolKind::Mod, Some("pub"), None),
+            (
+                "crate::Parser",
+                SymbolKind::Struct,
+                Some("pub(crate)"),
+                Some("Parses things.")
+            ),
+            ("crate::inner", SymbolKind::Mod, None, None),
+            ("crate::inner::LIMIT", SymbolKind::Const, Some("pub"), None),
+            ("crate::inner::m", SymbolKind::Macro, None, None),
+            ("crate::Load", SymbolKind::Trait, Some("pub"), None),
+            ("crate::Load::load", SymbolKind::Method, None, Some("Load it.")),
+            ("crate::Load::Out", SymbolKind::Type, None, None),
+        ]
+    );
+    let parser = &out.symbols[4];
+    assert_eq!(parser.krate.as_deref(), Some("sym"));
+    assert_eq!((parser.line, parser.column, parser.end_line), (6, 1, 6));
+}
+
+#[test]
+fn symbols_render_as_ctags_and_etags() {
+    let td = fixture();
+    let root = td.path();
+    let files = discover_sources(root, false, &PathFilter::default(), Discovery::default()).unwrap();
+    let out = symbols_sources(&rust_language(), root, &files).unwrap();
+
+    let ctags = render_ctags(&out.symbols, root);
+    let lines: Vec<_> = ctags.lines().filter(|l| !l.starts_with("!_TAG_")).collect();
+    assert_eq!(lines.len(), out.symbols.len());
+    assert!(lines.windows(2).all(|w| w[0] <= w[1]), "{lines:?}");
+    assert!(
+        lines.contains(&"fmt\tsrc/config/mod.rs\t3;\"\tP\tpath:crate::config::Config::fmt"),
+        "{lines:?}"
+    );
+
+    let etags = render_etags(&out.symbols, root).unwrap();
+    let sections: Vec<_> = etags.split('\x0c').skip(1).collect();
+    assert_eq!(sections.len(), 2);
+    let (header, body) = sections[1].trim_start_matches('\n').split_once('\n').unwrap();
+    assert_eq!(header, format!("src/lib.rs,{}", body.len()));
+    assert!(body.contains("pub(crate) struct Parser\x7fParser\x016,"), "{body:?}");
+    let lib = fs::read_to_string(root.join("src/lib.rs")).unwrap();
+    let offset = lib.find("pub(crate) struct").unwrap();
+    assert!(body.contains(&format!("\x016,{offset}\n")), "{body:?}");
+}
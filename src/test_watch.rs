@@ -0,0 +1,101 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use crate::fs::{discover_sources, Discovery, PathFilter};
+use crate::index::input_edit;
+use crate::rust_language;
+use crate::watch::WatchIndex;
+
+fn write(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut f = fs::File::create(path).unwrap();
+    f.write_all(content.as_bytes()).unwrap();
+}
+
+const UNWRAPS: &str =
+    r#"((call_expression function: (field_expression field: (field_identifier) @m)) @call (#eq? @m "unwrap"))"#;
+
+#[test]
+fn updates_report_added_and_removed_captures_per_file() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='w'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    let lib = root.join("src/lib.rs");
+    write(&lib, "fn a() { x.unwrap(); }\n");
+    let lang = rust_language();
+    let discover = || discover_sources(root, false, &PathFilter::default(), Discovery::default());
+    let (mut index, diagnostics) = WatchIndex::new(&lang, root, &discover().unwrap(), UNWRAPS, false).unwrap();
+    assert!(diagnostics.is_empty());
+    assert_eq!(index.total(), 2);
+    assert_eq!(index.snapshot(Vec::new()).deltas[0].added.len(), 2);
+
+    // Lines inserted above a capture only move it.
+    write(&lib, "// moved\nfn a() { x.unwrap(); }\nfn b() { y.unwrap(); }\n");
+    let update = index.update(std::slice::from_ref(&lib), discover).unwrap();
+    assert_eq!(update.total, 4);
+    assert_eq!(update.deltas.len(), 1);
+    let added: Vec<_> = update.deltas[0]
+        .added
+        .iter()
+        .map(|c| (c.line, c.column, c.name.as_str()))
+        .collect();
+    assert_eq!(added, [(3, 10, "call"), (3, 12, "m")]);
+    assert!(update.deltas[0].removed.is_empty());
+
+    // A file saved without changes produces no delta.
+    let update = index.update(std::slice::from_ref(&lib), discover).unwrap();
+    assert!(update.deltas.is_empty());
+
+    write(
+        &lib,
+        "// moved\nfn a() { x.expect(\"set\"); }\nfn b() { y.unwrap(); }\n",
+    );
+    let update = index.update(std::slice::from_ref(&lib), discover).unwrap();
+    assert_eq!(update.total, 2);
+    let removed: Vec<_> = update.deltas[0]
+        .removed
+        .iter()
+        .map(|c| (c.line, c.text.as_str()))
+        .collect();
+    assert_eq!(removed, [(2, "x.unwrap()"), (2, "unwrap")]);
+    assert!(update.deltas[0].added.is_empty());
+
+    // New files are found through discovery; removed ones drop all their captures.
+    let other = root.join("src/other.rs");
+    write(&other, "fn c() { z.unwrap(); }\n");
+    let update = index.update(std::slice::from_ref(&other), discover).unwrap();
+    assert_eq!((update.total, index.len()), (4, 2));
+    assert_eq!(update.deltas[0].file, other.display().to_string());
+    fs::remove_file(&lib).unwrap();
+    let update = index.update(std::slice::from_ref(&lib), discover).unwrap();
+    assert_eq!((update.total, index.len()), (2, 1));
+    assert_eq!(update.deltas[0].removed.len(), 2);
+}
+
+#[test]
+fn incremental_parse_matches_a_fresh_parse() {
+    let old = "fn é() {\n    let a = 1;\n}\n";
+    let new = "fn é() {\n    let a = 1;\n    let bé = a.unwrap();\n}\n";
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&rust_language()).unwrap();
+    let mut tree = parser.parse(old, None).unwrap();
+    let edit = input_edit(old, new);
+    assert_eq!(edit.start_byte, old.find("}\n").unwrap());
+    assert_eq!((edit.start_position.row, edit.new_end_position.row), (2, 3));
+    tree.edit(&edit);
+    let incremental = parser.parse(new, Some(&tree)).unwrap();
+    let fresh = parser.parse(new, None).unwrap();
+    assert_eq!(incremental.root_node().to_sexp(), fresh.root_node().to_sexp());
+
+    // A shared prefix or suffix never splits a multi-byte character.
+    let edit = input_edit("é", "è");
+    assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (0, 2, 2));
+}
@@ -1,8 +1,14 @@
-use serde::Serialize;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rules::Severity;
 
 #[derive(Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct FileAst {
     pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_path: Option<String>,
     pub root_kind: String,
     pub nodes: Vec<JsonNode>,
 }
@@ -15,24 +21,505 @@ pub struct JsonNode {
     pub start_line: u32,
     pub end_line: u32,
     pub child_count: u32,
+    /// The node is an `ERROR` node: source the grammar could not parse.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+    /// The node is a zero-width `MISSING` node inserted by error recovery.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_missing: bool,
+    /// The node is or contains an `ERROR` or `MISSING` node.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub has_error: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+/// Package identity read from a crate's `Cargo.toml`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CrateInfo {
+    pub name: String,
+    pub version: String,
+    pub edition: String,
+    pub workspace_member: bool,
+}
+
+/// Kind of Cargo target a source file is compiled into.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+    BuildScript,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target {
+    pub kind: TargetKind,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Capture {
     pub crate_path: String,
+    #[serde(rename = "crate", skip_serializing_if = "Option::is_none")]
+    pub krate: Option<CrateInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>,
     pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_path: Option<String>,
     pub line: usize,
     pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
     pub name: String,
     pub text: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub line_text: String,
+    /// The captured node is or contains an `ERROR` or `MISSING` node.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_error: bool,
 }
 
 #[derive(Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct CrateCaptures {
     pub crate_path: String,
+    #[serde(rename = "crate", skip_serializing_if = "Option::is_none")]
+    pub krate: Option<CrateInfo>,
     pub captures: Vec<Capture>,
 }
+
+/// One node bound to a capture name within a [`QueryMatch`]. Lines and columns are 1-based.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CapturedNode {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub line_text: String,
+    /// The captured node is or contains an `ERROR` or `MISSING` node.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_error: bool,
+}
+
+/// A single match of a query pattern, with every node it captured keyed by capture name.
+/// `line` / `column` is the position of the earliest captured node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QueryMatch {
+    pub crate_path: String,
+    #[serde(rename = "crate", skip_serializing_if = "Option::is_none")]
+    pub krate: Option<CrateInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>,
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_path: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub pattern_index: usize,
+    pub captures: BTreeMap<String, Vec<CapturedNode>>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrateMatches {
+    pub crate_path: String,
+    #[serde(rename = "crate", skip_serializing_if = "Option::is_none")]
+    pub krate: Option<CrateInfo>,
+    pub matches: Vec<QueryMatch>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileErrorKind {
+    Io,
+    InvalidUtf8,
+    Parse,
+    /// The file changed on disk after it was read, so rewriting it would lose those changes.
+    Modified,
+}
+
+/// A discovered file that was skipped because it could not be read or parsed.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileDiagnostic {
+    pub file: String,
+    pub kind: FileErrorKind,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueryOutput {
+    pub crates: Vec<CrateCaptures>,
+    pub diagnostics: Vec<FileDiagnostic>,
+    pub unused_suppressions: Vec<Suppression>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct MatchOutput {
+    pub crates: Vec<CrateMatches>,
+    pub diagnostics: Vec<FileDiagnostic>,
+    pub unused_suppressions: Vec<Suppression>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct DumpOutput {
+    pub files: Vec<FileAst>,
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// One streamed `dump-json` result; serialized with a `"type": "file" | "diagnostic"` tag.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DumpEvent {
+    File(FileAst),
+    Diagnostic(FileDiagnostic),
+}
+
+/// One streamed query result; serialized with a `"type": "capture" | "diagnostic" |
+/// "unused_suppression"` tag.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueryEvent {
+    Capture(Box<Capture>),
+    Diagnostic(FileDiagnostic),
+    UnusedSuppression(Suppression),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionScope {
+    /// `// arbol-ignore: id`: the next line of code.
+    NextLine,
+    /// `// arbol-ignore-file: id`: the whole file.
+    File,
+}
+
+/// One rule id named in an `arbol-ignore` comment, located at the comment. Lines and columns are
+/// 1-based.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Suppression {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub rule: String,
+    pub scope: SuppressionScope,
+}
+
+/// A lint rule match. Lines and columns are 1-based.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// The full (untrimmed) source line the finding starts on.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub source_line: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub help_uri: Option<String>,
+    /// Replacement produced by the rule's `rewrite` template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<Edit>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct LintOutput {
+    pub findings: Vec<Finding>,
+    pub diagnostics: Vec<FileDiagnostic>,
+    pub unused_suppressions: Vec<Suppression>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyntaxErrorKind {
+    Error,
+    Missing,
+}
+
+/// An `ERROR` or `MISSING` node found while parsing. Lines and columns are 1-based.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub kind: SyntaxErrorKind,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CheckOutput {
+    pub errors: Vec<SyntaxError>,
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// One replacement of a byte range of a file's original source. Lines and columns are 1-based.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub file: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub original: String,
+    pub replacement: String,
+}
+
+/// An edit that was dropped because its range overlaps an earlier edit of the same file.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EditConflict {
+    pub dropped: Edit,
+    pub kept: Edit,
+}
+
+/// The non-overlapping edits of one file, in source order, and the source they produce.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileRewrite {
+    pub file: String,
+    pub edits: Vec<Edit>,
+    #[serde(skip)]
+    pub source: String,
+    #[serde(skip)]
+    pub rewritten: String,
+}
+
+/// An edit refused because the source it produces has syntax errors the original did not.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RejectedEdit {
+    pub edit: Edit,
+    /// The new `ERROR` / `MISSING` nodes, positioned in the edited source.
+    pub errors: Vec<SyntaxError>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct RewriteOutput {
+    /// Files with at least one edit, in path order.
+    pub files: Vec<FileRewrite>,
+    pub conflicts: Vec<EditConflict>,
+    pub rejected: Vec<RejectedEdit>,
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// How the captures of one file changed. Captures are compared by capture name and text, so
+/// captures that only moved (lines inserted above them, say) are not reported.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CaptureDelta {
+    pub file: String,
+    /// Captures now present, positioned in the new source.
+    pub added: Vec<Capture>,
+    /// Captures gone, positioned in the previous source.
+    pub removed: Vec<Capture>,
+}
+
+/// The result of re-running a watched query after files changed.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct WatchUpdate {
+    /// Files whose captures changed, in path order.
+    pub deltas: Vec<CaptureDelta>,
+    /// Changed files that could not be read or parsed; their previous captures count as removed.
+    pub diagnostics: Vec<FileDiagnostic>,
+    /// Captures across every watched file after the update.
+    pub total: usize,
+}
+
+/// Kind of a named item in an [`OutlineItem`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Mod,
+    Fn,
+    Struct,
+    Enum,
+    Union,
+    Trait,
+    Impl,
+    Type,
+    Const,
+    Static,
+    Macro,
+}
+
+impl std::fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Mod => "mod",
+            Self::Fn => "fn",
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::Union => "union",
+            Self::Trait => "trait",
+            Self::Impl => "impl",
+            Self::Type => "type",
+            Self::Const => "const",
+            Self::Static => "static",
+            Self::Macro => "macro",
+        })
+    }
+}
+
+/// Kind of a [`Symbol`]: the [`ItemKind`]s other than `impl`, with functions of impls and traits
+/// told apart as methods.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Mod,
+    Fn,
+    Method,
+    Struct,
+    Enum,
+    Union,
+    Trait,
+    Type,
+    Const,
+    Static,
+    Macro,
+}
+
+impl std::fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Mod => "mod",
+            Self::Fn => "fn",
+            Self::Method => "method",
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::Union => "union",
+            Self::Trait => "trait",
+            Self::Type => "type",
+            Self::Const => "const",
+            Self::Static => "static",
+            Self::Macro => "macro",
+        })
+    }
+}
+
+/// A named item with its fully qualified path (`crate::module::Type::method`). Lines and columns are
+/// 1-based and span the whole item.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub path: String,
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The visibility modifier as written (`pub`, `pub(crate)`, ...); omitted for items without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    #[serde(rename = "crate", skip_serializing_if = "Option::is_none")]
+    pub krate: Option<String>,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// First line of the item's doc comment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SymbolOutput {
+    pub symbols: Vec<Symbol>,
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// What a public API entry is. Variants, fields and associated items are listed under their parent's
+/// path; `impl` entries are trait implementations on a public type.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKind {
+    Mod,
+    Fn,
+    Method,
+    Struct,
+    Field,
+    Enum,
+    Variant,
+    Union,
+    Trait,
+    Impl,
+    Type,
+    Const,
+    Static,
+    Macro,
+    /// A re-export of something outside the crate, whose signature is not known.
+    Use,
+}
+
+/// One public path of a library crate (`my_crate::config::Config::load`) and its signature, rendered
+/// from the source with comments, attributes and bodies dropped and whitespace normalized.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiItem {
+    pub path: String,
+    pub kind: ApiKind,
+    pub signature: String,
+    /// Where the item is defined, which for re-exports is not where its path points.
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApiOutput {
+    pub items: Vec<ApiItem>,
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// A public path whose signature differs between two versions. `file` and `line` point into the new one.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiChange {
+    pub path: String,
+    pub kind: ApiKind,
+    pub old: String,
+    pub new: String,
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApiDiff {
+    pub added: Vec<ApiItem>,
+    pub removed: Vec<ApiItem>,
+    pub changed: Vec<ApiChange>,
+}
+
+/// One item of a file outline: modules hold their items, impls and traits their associated items.
+/// Lines are 1-based and inclusive.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OutlineItem {
+    pub kind: ItemKind,
+    /// The item's name; `impl Trait for Type` / `impl Type` for impl blocks.
+    pub name: String,
+    pub line: usize,
+    pub end_line: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<OutlineItem>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileOutline {
+    pub file: String,
+    pub items: Vec<OutlineItem>,
+}
+
+/// How a file update changed an in-memory index.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Added,
+    Updated,
+    /// The new source is identical to the indexed one.
+    Unchanged,
+    /// The file no longer exists and was dropped.
+    Removed,
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::prelude::*;
+use tree_sitter::{InputEdit, Language};
+
+use crate::error::{ArbolError, Result};
+use crate::fs::{file_diagnostic, read_source, SourceFile};
+use crate::index::{input_edit, ParsedFile};
+use crate::query::{capture_order, compile_query, push_captures, query_tree, CompiledQuery};
+use crate::types::{Capture, CaptureDelta, FileDiagnostic, WatchUpdate};
+use crate::workspace::{discover_crates, CrateRoot};
+
+/// How long to keep collecting file events after the first one, so that a save touching several
+/// files (or one file several times) is handled as one update.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// A query kept running over a workspace: every file's source, tree and captures stay in memory so
+/// that a change only re-parses the files it touched, reusing their previous tree.
+pub struct WatchIndex {
+    lang: Language,
+    compiled: CompiledQuery,
+    crate_roots: Vec<CrateRoot>,
+    context: bool,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+struct IndexedFile {
+    parsed: ParsedFile,
+    captures: Vec<Capture>,
+}
+
+impl WatchIndex {
+    /// Parse and query every file in parallel. Files that cannot be read or parsed are returned as
+    /// diagnostics and picked up once they change.
+    pub fn new(
+        lang: &Language,
+        root: &Path,
+        files: &[SourceFile],
+        query_src: &str,
+        context: bool,
+    ) -> Result<(Self, Vec<FileDiagnostic>)> {
+        let mut index = Self {
+            lang: lang.clone(),
+            compiled: compile_query(lang, query_src)?,
+            crate_roots: discover_crates(root)?,
+            context,
+            files: BTreeMap::new(),
+        };
+        let results: Vec<std::result::Result<IndexedFile, FileDiagnostic>> = files
+            .par_iter()
+            .map(|source| {
+                let parsed = ParsedFile::read(&index.lang, source.clone())?;
+                Ok(index.query(parsed))
+            })
+            .collect();
+        let mut diagnostics = Vec::new();
+        for r in results {
+            match r {
+                Ok(file) => {
+                    index.files.insert(file.parsed.source.path.clone(), file);
+                }
+                Err(d) => diagnostics.push(d),
+            }
+        }
+        diagnostics.sort_by(|a, b| a.file.cmp(&b.file));
+        Ok((index, diagnostics))
+    }
+
+    /// Captures across every indexed file.
+    pub fn total(&self) -> usize {
+        self.files.values().map(|f| f.captures.len()).sum()
+    }
+
+    /// Number of indexed files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Current captures, in file and position order.
+    pub fn captures(&self) -> impl Iterator<Item = &Capture> {
+        self.files.values().flat_map(|f| f.captures.iter())
+    }
+
+    /// Every current capture reported as added, for the first report of a watch.
+    pub fn snapshot(&self, diagnostics: Vec<FileDiagnostic>) -> WatchUpdate {
+        WatchUpdate {
+            deltas: self
+                .files
+                .iter()
+                .filter(|(_, f)| !f.captures.is_empty())
+                .map(|(path, f)| capture_delta(path, &[], &f.captures, Some))
+                .collect(),
+            diagnostics,
+            total: self.total(),
+        }
+    }
+
+    /// Bring the index up to date after `changed` paths were written, created or removed.
+    /// `discover` lists the files that should be watched now; it is only called when a path is new
+    /// to the index or gone, or in module-tree mode where any edit may add or drop a `mod`, so that
+    /// discovery filters and module paths apply exactly as they did for the first run.
+    pub fn update(
+        &mut self,
+        changed: &[PathBuf],
+        discover: impl FnOnce() -> Result<Vec<SourceFile>>,
+    ) -> Result<WatchUpdate> {
+        let module_tree = self.files.values().any(|f| f.parsed.source.module_path.is_some());
+        let rediscover = module_tree || changed.iter().any(|p| !self.files.contains_key(p) || !p.is_file());
+        let mut paths: BTreeSet<PathBuf> = changed.iter().cloned().collect();
+        let mut sources: HashMap<PathBuf, SourceFile> = HashMap::new();
+        if rediscover {
+            for source in discover()? {
+                if !self.files.contains_key(&source.path)
+                    || self.files[&source.path].parsed.source.module_path != source.module_path
+                {
+                    paths.insert(source.path.clone());
+                }
+                sources.insert(source.path.clone(), source);
+            }
+            paths.extend(self.files.keys().filter(|p| !sources.contains_key(*p)).cloned());
+        } else {
+            sources.extend(paths.iter().map(|p| (p.clone(), self.files[p].parsed.source.clone())));
+        }
+
+        let mut update = WatchUpdate::default();
+        for path in paths {
+            let read = sources.remove(&path).map(|source| (read_source(&path), source));
+            if let Some((Ok(src), source)) = &read
+                && self
+                    .files
+                    .get(&path)
+                    .is_some_and(|old| old.parsed.src == *src && old.parsed.source == *source)
+            {
+                continue;
+            }
+            let old = self.files.remove(&path);
+            let new = match read {
+                Some((Ok(src), source)) => {
+                    match ParsedFile::parse(&self.lang, source, src, old.as_ref().map(|f| &f.parsed)) {
+                        Ok(parsed) => Some(self.query(parsed)),
+                        Err(d) => {
+                            update.diagnostics.push(d);
+                            None
+                        }
+                    }
+                }
+                Some((Err(e), _)) => {
+                    update.diagnostics.push(file_diagnostic(&path, &e));
+                    None
+                }
+                None => None,
+            };
+            let delta = match (&old, &new) {
+                (Some(old), Some(new)) => {
+                    let edit = input_edit(&old.parsed.src, &new.parsed.src);
+                    capture_delta(&path, &old.captures, &new.captures, |line| moved_line(&edit, line))
+                }
+                (Some(old), None) => capture_delta(&path, &old.captures, &[], Some),
+                (None, Some(new)) => capture_delta(&path, &[], &new.captures, Some),
+                (None, None) => continue,
+            };
+            if !delta.added.is_empty() || !delta.removed.is_empty() {
+                update.deltas.push(delta);
+            }
+            if let Some(file) = new {
+                self.files.insert(path, file);
+            }
+        }
+        update.total = self.total();
+        Ok(update)
+    }
+
+    /// Run the query over a freshly parsed file.
+    fn query(&self, parsed: ParsedFile) -> IndexedFile {
+        let capture_names = self.compiled.query.capture_names();
+        let (mut captures, _) = query_tree(
+            &parsed.source,
+            &self.crate_roots,
+            &self.compiled,
+            &parsed.src,
+            &parsed.tree,
+            self.context,
+            |ctx, m, out| push_captures(ctx, m, capture_names, out),
+        );
+        captures.sort_by(capture_order);
+        IndexedFile { parsed, captures }
+    }
+}
+
+/// Captures of `new` without a counterpart in `old`, and the reverse. A capture first pairs with one
+/// of the same name and text at the same column on the line it moved to (`line_map` maps an old
+/// 1-based line outside the edited lines to its new line), then with any other of the same name and
+/// text.
+fn capture_delta(
+    path: &Path,
+    old: &[Capture],
+    new: &[Capture],
+    line_map: impl Fn(usize) -> Option<usize>,
+) -> CaptureDelta {
+    let mut old_left = vec![true; old.len()];
+    let mut new_left = vec![true; new.len()];
+    let mut at: HashMap<(&str, &str, usize, usize), Vec<usize>> = HashMap::new();
+    for (j, c) in new.iter().enumerate().rev() {
+        at.entry((&c.name, &c.text, c.line, c.column)).or_default().push(j);
+    }
+    for (i, c) in old.iter().enumerate() {
+        if let Some(line) = line_map(c.line)
+            && let Some(j) = at.get_mut(&(&c.name, &c.text, line, c.column)).and_then(Vec::pop)
+        {
+            old_left[i] = false;
+            new_left[j] = false;
+        }
+    }
+    let mut same: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+    for (j, c) in new.iter().enumerate().rev().filter(|(j, _)| new_left[*j]) {
+        same.entry((&c.name, &c.text)).or_default().push(j);
+    }
+    for (i, c) in old.iter().enumerate() {
+        if old_left[i]
+            && let Some(j) = same.get_mut(&(&c.name, &c.text)).and_then(Vec::pop)
+        {
+            old_left[i] = false;
+            new_left[j] = false;
+        }
+    }
+    CaptureDelta {
+        file: path.display().to_string(),
+        added: new
+            .iter()
+            .zip(new_left)
+            .filter(|(_, left)| *left)
+            .map(|(c, _)| c.clone())
+            .collect(),
+        removed: old
+            .iter()
+            .zip(old_left)
+            .filter(|(_, left)| *left)
+            .map(|(c, _)| c.clone())
+            .collect(),
+    }
+}
+
+/// Where a line of the old source ended up after `edit`; `None` for lines the edit touched.
+fn moved_line(edit: &InputEdit, line: usize) -> Option<usize> {
+    let row = line - 1;
+    if row < edit.start_position.row {
+        Some(line)
+    } else if row > edit.old_end_position.row {
+        Some(line + edit.new_end_position.row - edit.old_end_position.row)
+    } else {
+        None
+    }
+}
+
+/// Recursive file system watcher reporting changed `.rs` files below a root.
+pub struct FileWatcher {
+    // Kept alive for as long as events are wanted.
+    _watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    root: PathBuf,
+    canonical_root: PathBuf,
+}
+
+impl FileWatcher {
+    pub fn new(root: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|e| ArbolError::Watch(e.to_string()))?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(|e| ArbolError::Watch(e.to_string()))?;
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            root: root.to_path_buf(),
+            canonical_root: root.canonicalize()?,
+        })
+    }
+
+    /// Block until `.rs` files change and return them, sorted and deduplicated, spelled like
+    /// discovered files (joined onto the root as given). Returns `None` once the watcher stops.
+    pub fn next_changes(&self) -> Option<Vec<PathBuf>> {
+        let mut changed = BTreeSet::new();
+        let mut event = self.events.recv().ok()?;
+        loop {
+            match event {
+                Ok(e) if !matches!(e.kind, EventKind::Access(_)) => {
+                    changed.extend(e.paths.iter().filter_map(|p| self.relative(p)));
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("watch: {e}"),
+            }
+            event = match self.events.recv_timeout(SETTLE) {
+                Ok(next) => next,
+                Err(mpsc::RecvTimeoutError::Timeout) if !changed.is_empty() => {
+                    return Some(changed.into_iter().collect())
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => self.events.recv().ok()?,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+            };
+        }
+    }
+
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        if path.extension().and_then(|s| s.to_str()) != Some("rs") {
+            return None;
+        }
+        let rel = path
+            .strip_prefix(&self.canonical_root)
+            .or_else(|_| path.strip_prefix(&self.root))
+            .ok()?;
+        Some(self.root.join(rel))
+    }
+}
@@ -1,8 +1,33 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use globset::{GlobBuilder, GlobSetBuilder};
+use toml::{Table, Value};
+use tracing::warn;
 use walkdir::WalkDir;
 
 use crate::error::Result;
+use crate::types::{CrateInfo, Target, TargetKind};
+
+/// A directory containing a `Cargo.toml`, with the parsed package identity and targets when the
+/// manifest declares a `[package]` (virtual workspace manifests have neither).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CrateRoot {
+    pub(crate) path: PathBuf,
+    pub(crate) info: Option<CrateInfo>,
+    pub(crate) targets: Vec<TargetRoot>,
+}
+
+/// A Cargo target and its root source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TargetRoot {
+    pub(crate) target: Target,
+    pub(crate) root_file: PathBuf,
+    /// Directory holding the target's non-root modules: the root file's own directory, which is where a
+    /// crate root resolves `mod foo;` (`src/` for `src/lib.rs`, `src/bin/` for `src/bin/foo.rs`). `None`
+    /// for build scripts, whose modules would otherwise claim the crate.
+    module_dir: Option<PathBuf>,
+}
 
 pub(crate) fn discover_crate_roots(root: &Path) -> Result<Vec<PathBuf>> {
     let mut crates = Vec::new();
@@ -18,11 +43,312 @@ pub(crate) fn discover_crate_roots(root: &Path) -> Result<Vec<PathBuf>> {
     Ok(crates)
 }
 
-pub(crate) fn crate_for_file(file: &Path, crate_roots: &[PathBuf]) -> Option<String> {
-    for root in crate_roots {
-        if file.starts_with(root) {
-            return Some(root.display().to_string());
+/// Like [`discover_crate_roots`], but parses each manifest for the package identity. Workspace
+/// roots are looked up on disk, so they may live above `root`.
+pub(crate) fn discover_crates(root: &Path) -> Result<Vec<CrateRoot>> {
+    let mut manifests = Manifests::default();
+    let crates = discover_crate_roots(root)?
+        .into_iter()
+        .map(|path| {
+            let info = manifests.crate_info(&path);
+            let targets = manifests
+                .get(&path)
+                .map(|m| discover_targets(&path, m))
+                .unwrap_or_default();
+            CrateRoot { path, info, targets }
+        })
+        .collect();
+    Ok(crates)
+}
+
+/// Deepest crate root containing `file`; `crates` must be sorted deepest first.
+pub(crate) fn crate_for_file<'a>(file: &Path, crates: &'a [CrateRoot]) -> Option<&'a CrateRoot> {
+    crates.iter().find(|c| file.starts_with(&c.path))
+}
+
+/// Cargo target compiling `file`: the target whose root file it is, or else the one whose module
+/// directory most closely contains it. The library wins over `src/main.rs` for shared `src/` modules;
+/// a directory that is home to several other roots (`tests/`, `src/bin/`) belongs to none of them.
+pub(crate) fn target_for_file(file: &Path, krate: &CrateRoot) -> Option<Target> {
+    target_root_for_file(file, krate).map(|t| t.target.clone())
+}
+
+fn target_root_for_file<'a>(file: &Path, krate: &'a CrateRoot) -> Option<&'a TargetRoot> {
+    if let Some(t) = krate.targets.iter().find(|t| t.root_file == file) {
+        return Some(t);
+    }
+    let candidates: Vec<(&TargetRoot, usize)> = krate
+        .targets
+        .iter()
+        .filter_map(|t| Some((t, t.module_dir.as_ref()?)))
+        .filter(|(_, dir)| file.starts_with(dir))
+        .map(|(t, dir)| (t, dir.components().count()))
+        .collect();
+    let deepest = candidates.iter().map(|&(_, depth)| depth).max()?;
+    let closest: Vec<&TargetRoot> = candidates
+        .into_iter()
+        .filter(|&(_, depth)| depth == deepest)
+        .map(|(t, _)| t)
+        .collect();
+    match closest.iter().find(|t| t.target.kind == TargetKind::Lib) {
+        Some(lib) => Some(lib),
+        None if closest.len() == 1 => Some(closest[0]),
+        None => None,
+    }
+}
+
+/// Module path of `file` guessed from where it sits below its target's module directory
+/// (`src/a/b.rs` and `src/a/b/mod.rs` are both `crate::a::b`), without following `mod` declarations.
+pub(crate) fn infer_module_path(file: &Path, krate: &CrateRoot) -> Option<String> {
+    let target = target_root_for_file(file, krate)?;
+    if target.root_file == file {
+        return Some("crate".to_string());
+    }
+    let rel = file.strip_prefix(target.module_dir.as_ref()?).ok()?.with_extension("");
+    let mut module = "crate".to_string();
+    for c in rel.components() {
+        let name = c.as_os_str().to_str()?;
+        if name != "mod" {
+            module.push_str("::");
+            module.push_str(name);
+        }
+    }
+    Some(module)
+}
+
+/// Targets declared in the manifest (`[lib]`, `[[bin]]`, `[[example]]`, `[[test]]`, `[[bench]]`,
+/// `package.build`) plus those found by Cargo's auto-discovery conventions.
+fn discover_targets(dir: &Path, manifest: &Table) -> Vec<TargetRoot> {
+    let Some(package) = manifest.get("package").and_then(Value::as_table) else {
+        return Vec::new();
+    };
+    let Some(pkg_name) = package.get("name").and_then(Value::as_str) else {
+        return Vec::new();
+    };
+    let mut targets = Vec::new();
+
+    let lib = manifest.get("lib").and_then(Value::as_table);
+    let lib_path = lib
+        .and_then(|l| l.get("path")?.as_str())
+        .map(|p| dir.join(p))
+        .or_else(|| Some(dir.join("src/lib.rs")).filter(|p| p.is_file()));
+    if let Some(root_file) = lib_path {
+        let name = lib
+            .and_then(|l| l.get("name")?.as_str())
+            .map_or_else(|| pkg_name.replace('-', "_"), str::to_string);
+        targets.push(target_root(TargetKind::Lib, name, root_file));
+    }
+
+    for (kind, key, auto_key, subdir) in [
+        (TargetKind::Bin, "bin", "autobins", "src/bin"),
+        (TargetKind::Example, "example", "autoexamples", "examples"),
+        (TargetKind::Test, "test", "autotests", "tests"),
+        (TargetKind::Bench, "bench", "autobenches", "benches"),
+    ] {
+        let start = targets.len();
+        let explicit = manifest.get(key).and_then(Value::as_array);
+        for entry in explicit.into_iter().flatten().filter_map(Value::as_table) {
+            let Some(name) = entry.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            let root_file = match entry.get("path").and_then(Value::as_str) {
+                Some(p) => dir.join(p),
+                None => {
+                    let mut candidates = vec![
+                        dir.join(subdir).join(format!("{name}.rs")),
+                        dir.join(subdir).join(name).join("main.rs"),
+                    ];
+                    if kind == TargetKind::Bin && name == pkg_name {
+                        candidates.insert(0, dir.join("src/main.rs"));
+                    }
+                    match candidates.into_iter().find(|p| p.is_file()) {
+                        Some(p) => p,
+                        None => continue,
+                    }
+                }
+            };
+            targets.push(target_root(kind, name.to_string(), root_file));
+        }
+        if package.get(auto_key).and_then(Value::as_bool) == Some(false) {
+            continue;
+        }
+        let mut found = Vec::new();
+        if kind == TargetKind::Bin && dir.join("src/main.rs").is_file() {
+            found.push((pkg_name.to_string(), dir.join("src/main.rs")));
+        }
+        found.extend(auto_discover(&dir.join(subdir)));
+        for (name, root_file) in found {
+            let claimed = targets[start..]
+                .iter()
+                .any(|t| t.target.name == name || t.root_file == root_file);
+            if !claimed {
+                targets.push(target_root(kind, name, root_file));
+            }
+        }
+    }
+
+    let build = match package.get("build") {
+        Some(Value::String(p)) => Some(dir.join(p)),
+        Some(Value::Boolean(false)) => None,
+        _ => Some(dir.join("build.rs")).filter(|p| p.is_file()),
+    };
+    if let Some(root_file) = build {
+        targets.push(TargetRoot {
+            target: Target {
+                kind: TargetKind::BuildScript,
+                name: "build-script-build".to_string(),
+            },
+            root_file,
+            module_dir: None,
+        });
+    }
+    targets
+}
+
+fn target_root(kind: TargetKind, name: String, root_file: PathBuf) -> TargetRoot {
+    let module_dir = root_file.parent().map(Path::to_path_buf);
+    TargetRoot {
+        target: Target { kind, name },
+        root_file,
+        module_dir,
+    }
+}
+
+/// Cargo's auto-discovered targets in `dir`: `<name>.rs` and `<name>/main.rs`, sorted by name.
+fn auto_discover(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut found: Vec<(String, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            if path.is_dir() {
+                let main = path.join("main.rs");
+                let name = path.file_name()?.to_str()?.to_string();
+                main.is_file().then_some((name, main))
+            } else if path.extension().and_then(|s| s.to_str()) == Some("rs") {
+                Some((path.file_stem()?.to_str()?.to_string(), path))
+            } else {
+                None
+            }
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// Parsed manifests keyed by directory; `None` when missing or unparsable.
+#[derive(Default)]
+struct Manifests {
+    cache: HashMap<PathBuf, Option<Table>>,
+}
+
+impl Manifests {
+    fn get(&mut self, dir: &Path) -> Option<&Table> {
+        self.cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| read_manifest(&dir.join("Cargo.toml")))
+            .as_ref()
+    }
+
+    fn crate_info(&mut self, dir: &Path) -> Option<CrateInfo> {
+        let package = self.get(dir)?.get("package")?.as_table()?.clone();
+        let name = package.get("name")?.as_str()?.to_string();
+        // Work on absolute paths so the ancestor walk can leave a relative scan root.
+        let dir = &std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        let ws_root = self.workspace_root(dir, &package);
+        let inherited = ws_root
+            .as_deref()
+            .and_then(|r| self.get(r))
+            .and_then(|m| m.get("workspace")?.get("package")?.as_table())
+            .cloned()
+            .unwrap_or_default();
+        let field = |key: &str, default: &str| match package.get(key) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Table(t)) if t.get("workspace").and_then(Value::as_bool) == Some(true) => inherited
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or(default)
+                .to_string(),
+            _ => default.to_string(),
+        };
+        let version = field("version", "0.0.0");
+        let edition = field("edition", "2015");
+        let workspace_member = match &ws_root {
+            Some(r) => self.is_member(r, dir),
+            None => false,
+        };
+        Some(CrateInfo {
+            name,
+            version,
+            edition,
+            workspace_member,
+        })
+    }
+
+    /// Workspace root for the package in `dir`: an explicit `package.workspace` path, or else the
+    /// nearest directory (including `dir` itself) whose manifest has a `[workspace]` table.
+    fn workspace_root(&mut self, dir: &Path, package: &Table) -> Option<PathBuf> {
+        if let Some(explicit) = package.get("workspace").and_then(Value::as_str) {
+            let joined = dir.join(explicit);
+            return Some(std::fs::canonicalize(&joined).unwrap_or(joined));
+        }
+        let mut cur = Some(dir);
+        while let Some(d) = cur {
+            if self.get(d).is_some_and(|m| m.contains_key("workspace")) {
+                return Some(d.to_path_buf());
+            }
+            cur = d.parent();
+        }
+        None
+    }
+
+    fn is_member(&mut self, ws_root: &Path, dir: &Path) -> bool {
+        if ws_root == dir {
+            return true;
+        }
+        let Ok(rel) = dir.strip_prefix(ws_root) else {
+            return false;
+        };
+        let Some(workspace) = self.get(ws_root).and_then(|m| m.get("workspace")) else {
+            return false;
+        };
+        let list = |key: &str| -> Vec<String> {
+            workspace
+                .get(key)
+                .and_then(Value::as_array)
+                .map(|a| a.iter().filter_map(Value::as_str).map(trim_dot_slash).collect())
+                .unwrap_or_default()
+        };
+        let (members, exclude) = (list("members"), list("exclude"));
+        if exclude.iter().any(|e| rel.starts_with(e)) {
+            return false;
+        }
+        let mut set = GlobSetBuilder::new();
+        for m in &members {
+            match GlobBuilder::new(m).literal_separator(true).build() {
+                Ok(g) => {
+                    set.add(g);
+                }
+                Err(e) => warn!("workspace: invalid member glob {m:?} in {}: {e}", ws_root.display()),
+            }
+        }
+        set.build().is_ok_and(|s| s.is_match(rel))
+    }
+}
+
+fn trim_dot_slash(s: &str) -> String {
+    s.trim_start_matches("./").trim_end_matches('/').to_string()
+}
+
+fn read_manifest(path: &Path) -> Option<Table> {
+    let src = std::fs::read_to_string(path).ok()?;
+    match src.parse::<Table>() {
+        Ok(t) => Some(t),
+        Err(e) => {
+            warn!("manifest: failed to parse {}: {e}", path.display());
+            None
         }
     }
-    None
 }
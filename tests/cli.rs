@@ -4,6 +4,7 @@ use std::path::Path;
 use std::process::Command;
 
 use assert_cmd::prelude::*;
+use predicates::prelude::*;
 use predicates::str::contains;
 use tempfile::TempDir;
 
@@ -28,7 +29,8 @@ fn cli_dump_json_depth_zero_outputs_json_array() {
     cmd.current_dir(root).args(["dump-json", "--max-depth", "0"]);
     let out = cmd.assert().success().get_output().stdout.clone();
     let v: serde_json::Value = serde_json::from_slice(&out).expect("valid json");
-    assert!(v.as_array().is_some(), "top level should be array");
+    assert!(v["files"].as_array().is_some(), "files should be an array");
+    assert_eq!(v["diagnostics"], serde_json::json!([]), "no skipped files");
 }
 
 #[test]
@@ -49,12 +51,101 @@ fn cli_query_json_mode_captures_function() {
     ]);
     let out = cmd.assert().success().get_output().stdout.clone();
     let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
-    let arr = v.as_array().unwrap();
+    let arr = v["crates"].as_array().unwrap();
     assert!(!arr.is_empty());
     let captures = &arr[0]["captures"];
     assert!(captures.to_string().contains("alpha"));
 }
 
+#[test]
+fn cli_query_matches_mode_groups_captures() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_m'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn alpha(x: u8) {}\n");
+    let q =
+        "(function_item name: (identifier) @fn.name parameters: (parameters (parameter pattern: (identifier) @param)))";
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["query", "--expr", q, "--matches", "--json"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let m = &v["crates"][0]["matches"][0];
+    assert_eq!(m["pattern_index"], 0);
+    assert_eq!(m["captures"]["fn.name"][0]["text"], "alpha");
+    assert_eq!(m["captures"]["param"][0]["text"], "x");
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["query", "--expr", q, "--matches"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("#0 @fn.name=`alpha` @param=`x`"))
+        .stdout(contains("-- total matches: 1"));
+}
+
+#[test]
+fn cli_ndjson_streams_one_object_per_line() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_nd'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn alpha(){} pub fn beta(){}\n");
+    fs::write(root.join("src/bad.rs"), b"\xff\n").unwrap();
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args([
+        "query",
+        "--expr",
+        "(function_item name: (identifier) @fn.name)",
+        "--ndjson",
+    ]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let lines: Vec<serde_json::Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("each line is a JSON object"))
+        .collect();
+    let types: Vec<_> = lines.iter().map(|v| v["type"].as_str().unwrap()).collect();
+    assert_eq!(types, vec!["diagnostic", "capture", "capture"]);
+    assert_eq!(lines[1]["text"], "alpha");
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["dump-json", "--ndjson", "--max-depth", "0"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.lines().any(|l| l.starts_with(r#"{"type":"file","path":"#)));
+}
+
+#[test]
+fn cli_query_sarif_format() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_sarif'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn alpha(){}\n");
+    write(
+        &root.join("fns.scm"),
+        "; message: function {fn.name}\n; severity: error\n; help: https://example.com/fns\n(function_item name: (identifier) @fn.name)\n",
+    );
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["query", "--query-file", "fns.scm", "--format", "sarif"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let run = &v["runs"][0];
+    assert_eq!(run["tool"]["driver"]["rules"][0]["helpUri"], "https://example.com/fns");
+    assert_eq!(run["results"][0]["level"], "error");
+    assert_eq!(run["results"][0]["message"]["text"], "function alpha");
+}
+
 #[test]
 fn cli_query_plain_text_contains_crate_header() {
     let td = TempDir::new().unwrap();
@@ -96,3 +187,545 @@ fn cli_respects_skip_dir_flag() {
     assert!(text.contains("a"), "expected function a present");
     assert!(!text.contains("auto"), "expected skipped function auto not present");
 }
+
+#[test]
+fn cli_no_ignore_flag_includes_gitignored_files() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_ignore'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join(".gitignore"), "src/vendored.rs\n");
+    write(&root.join("src/lib.rs"), "pub fn kept(){}\n");
+    write(&root.join("src/vendored.rs"), "pub fn vendored(){}\n");
+    let expr = "(function_item name: (identifier) @fn.name)";
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["query", "--expr", expr, "--json"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains("kept"));
+    assert!(
+        !text.contains("vendored"),
+        "gitignored file should be skipped by default"
+    );
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["query", "--expr", expr, "--json", "--no-ignore"]);
+    cmd.assert().success().stdout(contains("vendored"));
+}
+
+#[test]
+fn cli_include_and_exclude_globs() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_glob'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn root_fn(){}\n");
+    write(&root.join("src/rapid_api.rs"), "pub fn rapid(){}\n");
+    write(&root.join("src/api/mod.rs"), "pub fn api_fn(){}\n");
+    write(&root.join("examples/demo.rs"), "fn demo(){}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args([
+        "query",
+        "--expr",
+        "(function_item name: (identifier) @fn.name)",
+        "--json",
+        "--include",
+        "src/",
+        "--exclude",
+        "src/api/",
+    ]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains("root_fn") && text.contains("rapid"));
+    assert!(!text.contains("api_fn"), "excluded directory should be skipped");
+    assert!(!text.contains("demo"), "file outside include set should be skipped");
+}
+
+#[test]
+fn cli_target_kind_filter() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_targets'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn lib_fn(){}\n");
+    write(&root.join("src/main.rs"), "fn main(){}\n");
+    write(&root.join("examples/demo.rs"), "fn demo_fn(){}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args([
+        "query",
+        "--expr",
+        "(function_item name: (identifier) @fn.name)",
+        "--json",
+        "--target-kind",
+        "bin",
+        "--target-kind",
+        "example",
+    ]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let mut kinds: Vec<String> = v["crates"][0]["captures"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            format!(
+                "{}:{}",
+                c["target"]["kind"].as_str().unwrap(),
+                c["text"].as_str().unwrap()
+            )
+        })
+        .collect();
+    kinds.sort();
+    assert_eq!(kinds, vec!["bin:main", "example:demo_fn"]);
+}
+
+#[test]
+fn cli_module_tree_skips_orphans_and_reports_module_path() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_mods'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub mod api;\n");
+    write(&root.join("src/api.rs"), "pub fn reachable(){}\n");
+    write(&root.join("src/orphan.rs"), "pub fn orphaned(){}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args([
+        "query",
+        "--expr",
+        "(function_item name: (identifier) @fn.name)",
+        "--json",
+        "--module-tree",
+    ]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let captures = v["crates"][0]["captures"].as_array().unwrap();
+    assert_eq!(captures.len(), 1, "orphaned file should not be scanned: {v}");
+    assert_eq!(captures[0]["text"], "reachable");
+    assert_eq!(captures[0]["module_path"], "crate::api");
+}
+
+#[test]
+fn cli_query_compile_error_as_json() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn a(){}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args([
+        "query",
+        "--expr",
+        "(function_item name: (identifier) @fn.name",
+        "--json",
+    ]);
+    let out = cmd.assert().failure().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).expect("valid json error");
+    assert_eq!(v["error"]["kind"], "syntax");
+    assert_eq!(v["error"]["row"], 1);
+    assert!(v["error"]["snippet"].as_str().unwrap().ends_with('^'));
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["query", "--expr", "(function_item name: (identifer))"]);
+    cmd.assert().failure().stderr(contains("invalid node type `identifer`"));
+}
+
+#[test]
+fn cli_reports_unreadable_files_as_diagnostics() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_diag'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn good(){}\n");
+    fs::write(root.join("src/bad.rs"), b"pub fn bad(){} // \xff\xfe\n").unwrap();
+    let expr = "(function_item name: (identifier) @fn.name)";
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["query", "--expr", expr, "--json"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let diags = v["diagnostics"].as_array().unwrap();
+    assert_eq!(diags.len(), 1, "{v}");
+    assert!(diags[0]["file"].as_str().unwrap().ends_with("bad.rs"));
+    assert_eq!(diags[0]["kind"], "invalid_utf8");
+    assert!(v["crates"].to_string().contains("good"));
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["query", "--expr", expr]);
+    cmd.assert().success().stdout(contains("== Skipped files =="));
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["dump-json", "--max-depth", "0", "--fail-on-file-errors"]);
+    let out = cmd.assert().code(2).get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["files"].as_array().unwrap().len(), 1);
+    assert_eq!(v["diagnostics"][0]["kind"], "invalid_utf8");
+}
+
+#[test]
+fn cli_check_lists_syntax_errors_and_exits_nonzero() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn ok(){}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["check"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("-- 0 syntax error(s) in 0 file(s)"));
+
+    write(&root.join("src/broken.rs"), "fn a() {\n    let x = ;\n}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["check"]);
+    cmd.assert()
+        .code(1)
+        .stdout(contains("broken.rs:2:11: error: unexpected `=`"));
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["check", "--json"]);
+    let out = cmd.assert().code(1).get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["errors"][0]["line"], 2);
+    assert_eq!(v["errors"][0]["kind"], "error");
+}
+
+#[test]
+fn cli_lint_prints_diagnostics_and_fails_on_errors() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_lint'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "pub fn ab(){}\n");
+    write(
+        &root.join(".arbol/rules/pub-fn.scm"),
+        "; message: public `{name}`\n; severity: warning\n((function_item name: (identifier) @name) @fn (#is-pub? @fn))\n",
+    );
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).arg("lint");
+    cmd.assert()
+        .success()
+        .stdout(contains("warning[pub-fn]: public `ab`"))
+        .stdout(contains(
+            "-- 1 finding(s) from 1 rule(s): 0 error(s), 1 warning(s), 0 note(s)",
+        ));
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["lint", "--deny-warnings", "--json"]);
+    let out = cmd.assert().code(1).get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["findings"][0]["rule"], "pub-fn");
+    assert_eq!(v["findings"][0]["severity"], "warning");
+}
+
+#[test]
+fn cli_lint_honours_ignore_comments_and_lists_unused_ones() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_ignore'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "// arbol-ignore: pub-fn\npub fn ab(){}\n// arbol-ignore: pub-fn\nfn private(){}\n",
+    );
+    write(
+        &root.join(".arbol/rules/pub-fn.scm"),
+        "; severity: error\n((function_item name: (identifier) @name) @fn (#is-pub? @fn))\n",
+    );
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).arg("lint");
+    cmd.assert()
+        .success()
+        .stdout(contains("== Unused suppressions =="))
+        .stdout(contains(":3:1: `pub-fn` suppressed nothing"))
+        .stdout(contains("-- 0 finding(s) from 1 rule(s)"));
+}
+
+#[test]
+fn cli_query_baseline_reports_only_new_captures() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_baseline'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "fn old(){}\n");
+    let q = "(function_item name: (identifier) @fn.name)";
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["query", "--expr", q, "--baseline", "baseline.json", "--write-baseline"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("-- wrote 1 capture(s) to baseline.json"));
+
+    write(&root.join("src/lib.rs"), "// moved\nfn new(){}\nfn old(){}\n");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["query", "--expr", q, "--baseline", "baseline.json"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("fn.name new"))
+        .stdout(contains("fn.name old").not())
+        .stdout(contains("-- total captures: 1 (1 in baseline)"));
+}
+
+#[test]
+fn cli_rewrite_diffs_by_default_and_writes_with_apply() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_rewrite'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "fn f() -> u8 { x.unwrap() }\n");
+    let args = [
+        "rewrite",
+        "--expr",
+        "(call_expression function: (field_expression value: (_) @recv field: (field_identifier) @m (#eq? @m \"unwrap\"))) @call",
+        "--template",
+        "{{recv}}.expect(\"checked\")",
+    ];
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(args);
+    cmd.assert()
+        .success()
+        .stdout(contains("-fn f() -> u8 { x.unwrap() }"))
+        .stdout(contains("+fn f() -> u8 { x.expect(\"checked\") }"))
+        .stdout(contains("-- 1 edit(s) in 1 file(s), 0 conflict(s), 0 rejected"));
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.rs")).unwrap(),
+        "fn f() -> u8 { x.unwrap() }\n"
+    );
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(args).arg("--apply");
+    cmd.assert()
+        .success()
+        .stdout(contains("-- applied 1 edit(s) in 1 file(s)"));
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.rs")).unwrap(),
+        "fn f() -> u8 { x.expect(\"checked\") }\n"
+    );
+}
+
+#[test]
+fn cli_query_caches_results_until_cleared() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_cache'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(&root.join("src/lib.rs"), "fn cached(){}\n");
+    let q = "(function_item name: (identifier) @fn.name)";
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["query", "--no-cache", "--expr", q]);
+    cmd.assert().success().stdout(contains("fn.name cached"));
+    assert!(!root.join("target/arbol-cache").exists());
+
+    for _ in 0..2 {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+        cmd.current_dir(root).args(["query", "--expr", q]);
+        cmd.assert().success().stdout(contains("fn.name cached"));
+    }
+    assert!(root.join("target/arbol-cache").is_dir());
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["cache", "clear"]);
+    cmd.assert().success().stdout(contains("-- removed 1 cache entry from"));
+    assert!(!root.join("target/arbol-cache").exists());
+}
+
+#[test]
+fn cli_serve_answers_json_rpc_requests_until_shutdown() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "fn served(){}\n");
+    let requests = concat!(
+        r#"{"jsonrpc":"2.0","id":1,"method":"query","params":{"query":"(function_item name: (identifier) @fn.name)"}}"#,
+        "\n",
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        "\n",
+    );
+    // `assert_cmd::Command` can feed stdin.
+    let mut cmd = assert_cmd::Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["serve", "--verbose"]).write_stdin(requests);
+    cmd.assert()
+        .success()
+        .stdout(contains(r#""id":1,"result":{"crates""#))
+        .stdout(contains(r#""text":"served""#))
+        .stdout(contains(r#"{"jsonrpc":"2.0","id":2,"result":null}"#));
+}
+
+#[test]
+fn cli_lsp_publishes_diagnostics_for_opened_buffers() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join(".arbol/rules/no-todo.scm"),
+        "; message: leftover todo!()\n(macro_invocation macro: (identifier) @m (#eq? @m \"todo\")) @call\n",
+    );
+    let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{body}", body.len());
+    let open = r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///tmp/x.rs","languageId":"rust","version":1,"text":"fn f() { todo!() }\n"}}}"#;
+    let input = [
+        frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#),
+        frame(open),
+        frame(r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+        frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+    ]
+    .concat();
+    let mut cmd = assert_cmd::Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).arg("lsp").write_stdin(input);
+    cmd.assert()
+        .success()
+        .stdout(contains("Content-Length: "))
+        .stdout(contains(r#""codeActionProvider""#))
+        .stdout(contains(r#""method":"textDocument/publishDiagnostics""#))
+        .stdout(contains(r#""message":"leftover todo!()""#))
+        .stdout(contains(r#"{"jsonrpc":"2.0","id":2,"result":null}"#));
+}
+
+#[test]
+fn cli_mcp_answers_tool_calls_over_stdio() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(&root.join("src/lib.rs"), "pub fn exposed() {}\n");
+    let requests = concat!(
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-06-18","capabilities":{}}}"#,
+        "\n",
+        r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        "\n",
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"find_symbol","arguments":{"name":"exposed"}}}"#,
+        "\n",
+    );
+    let mut cmd = assert_cmd::Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).arg("mcp").write_stdin(requests);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let responses: Vec<serde_json::Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0]["result"]["serverInfo"]["name"], "arbol");
+    let text = responses[1]["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains(r#""name": "exposed""#), "{text}");
+}
+
+#[test]
+fn cli_symbols_lists_items_and_writes_tags_files() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("Cargo.toml"),
+        "[package]\nname='cli_sym'\nversion='0.0.0'\nedition='2021'\n",
+    );
+    write(
+        &root.join("src/lib.rs"),
+        "/// A widget.\npub struct Widget;\nimpl Widget {\n    pub fn spin(&self) {}\n}\n",
+    );
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).arg("symbols");
+    cmd.assert()
+        .success()
+        .stdout(contains("src/lib.rs:2: pub struct crate::Widget"))
+        .stdout(contains("src/lib.rs:4: pub method crate::Widget::spin"));
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["symbols", "--format", "json"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["symbols"][0]["doc"], "A widget.");
+    assert_eq!(v["symbols"][1]["kind"], "method");
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root)
+        .args(["symbols", "--format", "ctags", "--output", "tags"]);
+    cmd.assert().success().stdout("");
+    let tags = fs::read_to_string(root.join("tags")).unwrap();
+    assert!(tags.starts_with("!_TAG_FILE_FORMAT"));
+    assert!(
+        tags.contains("spin\tsrc/lib.rs\t4;\"\tP\tpath:crate::Widget::spin\n"),
+        "{tags}"
+    );
+}
+
+#[test]
+fn cli_outline_prints_a_tree_or_nested_json() {
+    let td = TempDir::new().unwrap();
+    let root = td.path();
+    write(
+        &root.join("src/lib.rs"),
+        "pub trait Shape {\n    fn area(&self) -> f64;\n}\nmod shapes {\n    pub struct Square;\n}\n",
+    );
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["outline", "src/lib.rs"]);
+    cmd.assert().success().stdout(
+        "src/lib.rs\n├── trait Shape  1-3\n│   └── fn area  2\n└── mod shapes  4-6\n    └── struct Square  5\n",
+    );
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(root).args(["outline", "src/lib.rs", "--json"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["items"][1]["kind"], "mod");
+    assert_eq!(v["items"][1]["children"][0]["name"], "Square");
+}
+
+#[test]
+fn cli_api_lists_public_paths_and_api_diff_flags_breaking_changes() {
+    let td = TempDir::new().unwrap();
+    for (dir, lib) in [
+        (
+            "old",
+            "mod imp;\npub use imp::Parser;\npub fn parse(s: String) {}\npub fn gone() {}\n",
+        ),
+        (
+            "new",
+            "mod imp;\npub use imp::Parser;\npub fn parse(s: &str) {}\npub fn added() {}\n",
+        ),
+    ] {
+        let root = td.path().join(dir);
+        write(
+            &root.join("Cargo.toml"),
+            "[package]\nname='p'\nversion='0.0.0'\nedition='2021'\n",
+        );
+        write(&root.join("src/lib.rs"), lib);
+        write(
+            &root.join("src/imp.rs"),
+            "pub struct Parser;\nimpl Parser {\n    pub fn run(&self) {}\n}\n",
+        );
+    }
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(td.path()).args(["api", "old"]);
+    cmd.assert().success().stdout(
+        "p::Parser: pub struct Parser\np::Parser::run: pub fn run(&self)\np::gone: pub fn gone()\np::parse: pub fn parse(s: String)\n",
+    );
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(td.path()).args(["api-diff", "old", "new"]);
+    cmd.assert().code(1).stdout(
+        "- p::gone: pub fn gone()\n+ p::added: pub fn added()\n~ p::parse\n    - pub fn parse(s: String)\n    + pub fn parse(s: &str)\n-- api-diff: 1 added, 1 removed, 1 changed\n",
+    );
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_arbol"));
+    cmd.current_dir(td.path()).args(["api-diff", "old", "old", "--json"]);
+    let out = cmd.assert().success().get_output().stdout.clone();
+    let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["changed"].as_array().unwrap().len(), 0);
+}